@@ -1,10 +1,52 @@
-use std::io::{stdin, stdout, Write};
+use std::{
+    env,
+    io::{stdin, stdout, BufRead, Write},
+};
 
 use forth3::{
+    input::{InputError, InputSource},
     leakbox::{LBForth, LBForthParams},
     Forth,
 };
 
+/// Lets `key`, `refill` etc. (and multi-line definitions) read from stdin.
+///
+/// The lock is taken for each read, so the main loop can keep using stdin.
+struct StdinSource;
+
+impl InputSource for StdinSource {
+    fn read_line(&mut self, buf: &mut [u8]) -> Result<Option<usize>, InputError> {
+        let mut line = Vec::new();
+        let n = stdin()
+            .lock()
+            .read_until(b'\n', &mut line)
+            .map_err(|_| InputError::ReadFailed)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        buf.get_mut(..n)
+            .ok_or(InputError::LineTooLong)?
+            .copy_from_slice(&line);
+        Ok(Some(n))
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, InputError> {
+        let mut stdin = stdin().lock();
+        let avail = stdin.fill_buf().map_err(|_| InputError::ReadFailed)?;
+        let key = avail.first().copied();
+        if key.is_some() {
+            stdin.consume(1);
+        }
+        Ok(key)
+    }
+
+    fn key_ready(&mut self) -> bool {
+        // There's no portable way to poll stdin without blocking, so `key?`
+        // never says a key is ready; `key` still waits for one.
+        false
+    }
+}
+
 fn main() {
     let params = LBForthParams {
         data_stack_elems: 1024,
@@ -15,8 +57,33 @@ fn main() {
         dict_buf_elems: 16 * 1024,
     };
     let mut lbf = LBForth::from_params(params, (), Forth::FULL_BUILTINS);
+    lbf.set_input_source(StdinSource);
     let forth = &mut lbf.forth;
 
+    // Any files named on the command line are included before starting the
+    // REPL, e.g. `f3repl lib.fs app.fs`. Forth code can only open or include
+    // files itself if `--file-access` is given too.
+    let (flags, paths): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    for flag in flags {
+        match flag.as_str() {
+            "--file-access" => forth.set_file_access(true),
+            _ => {
+                eprintln!("Unknown option {flag}");
+                std::process::exit(1);
+            }
+        }
+    }
+    for path in paths {
+        let res = forth.include_file(&path);
+        print!("{}", forth.output.as_str());
+        forth.output.clear();
+        if let Err(e) = res {
+            println!();
+            println!("Including {path} failed. Error: {:?}", e);
+        }
+    }
+
     let mut inp = String::new();
     loop {
         print!("> ");
@@ -0,0 +1,257 @@
+//! Procedural macros for `forth3`.
+//!
+//! These are re-exported by `forth3` with the `macros` feature, and should be
+//! used from there.
+
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, spanned::Spanned, FnArg, GenericArgument, ItemFn, LitStr, Pat, Path,
+    PathArguments, ReturnType, Type,
+};
+
+/// Defines a builtin word with a plain function, whose arguments are popped
+/// off the data stack and whose result is pushed back on.
+///
+/// ```rust,ignore
+/// #[forth3::macros::builtin(name = "clamp")]
+/// fn clamp(v: i32, lo: i32, hi: i32) -> i32 {
+///     v.max(lo).min(hi)
+/// }
+///
+/// const BUILTINS: &[BuiltinEntry<()>] = &[clamp::entry()];
+/// ```
+///
+/// The function is replaced by a [`Builtin`] function of the same name, alongside a
+/// module of the same name with a `const fn entry()` returning its
+/// [`BuiltinEntry`]. This means it must be used on a function in a module,
+/// rather than one inside another function's body.
+///
+/// - Arguments must implement [`FromWords`], and are popped so that the
+///   last one comes from the top of the stack.
+/// - The result must implement [`IntoWords`] (including `()` and tuples), or
+///   be a `Result` of one, in which case errors are returned from the word.
+///
+/// # Options
+///
+/// - `name = "..."`: the word's name. It's named after the function
+///   otherwise.
+/// - `ctx`: the first argument is `&mut Ctx`, the VM's host context, and the
+///   builtin is only for VMs with that context. Without `ctx`, the builtin
+///   can be used with any VM, and a first argument of `&mut Ctx` is an error
+///   rather than being taken as the context.
+/// - `crate = path`: the path to `forth3`, if it isn't `::forth3`, e.g.
+///   because it's been renamed or re-exported.
+///
+/// ```rust,ignore
+/// #[forth3::macros::builtin(ctx, name = "remember")]
+/// fn remember(ctx: &mut Vec<i32>, val: i32) {
+///     ctx.push(val);
+/// }
+/// ```
+///
+/// The word's stack effect, e.g. `clamp ( v lo hi -- n )`, is added to the
+/// function's docs.
+///
+/// [`Builtin`]: https://docs.rs/forth3/latest/forth3/trait.Builtin.html
+/// [`BuiltinEntry`]: https://docs.rs/forth3/latest/forth3/dictionary/struct.BuiltinEntry.html
+/// [`FromWords`]: https://docs.rs/forth3/latest/forth3/word/trait.FromWords.html
+/// [`IntoWords`]: https://docs.rs/forth3/latest/forth3/word/trait.IntoWords.html
+#[proc_macro_attribute]
+pub fn builtin(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut opts = Options::default();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            opts.name = Some(meta.value()?.parse::<LitStr>()?);
+        } else if meta.path.is_ident("ctx") {
+            opts.ctx = true;
+        } else if meta.path.is_ident("crate") {
+            opts.krate = Some(meta.value()?.parse::<Path>()?);
+        } else {
+            return Err(meta.error("expected `name = \"...\"`, `ctx` or `crate = path`"));
+        }
+        Ok(())
+    });
+    parse_macro_input!(attr with parser);
+    let func = parse_macro_input!(item as ItemFn);
+
+    match expand(opts, func) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// The options given to `#[builtin(...)]`.
+#[derive(Default)]
+struct Options {
+    name: Option<LitStr>,
+    ctx: bool,
+    krate: Option<Path>,
+}
+
+fn expand(opts: Options, func: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let Options { name, ctx, krate } = opts;
+    let ItemFn { attrs, vis, sig, block } = func;
+    if !sig.generics.params.is_empty() || sig.asyncness.is_some() {
+        return Err(syn::Error::new(
+            sig.span(),
+            "builtins can't be generic or async",
+        ));
+    }
+    let ident = &sig.ident;
+    let name = name.unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+
+    // The host context, which is the first argument if `ctx` is given.
+    let mut inputs = sig.inputs.iter().peekable();
+    let context = match (inputs.peek(), ctx) {
+        (Some(FnArg::Receiver(recv)), _) => {
+            return Err(syn::Error::new(recv.span(), "builtins can't take `self`"));
+        }
+        (Some(FnArg::Typed(arg)), true) => match &*arg.ty {
+            Type::Reference(r) if r.mutability.is_some() => {
+                inputs.next();
+                Some(&*r.elem)
+            }
+            _ => {
+                return Err(syn::Error::new(
+                    arg.ty.span(),
+                    "with `ctx`, the first argument must be `&mut Ctx`",
+                ));
+            }
+        },
+        (Some(FnArg::Typed(arg)), false) => match &*arg.ty {
+            Type::Reference(r) if r.mutability.is_some() => {
+                return Err(syn::Error::new(
+                    arg.ty.span(),
+                    "builtins can't take `&mut` arguments; use `#[builtin(ctx)]` if this is the host context",
+                ));
+            }
+            _ => None,
+        },
+        (None, true) => {
+            return Err(syn::Error::new(
+                sig.span(),
+                "with `ctx`, the first argument must be `&mut Ctx`",
+            ));
+        }
+        (None, false) => None,
+    };
+
+    let mut arg_names = Vec::new();
+    let mut arg_tys = Vec::new();
+    let mut effect_in = Vec::new();
+    for (idx, arg) in inputs.enumerate() {
+        let FnArg::Typed(arg) = arg else {
+            return Err(syn::Error::new(arg.span(), "builtins can't take `self`"));
+        };
+        arg_names.push(format_ident!("__arg{}", idx));
+        arg_tys.push(&*arg.ty);
+        effect_in.push(match &*arg.pat {
+            Pat::Ident(pat) => pat.ident.to_string(),
+            _ => stack_name(&arg.ty).join(" "),
+        });
+    }
+
+    let (fallible, effect_out) = match &sig.output {
+        ReturnType::Default => (false, Vec::new()),
+        ReturnType::Type(_, ty) => match result_ok_type(ty) {
+            Some(ok) => (true, stack_name(ok)),
+            None => (false, stack_name(ty)),
+        },
+    };
+    let effect = effect_in
+        .into_iter()
+        .chain(Some("--".to_string()))
+        .chain(effect_out)
+        .collect::<Vec<_>>();
+    let doc = format!(" `{} ( {} )`", name.value(), effect.join(" "));
+
+    let inner = format_ident!("__{}", ident);
+    let inner_inputs = &sig.inputs;
+    let inner_output = &sig.output;
+    let question = fallible.then(|| quote!(?));
+    let (generics, forth_ty, context_arg) = match context {
+        Some(ctx) => (quote!(), quote!(#ctx), quote!(&mut forth.host_ctxt,)),
+        None => (quote!(<__T: 'static>), quote!(__T), quote!()),
+    };
+    let turbofish = context.is_none().then(|| quote!(::<__T>));
+    let entry_doc = format!(" The builtin entry for `{}`.", name.value());
+    let krate = match krate {
+        Some(path) => quote!(#path),
+        None => quote!(::forth3),
+    };
+
+    Ok(quote! {
+        #(#attrs)*
+        #[doc = ""]
+        #[doc = #doc]
+        #[allow(clippy::unit_arg)]
+        #vis fn #ident #generics(
+            forth: &mut #krate::Forth<#forth_ty>,
+        ) -> ::core::result::Result<(), #krate::Error> {
+            fn #inner(#inner_inputs) #inner_output #block
+
+            let (#(#arg_names,)*): (#(#arg_tys,)*) =
+                #krate::word::FromWords::pop_words(&mut forth.data_stack)?;
+            #krate::word::IntoWords::push_words(
+                #inner(#context_arg #(#arg_names),*)#question,
+                &mut forth.data_stack,
+            )
+        }
+
+        #[doc = #entry_doc]
+        #vis mod #ident {
+            #[allow(unused_imports)]
+            use super::*;
+
+            pub const fn entry #generics() -> #krate::dictionary::BuiltinEntry<#forth_ty> {
+                #krate::dictionary::BuiltinEntry {
+                    hdr: #krate::dictionary::EntryHeader {
+                        name: #krate::fastr::comptime_fastr(#name),
+                        kind: #krate::dictionary::EntryKind::StaticBuiltin,
+                        len: 0,
+                        _pd: ::core::marker::PhantomData,
+                    },
+                    func: super::#ident #turbofish,
+                }
+            }
+        }
+    })
+}
+
+/// If `ty` is a `Result`, returns the type of its `Ok` value.
+fn result_ok_type(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let last = path.path.segments.last()?;
+    if last.ident != "Result" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &last.arguments else {
+        return None;
+    };
+    args.args.iter().find_map(|arg| match arg {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    })
+}
+
+/// The conventional stack effect names for the values making up `ty`.
+fn stack_name(ty: &Type) -> Vec<String> {
+    match ty {
+        Type::Tuple(tuple) => tuple.elems.iter().flat_map(stack_name).collect(),
+        Type::Paren(paren) => stack_name(&paren.elem),
+        Type::Path(path) => {
+            let name = match path.path.segments.last() {
+                Some(seg) if seg.ident == "i32" => "n",
+                Some(seg) if seg.ident == "u32" => "u",
+                Some(seg) if seg.ident == "bool" => "flag",
+                Some(seg) if seg.ident == "f32" => "r",
+                _ => "x",
+            };
+            vec![name.to_string()]
+        }
+        _ => vec!["x".to_string()],
+    }
+}
@@ -46,11 +46,12 @@ impl<'a, 'b, const L: usize, I> Iterator for BrickIterMut<'a, 'b, L, I> {
 // higher: oldest
 
 impl<const L: usize> Bricks<L> {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         let mut idx_buf = [0; L];
         idx_buf.iter_mut().enumerate().for_each(|(i, v)| *v = i);
         Self {
-            idx_buf: idx_buf,
+            idx_buf,
             user_editable_end: 0,
             inco_editable_end: 0,
             history_end: 0,
@@ -132,6 +133,7 @@ impl<const L: usize> Bricks<L> {
     // * Insert user editable -> Fails if all items already UE
     // * Insert inco editable -> Fails if all items already UE + IE
     // * Insert history       -> Fails if all items already UE + IE (not + history!)
+    #[allow(clippy::result_unit_err)]
     pub fn insert_ue_front(&mut self) -> Result<usize, ()> {
         if self.user_editable_end == L {
             return Err(());
@@ -145,6 +147,7 @@ impl<const L: usize> Bricks<L> {
         Ok(self.idx_buf[0])
     }
 
+    #[allow(clippy::result_unit_err)]
     pub fn insert_ie_front(&mut self) -> Result<usize, ()> {
         if self.inco_editable_end == L {
             return Err(());
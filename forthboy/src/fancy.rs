@@ -25,6 +25,7 @@ pub struct Line<const C: usize> {
 }
 
 impl<const C: usize> Line<C> {
+    #[allow(clippy::new_without_default)]
     pub const fn new() -> Self {
         Self {
             fill: 0,
@@ -137,7 +138,7 @@ impl<const C: usize> Line<C> {
                 self.fill += 1;
                 Ok(())
             }
-            Ordering::Less => return Err(LineError::WriteGap), // trying to insert AFTER the "tip"
+            Ordering::Less => Err(LineError::WriteGap), // trying to insert AFTER the "tip"
         }
     }
 }
@@ -278,6 +279,7 @@ impl<const L: usize, const C: usize> RingLine<L, C> {
     const ONELINE: Line<C> = Line::<C>::new();
     const INIT: [Line<C>; L] = [Self::ONELINE; L];
 
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         Self {
             lines: Self::INIT,
@@ -379,9 +381,9 @@ impl<const L: usize, const C: usize> RingLine<L, C> {
 
 #[cfg(test)]
 mod test {
-    use crate::fancy::{LineError, Source};
+    use crate::fancy::LineError;
 
-    use super::{Line, RingLine};
+    use super::Line;
 
     #[test]
     fn ascii() {
@@ -39,6 +39,7 @@ const CHAR_PIXELS_Y: u32 = FONT.character_size.height;
 const DISP_PIXELS_X: usize = (CHAR_PIXELS_X as usize) * CHARS_X;
 const DISP_PIXELS_Y: usize = (CHAR_PIXELS_Y as usize) * CHARS_Y;
 const DISP_PIXELS_TTL: usize = DISP_PIXELS_X * DISP_PIXELS_Y;
+#[allow(clippy::large_const_arrays)]
 const DISP_DEFAULT: [u32; DISP_PIXELS_TTL] = [0; DISP_PIXELS_TTL];
 
 struct GloboChar {
@@ -449,7 +450,7 @@ impl GloboChar {
                         .iter_user_editable(&self.grid.lines)
                         .map(Line::as_str)
                         .collect();
-                    let input: String = input.iter().rev().map(|s| *s).collect();
+                    let input: String = input.iter().rev().copied().collect();
 
                     self.lb_forth.forth.input.fill(&input).unwrap();
                     self.lb_forth.forth.output.clear();
@@ -567,8 +568,10 @@ impl Default for Display {
 
 fn main() {
     let mut disp = Display::default();
-    let mut options = WindowOptions::default();
-    options.scale = Scale::X4;
+    let options = WindowOptions {
+        scale: Scale::X4,
+        ..Default::default()
+    };
     let mut window =
         Window::new("Test - ESC to exit", DISP_PIXELS_X, DISP_PIXELS_Y, options).unwrap();
     window.limit_update_rate(Some(Duration::from_micros(1_000_000 / 60)));
@@ -0,0 +1,277 @@
+//! Block storage.
+//!
+//! Blocks are the classic way for a Forth system to store source and data
+//! persistently: storage is split into numbered blocks of [`BLOCK_SIZE`]
+//! bytes, which are read into (and written back from) a small number of
+//! [`BlockBuffer`]s in memory by the `block`, `buffer`, `update`,
+//! `save-buffers` and `flush` words. Block source can be interpreted with
+//! `load`, and displayed with `list`.
+//!
+//! Where the blocks are actually stored is up to the [`BlockDevice`] attached
+//! to the VM with [`Forth::set_block_device`](crate::Forth::set_block_device).
+
+use core::ptr::NonNull;
+
+/// The size of a block, in bytes.
+pub const BLOCK_SIZE: usize = 1024;
+
+/// The length of each line of a block, when interpreted by `load` or shown by
+/// `list`.
+pub const BLOCK_LINE_LEN: usize = 64;
+
+#[derive(Debug, PartialEq)]
+pub enum BlockError {
+    /// The block number is past the end of the device.
+    OutOfRange,
+    ReadFailed,
+    WriteFailed,
+}
+
+/// Storage for numbered blocks of [`BLOCK_SIZE`] bytes.
+pub trait BlockDevice {
+    /// Reads block `block` into `buf`.
+    fn read_block(&mut self, block: u32, buf: &mut [u8; BLOCK_SIZE]) -> Result<(), BlockError>;
+
+    /// Writes `buf` to block `block`.
+    fn write_block(&mut self, block: u32, buf: &[u8; BLOCK_SIZE]) -> Result<(), BlockError>;
+}
+
+/// A buffer holding the contents of a block in memory.
+pub struct BlockBuffer {
+    block: Option<u32>,
+    dirty: bool,
+    data: [u8; BLOCK_SIZE],
+}
+
+/// A [`BlockDevice`] storing blocks in a region of memory.
+///
+/// Block `n` is stored at offset `n * BLOCK_SIZE` of the region, so the
+/// device has `size / BLOCK_SIZE` blocks.
+pub struct MemBlockDevice {
+    start: *mut u8,
+    blocks: u32,
+}
+
+/// A [`BlockDevice`] storing blocks in a file.
+///
+/// Block `n` is stored at offset `n * BLOCK_SIZE` of the file. Blocks past
+/// the end of the file read as spaces, and writing them extends the file.
+#[cfg(feature = "use-std")]
+pub struct FileBlockDevice {
+    file: std::fs::File,
+}
+
+/// The block device attached to a VM, and its buffers.
+pub(crate) struct Blocks {
+    device: NonNull<dyn BlockDevice>,
+    buffers: NonNull<BlockBuffer>,
+    count: usize,
+    /// The buffer most recently returned by `block` or `buffer`, for `update`.
+    current: Option<usize>,
+    /// The buffer to reuse next, if the block isn't already in a buffer.
+    next: usize,
+}
+
+// === impl BlockBuffer ===
+
+impl BlockBuffer {
+    pub const EMPTY: Self = Self {
+        block: None,
+        dirty: false,
+        data: [b' '; BLOCK_SIZE],
+    };
+}
+
+// === impl MemBlockDevice ===
+
+impl MemBlockDevice {
+    pub fn new(bottom: *mut u8, size: usize) -> Self {
+        Self {
+            start: bottom,
+            blocks: u32::try_from(size / BLOCK_SIZE).unwrap_or(u32::MAX),
+        }
+    }
+
+    fn block_ptr(&self, block: u32) -> Result<*mut u8, BlockError> {
+        if block >= self.blocks {
+            return Err(BlockError::OutOfRange);
+        }
+        Ok(self.start.wrapping_add(block as usize * BLOCK_SIZE))
+    }
+}
+
+impl BlockDevice for MemBlockDevice {
+    fn read_block(&mut self, block: u32, buf: &mut [u8; BLOCK_SIZE]) -> Result<(), BlockError> {
+        let src = self.block_ptr(block)?;
+        unsafe {
+            core::ptr::copy_nonoverlapping(src, buf.as_mut_ptr(), BLOCK_SIZE);
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, block: u32, buf: &[u8; BLOCK_SIZE]) -> Result<(), BlockError> {
+        let dest = self.block_ptr(block)?;
+        unsafe {
+            core::ptr::copy_nonoverlapping(buf.as_ptr(), dest, BLOCK_SIZE);
+        }
+        Ok(())
+    }
+}
+
+// === impl FileBlockDevice ===
+
+#[cfg(feature = "use-std")]
+impl FileBlockDevice {
+    /// The file must be opened for reading and writing.
+    pub fn new(file: std::fs::File) -> Self {
+        Self { file }
+    }
+
+    pub fn into_inner(self) -> std::fs::File {
+        self.file
+    }
+
+    fn seek_to(&mut self, block: u32) -> Result<(), BlockError> {
+        use std::io::{Seek, SeekFrom};
+        let pos = u64::from(block) * BLOCK_SIZE as u64;
+        self.file
+            .seek(SeekFrom::Start(pos))
+            .map(drop)
+            .map_err(|_| BlockError::OutOfRange)
+    }
+}
+
+#[cfg(feature = "use-std")]
+impl BlockDevice for FileBlockDevice {
+    fn read_block(&mut self, block: u32, buf: &mut [u8; BLOCK_SIZE]) -> Result<(), BlockError> {
+        use std::io::Read;
+        self.seek_to(block)?;
+        let mut read = 0;
+        while read < BLOCK_SIZE {
+            match self.file.read(&mut buf[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(_) => return Err(BlockError::ReadFailed),
+            }
+        }
+        buf[read..].fill(b' ');
+        Ok(())
+    }
+
+    fn write_block(&mut self, block: u32, buf: &[u8; BLOCK_SIZE]) -> Result<(), BlockError> {
+        use std::io::Write;
+        self.seek_to(block)?;
+        self.file.write_all(buf).map_err(|_| BlockError::WriteFailed)
+    }
+}
+
+// === impl Blocks ===
+
+impl Blocks {
+    /// # Safety
+    ///
+    /// `device` and `buffers` must stay valid for as long as the `Blocks` is
+    /// in use. `buffers` needn't be initialized.
+    pub(crate) unsafe fn new(
+        device: NonNull<dyn BlockDevice>,
+        buffers: (*mut BlockBuffer, usize),
+    ) -> Option<Self> {
+        let (ptr, count) = buffers;
+        let buffers = NonNull::new(ptr)?;
+        if count == 0 {
+            return None;
+        }
+        for i in 0..count {
+            buffers.as_ptr().add(i).write(BlockBuffer::EMPTY);
+        }
+        Some(Self {
+            device,
+            buffers,
+            count,
+            current: None,
+            next: 0,
+        })
+    }
+
+    fn buffer(&mut self, idx: usize) -> &mut BlockBuffer {
+        debug_assert!(idx < self.count);
+        unsafe { &mut *self.buffers.as_ptr().add(idx) }
+    }
+
+    /// Returns where the buffers are, and their total size in bytes.
+    pub(crate) fn buffers(&self) -> (*const u8, usize) {
+        (self.buffers.as_ptr().cast(), self.count * core::mem::size_of::<BlockBuffer>())
+    }
+
+    /// Returns whether the `len` bytes at `addr` are all in the data of one
+    /// buffer, rather than in the bookkeeping around it.
+    pub(crate) fn data_contains(&self, addr: *const u8, len: usize) -> bool {
+        (0..self.count).any(|i| {
+            let data = unsafe { core::ptr::addr_of!((*self.buffers.as_ptr().add(i)).data) };
+            let (start, addr) = (data as usize, addr as usize);
+            addr >= start && addr.checked_add(len).is_some_and(|end| end <= start + BLOCK_SIZE)
+        })
+    }
+
+    /// Returns a buffer assigned to `block`, reading the block from the
+    /// device first if `read` is true and it isn't already in a buffer.
+    pub(crate) fn assign(&mut self, block: u32, read: bool) -> Result<*mut u8, BlockError> {
+        let idx = match (0..self.count).find(|&i| self.buffer(i).block == Some(block)) {
+            Some(idx) => idx,
+            None => {
+                let idx = self.next;
+                self.next = (idx + 1) % self.count;
+                self.save(idx)?;
+                let mut device = self.device;
+                let buf = self.buffer(idx);
+                buf.block = None;
+                if read {
+                    unsafe { device.as_mut() }.read_block(block, &mut buf.data)?;
+                }
+                buf.block = Some(block);
+                idx
+            }
+        };
+        self.current = Some(idx);
+        Ok(self.buffer(idx).data.as_mut_ptr())
+    }
+
+    /// Marks the current buffer as modified. Returns `false` if there is no
+    /// current buffer.
+    pub(crate) fn update(&mut self) -> bool {
+        match self.current {
+            Some(idx) => {
+                self.buffer(idx).dirty = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Writes buffer `idx` back to the device, if it has been modified.
+    fn save(&mut self, idx: usize) -> Result<(), BlockError> {
+        let mut device = self.device;
+        let buf = self.buffer(idx);
+        if let (Some(block), true) = (buf.block, buf.dirty) {
+            unsafe { device.as_mut() }.write_block(block, &buf.data)?;
+            buf.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Writes all modified buffers back to the device.
+    pub(crate) fn save_all(&mut self) -> Result<(), BlockError> {
+        (0..self.count).try_for_each(|idx| self.save(idx))
+    }
+
+    /// Writes all modified buffers back to the device, and unassigns them.
+    pub(crate) fn flush(&mut self) -> Result<(), BlockError> {
+        self.save_all()?;
+        for idx in 0..self.count {
+            self.buffer(idx).block = None;
+        }
+        self.current = None;
+        Ok(())
+    }
+}
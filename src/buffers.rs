@@ -0,0 +1,222 @@
+//! Statically sized memory for a VM, for use without an allocator.
+//!
+//! [`Forth::new`] (and [`AsyncForth::new`]) take raw pointers to the VM's
+//! memory, and so are `unsafe`. A [`ForthBuffers`] holds all of that memory
+//! in one value, which can be kept in a `static` and borrowed by a VM with
+//! the safe [`Forth::from_buffers`] (or [`AsyncForth::from_buffers`]).
+//!
+//! The buffers must be borrowed mutably for `'static`, which is usually done
+//! by initializing them at runtime with something like
+//! [`static_cell::StaticCell`](https://docs.rs/static_cell):
+//!
+//! ```rust
+//! use forth3::{buffers::ForthBuffers, Forth};
+//! use static_cell::StaticCell;
+//!
+//! type Buffers = ForthBuffers<(), 16, 16, 16, 64, 64, 512>;
+//! static BUFFERS: StaticCell<Buffers> = StaticCell::new();
+//!
+//! let buffers = BUFFERS.init(Buffers::new());
+//! let mut forth = Forth::from_buffers(buffers, (), Forth::FULL_BUILTINS).unwrap();
+//! forth.input.fill("2 3 + .").unwrap();
+//! forth.process_line().unwrap();
+//! assert_eq!(forth.output.as_str(), "5 ok.\n");
+//! ```
+//!
+//! Where there is an allocator, leaking a `Box` with [`Box::leak`] works too.
+
+use core::{alloc::Layout, mem::MaybeUninit, ptr::NonNull};
+
+use crate::{
+    dictionary::{BuiltinEntry, Dictionary, DropDict, OwnedDict},
+    input::WordStrBuf,
+    output::OutputBuf,
+    word::Word,
+    CallContext, Error, Forth,
+};
+
+#[cfg(feature = "async")]
+use crate::{dictionary::AsyncBuiltins, AsyncForth};
+
+/// The memory for a VM: its stacks, input and output buffers, and dictionary.
+///
+/// `DS`, `RS` and `CS` are the number of elements in the data, return and
+/// call stacks, and `IN`, `OUT` and `DICT` are the sizes of the input and
+/// output buffers and the dictionary, in bytes.
+pub struct ForthBuffers<
+    T: 'static,
+    const DS: usize,
+    const RS: usize,
+    const CS: usize,
+    const IN: usize,
+    const OUT: usize,
+    const DICT: usize,
+> {
+    data_stack: [MaybeUninit<Word>; DS],
+    return_stack: [MaybeUninit<Word>; RS],
+    call_stack: [MaybeUninit<CallContext<T>>; CS],
+    input: [MaybeUninit<u8>; IN],
+    output: [MaybeUninit<u8>; OUT],
+    dict: DictBuffer<T, DICT>,
+}
+
+/// A dictionary's header followed by its memory, laid out as
+/// [`Dictionary::layout`] expects.
+#[repr(C)]
+struct DictBuffer<T: 'static, const N: usize> {
+    header: MaybeUninit<Dictionary<T>>,
+    bytes: [MaybeUninit<u8>; N],
+}
+
+/// Dictionaries in [`ForthBuffers`], which are never freed.
+struct BufferDict;
+
+/// The parts of a VM, made from [`ForthBuffers`].
+struct Parts<T: 'static> {
+    dstack_buf: (*mut Word, usize),
+    rstack_buf: (*mut Word, usize),
+    cstack_buf: (*mut CallContext<T>, usize),
+    dict: OwnedDict<T>,
+    input: WordStrBuf,
+    output: OutputBuf,
+}
+
+// Safety: the buffers are only uninitialized memory until they're borrowed by
+// a VM, which is then the only thing that can use them.
+unsafe impl<
+        T: 'static,
+        const DS: usize,
+        const RS: usize,
+        const CS: usize,
+        const IN: usize,
+        const OUT: usize,
+        const DICT: usize,
+    > Send for ForthBuffers<T, DS, RS, CS, IN, OUT, DICT>
+{
+}
+
+// === impl ForthBuffers ===
+
+impl<
+        T: 'static,
+        const DS: usize,
+        const RS: usize,
+        const CS: usize,
+        const IN: usize,
+        const OUT: usize,
+        const DICT: usize,
+    > ForthBuffers<T, DS, RS, CS, IN, OUT, DICT>
+{
+    pub const fn new() -> Self {
+        Self {
+            data_stack: [const { MaybeUninit::uninit() }; DS],
+            return_stack: [const { MaybeUninit::uninit() }; RS],
+            call_stack: [const { MaybeUninit::uninit() }; CS],
+            input: [const { MaybeUninit::uninit() }; IN],
+            output: [const { MaybeUninit::uninit() }; OUT],
+            dict: DictBuffer {
+                header: MaybeUninit::uninit(),
+                bytes: [const { MaybeUninit::uninit() }; DICT],
+            },
+        }
+    }
+
+    fn parts(&'static mut self) -> Parts<T> {
+        let dict = NonNull::from(&mut self.dict).cast::<MaybeUninit<Dictionary<T>>>();
+        Parts {
+            dstack_buf: (self.data_stack.as_mut_ptr().cast(), DS),
+            rstack_buf: (self.return_stack.as_mut_ptr().cast(), RS),
+            cstack_buf: (self.call_stack.as_mut_ptr().cast(), CS),
+            dict: OwnedDict::new::<BufferDict>(dict, DICT),
+            input: WordStrBuf::new(self.input.as_mut_ptr().cast(), IN),
+            output: OutputBuf::new(self.output.as_mut_ptr().cast(), OUT),
+        }
+    }
+}
+
+impl<
+        T: 'static,
+        const DS: usize,
+        const RS: usize,
+        const CS: usize,
+        const IN: usize,
+        const OUT: usize,
+        const DICT: usize,
+    > Default for ForthBuffers<T, DS, RS, CS, IN, OUT, DICT>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DropDict for BufferDict {
+    unsafe fn drop_dict(_ptr: NonNull<u8>, _layout: Layout) {
+        // The buffers stay borrowed for `'static`, so there's nothing to free.
+    }
+}
+
+// === impl Forth ===
+
+impl<T: 'static> Forth<T> {
+    /// Creates a VM which uses `buffers` for all of its memory.
+    ///
+    /// Unlike [`Forth::new`], this is safe, as the buffers are borrowed for
+    /// as long as the VM could use them.
+    pub fn from_buffers<
+        const DS: usize,
+        const RS: usize,
+        const CS: usize,
+        const IN: usize,
+        const OUT: usize,
+        const DICT: usize,
+    >(
+        buffers: &'static mut ForthBuffers<T, DS, RS, CS, IN, OUT, DICT>,
+        host_ctxt: T,
+        builtins: &'static [BuiltinEntry<T>],
+    ) -> Result<Self, Error> {
+        let Parts { dstack_buf, rstack_buf, cstack_buf, dict, input, output } = buffers.parts();
+        unsafe {
+            Self::new(dstack_buf, rstack_buf, cstack_buf, dict, input, output, host_ctxt, builtins)
+        }
+    }
+}
+
+// === impl AsyncForth ===
+
+#[cfg(feature = "async")]
+impl<T, A> AsyncForth<T, A>
+where
+    T: 'static,
+    A: for<'forth> AsyncBuiltins<'forth, T>,
+{
+    /// Creates a VM which uses `buffers` for all of its memory. See
+    /// [`Forth::from_buffers`].
+    pub fn from_buffers<
+        const DS: usize,
+        const RS: usize,
+        const CS: usize,
+        const IN: usize,
+        const OUT: usize,
+        const DICT: usize,
+    >(
+        buffers: &'static mut ForthBuffers<T, DS, RS, CS, IN, OUT, DICT>,
+        host_ctxt: T,
+        sync_builtins: &'static [BuiltinEntry<T>],
+        async_builtins: A,
+    ) -> Result<Self, Error> {
+        let Parts { dstack_buf, rstack_buf, cstack_buf, dict, input, output } = buffers.parts();
+        unsafe {
+            Self::new(
+                dstack_buf,
+                rstack_buf,
+                cstack_buf,
+                dict,
+                input,
+                output,
+                host_ctxt,
+                sync_builtins,
+                async_builtins,
+            )
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Combining libraries of builtin words at compile time.
+//!
+//! A VM takes a single slice of [`BuiltinEntry`]s, and looks builtins up by
+//! name in order, so if two libraries concatenated by hand both have a word
+//! with the same name, the second is silently ignored. A [`BuiltinSet`] is
+//! built up from libraries in a `const`, and fails to compile if any names
+//! collide:
+//!
+//! ```rust
+//! use forth3::{builtin_set::BuiltinSet, dictionary::BuiltinEntry, prefixed_builtins, Forth};
+//! # use forth3::{buffers::ForthBuffers, word::Word, Error};
+//! # use static_cell::StaticCell;
+//! # fn hello(forth: &mut Forth<()>) -> Result<(), Error> {
+//! #     forth.data_stack.push(Word::data(42))?;
+//! #     Ok(())
+//! # }
+//! # const MY_WORDS: &[BuiltinEntry<()>] = &[BuiltinEntry {
+//! #     hdr: forth3::dictionary::EntryHeader {
+//! #         name: forth3::fastr::comptime_fastr("hello"),
+//! #         kind: forth3::dictionary::EntryKind::StaticBuiltin,
+//! #         len: 0,
+//! #         _pd: core::marker::PhantomData,
+//! #     },
+//! #     func: hello,
+//! # }];
+//!
+//! const BUILTINS: BuiltinSet<(), 256> = BuiltinSet::new()
+//!     .with(Forth::FULL_BUILTINS)
+//!     // `MY_WORDS` has a `hello` word, which becomes `my:hello`.
+//!     .with(prefixed_builtins!("my:", MY_WORDS))
+//!     .without("forget")
+//!     .without("execute");
+//!
+//! # type Buffers = ForthBuffers<(), 16, 16, 16, 64, 64, 512>;
+//! # static BUFFERS: StaticCell<Buffers> = StaticCell::new();
+//! # let buffers = BUFFERS.init(Buffers::new());
+//! let mut forth = Forth::from_buffers(buffers, (), BUILTINS.as_slice()).unwrap();
+//! forth.input.fill("my:hello .").unwrap();
+//! forth.process_line().unwrap();
+//! assert_eq!(forth.output.as_str(), "42 ok.\n");
+//! ```
+//!
+//! To replace a builtin in one library with one from another, remove it with
+//! [`BuiltinSet::without`] first:
+//!
+//! ```rust,compile_fail
+//! # use forth3::{builtin_set::BuiltinSet, Forth};
+//! // `dup` is already in the set!
+//! const BUILTINS: BuiltinSet<(), 256> = BuiltinSet::new()
+//!     .with(Forth::FULL_BUILTINS)
+//!     .with(Forth::FULL_BUILTINS);
+//! # let _ = BUILTINS.as_slice();
+//! ```
+
+use core::mem::MaybeUninit;
+
+use crate::{dictionary::BuiltinEntry, fastr::comptime_fastr_bytes, rom::bytes_eq};
+
+/// A set of up to `N` builtin words with unique names, built from libraries
+/// of builtins in a `const`.
+///
+/// The [module docs](self) have an example.
+pub struct BuiltinSet<T: 'static, const N: usize> {
+    entries: [MaybeUninit<BuiltinEntry<T>>; N],
+    len: usize,
+}
+
+// === impl BuiltinSet ===
+
+impl<T: 'static, const N: usize> BuiltinSet<T, N> {
+    /// Returns an empty set.
+    pub const fn new() -> Self {
+        Self {
+            entries: [const { MaybeUninit::uninit() }; N],
+            len: 0,
+        }
+    }
+
+    /// Adds all of the builtins in `builtins` to the set.
+    ///
+    /// # Panics
+    ///
+    /// If the set would have more than `N` builtins, or already has a builtin
+    /// with the same name as one being added. In a `const`, this is a
+    /// compile error.
+    pub const fn with(mut self, builtins: &[BuiltinEntry<T>]) -> Self {
+        let mut idx = 0;
+        while idx < builtins.len() {
+            let bi = &builtins[idx];
+            assert!(
+                self.position(bi.hdr.name.as_bytes()).is_none(),
+                "a builtin with the same name is already in the set",
+            );
+            assert!(self.len < N, "too many builtins for the set");
+            // Safety: entries are only read, never dropped.
+            self.entries[self.len] = MaybeUninit::new(unsafe { core::ptr::read(bi) });
+            self.len += 1;
+            idx += 1;
+        }
+        self
+    }
+
+    /// Removes the builtin named `name` from the set, e.g. to leave out words
+    /// which shouldn't be available to untrusted code.
+    ///
+    /// # Panics
+    ///
+    /// If there's no builtin named `name` in the set. In a `const`, this is a
+    /// compile error.
+    pub const fn without(mut self, name: &str) -> Self {
+        let Some(mut idx) = self.position(name.as_bytes()) else {
+            panic!("no builtin with that name is in the set");
+        };
+        // Keep the remaining builtins in order, in case that matters to
+        // something like `words`.
+        while idx + 1 < self.len {
+            let next = unsafe { self.entries[idx + 1].assume_init_read() };
+            self.entries[idx] = MaybeUninit::new(next);
+            idx += 1;
+        }
+        self.len -= 1;
+        self
+    }
+
+    /// Returns the builtins in the set, to be passed to a VM's constructor.
+    pub const fn as_slice(&self) -> &[BuiltinEntry<T>] {
+        // Safety: the first `len` entries are initialized.
+        unsafe { core::slice::from_raw_parts(self.entries.as_ptr().cast(), self.len) }
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    const fn position(&self, name: &[u8]) -> Option<usize> {
+        let mut idx = 0;
+        while idx < self.len {
+            let entry = unsafe { self.entries[idx].assume_init_ref() };
+            if bytes_eq(entry.hdr.name.as_bytes(), name) {
+                return Some(idx);
+            }
+            idx += 1;
+        }
+        None
+    }
+}
+
+impl<T: 'static, const N: usize> Default for BuiltinSet<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns a library of builtins with each of the names in `$builtins`
+/// prefixed by `$prefix`, for use with [`BuiltinSet::with`].
+///
+/// The names are kept in a `static`, so `$builtins` must be a constant with a
+/// known context type, rather than one generic over it. Prefixed names are
+/// limited to 31 bytes, like all other names.
+///
+/// See the [`builtin_set` module docs](crate::builtin_set) for an example.
+#[macro_export]
+macro_rules! prefixed_builtins {
+    ($prefix:literal, $builtins:expr) => {{
+        static NAMES: [u8; $crate::builtin_set::prefixed_names_len($prefix, $builtins)] =
+            $crate::builtin_set::prefixed_names($prefix, $builtins);
+        &$crate::builtin_set::prefixed::<_, { $builtins.len() }>(&NAMES, $prefix, $builtins)
+    }};
+}
+
+/// The number of bytes needed for the prefixed names of `builtins`.
+#[doc(hidden)]
+pub const fn prefixed_names_len<T: 'static>(prefix: &str, builtins: &[BuiltinEntry<T>]) -> usize {
+    let mut len = 0;
+    let mut idx = 0;
+    while idx < builtins.len() {
+        len += prefix.len() + builtins[idx].hdr.name.as_bytes().len();
+        idx += 1;
+    }
+    len
+}
+
+/// The prefixed names of `builtins`, one after another.
+#[doc(hidden)]
+pub const fn prefixed_names<T: 'static, const LEN: usize>(
+    prefix: &str,
+    builtins: &[BuiltinEntry<T>],
+) -> [u8; LEN] {
+    let mut names = [0; LEN];
+    let mut pos = 0;
+    let mut idx = 0;
+    while idx < builtins.len() {
+        pos = copy_into(&mut names, pos, prefix.as_bytes());
+        pos = copy_into(&mut names, pos, builtins[idx].hdr.name.as_bytes());
+        idx += 1;
+    }
+    names
+}
+
+/// Copies of `builtins`, named by the [`prefixed_names`] in `names`.
+#[doc(hidden)]
+pub const fn prefixed<T: 'static, const N: usize>(
+    names: &'static [u8],
+    prefix: &str,
+    builtins: &[BuiltinEntry<T>],
+) -> [BuiltinEntry<T>; N] {
+    assert!(builtins.len() == N);
+    let mut entries: [MaybeUninit<BuiltinEntry<T>>; N] = [const { MaybeUninit::uninit() }; N];
+    let mut rest = names;
+    let mut idx = 0;
+    while idx < N {
+        let len = prefix.len() + builtins[idx].hdr.name.as_bytes().len();
+        assert!(len <= 31, "prefixed builtin names can't be longer than 31 bytes");
+        let (name, next) = rest.split_at(len);
+        // Safety: entries are only read, never dropped.
+        let mut entry = unsafe { core::ptr::read(&builtins[idx]) };
+        entry.hdr.name = comptime_fastr_bytes(name);
+        entries[idx] = MaybeUninit::new(entry);
+        rest = next;
+        idx += 1;
+    }
+    // Safety: all `N` entries were initialized above.
+    unsafe { core::ptr::read(entries.as_ptr().cast()) }
+}
+
+const fn copy_into(buf: &mut [u8], mut pos: usize, bytes: &[u8]) -> usize {
+    let mut idx = 0;
+    while idx < bytes.len() {
+        buf[pos] = bytes[idx];
+        pos += 1;
+        idx += 1;
+    }
+    pos
+}
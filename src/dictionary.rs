@@ -1,4 +1,5 @@
 use crate::fastr::FaStr;
+use crate::rom::StaticDict;
 use crate::{Word, WordFunc};
 use core::{
     alloc::{Layout, LayoutError},
@@ -9,6 +10,10 @@ use core::{
 };
 use portable_atomic::{Ordering::*, AtomicUsize};
 
+/// The wordlist that entries are added to until `definitions` picks another,
+/// and which entries in a [`StaticDict`] are always in.
+pub(crate) const FORTH_WORDLIST: u16 = 0;
+
 #[derive(Debug, PartialEq)]
 pub enum BumpError {
     OutOfMemory,
@@ -24,6 +29,52 @@ pub enum EntryKind {
     Dictionary,
     #[cfg(feature = "async")]
     AsyncBuiltin,
+    /// An async builtin added at runtime with
+    /// [`AsyncForth::add_async_builtin`](crate::AsyncForth::add_async_builtin).
+    #[cfg(feature = "async")]
+    RuntimeAsyncBuiltin,
+}
+
+/// What defined a [`DictionaryEntry`], and so what its parameter field holds.
+///
+/// This is recorded when the entry is created, rather than worked out from
+/// its `func`, as function pointers can't be reliably compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum EntryType {
+    /// A builtin added at runtime, whose parameter field (if any) belongs to
+    /// the builtin.
+    Builtin,
+    /// A `:` definition, whose parameter field is compiled code.
+    Colon,
+    /// A `constant`, whose parameter field is its value.
+    Constant,
+    /// A `variable`, whose parameter field is its value.
+    Variable,
+    /// An `array`, whose parameter field is its elements.
+    Array,
+    /// A `channel`, whose parameter field is its state and buffer.
+    Channel,
+    /// A `vocabulary`, whose parameter field is its wordlist id.
+    Vocabulary,
+}
+
+impl EntryType {
+    const ALL: [Self; 7] = [
+        Self::Builtin,
+        Self::Colon,
+        Self::Constant,
+        Self::Variable,
+        Self::Array,
+        Self::Channel,
+        Self::Vocabulary,
+    ];
+
+    /// Returns the type whose `u8` value is `val`.
+    pub(crate) fn from_u8(val: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|ty| *ty as u8 == val)
+    }
 }
 
 /// Where a dictionary entry was found
@@ -70,6 +121,12 @@ pub struct DictionaryEntry<T: 'static> {
     /// Link field, points back to the previous entry
     pub(crate) link: Option<NonNull<DictionaryEntry<T>>>,
 
+    /// The wordlist that the entry is in.
+    pub(crate) wordlist: u16,
+
+    /// What defined the entry.
+    pub(crate) ty: EntryType,
+
     /// data OR an array of compiled code.
     /// the first word is the "p(arameter)fa" or "c(ode)fa"
     pub(crate) parameter_field: [Word; 0],
@@ -99,11 +156,21 @@ pub struct Dictionary<T: 'static> {
     ///
     /// When looking up a binding that isn't present in `self`, we traverse this
     /// chain of references. When dropping the dictionary, we decrement the
-    /// parent's ref count.
-    parent: Option<SharedDict<T>>,
+    /// parent's ref count (if it has one).
+    parent: Option<ParentDict<T>>,
+    /// The wordlist that new entries are added to, set by `definitions`.
+    pub(crate) current: u16,
     deallocate: unsafe fn (ptr: NonNull<u8>, layout: Layout),
 }
 
+/// A frozen dictionary that a [`Dictionary`] falls back to.
+pub(crate) enum ParentDict<T: 'static> {
+    /// A dictionary that was frozen by forking a VM.
+    Shared(SharedDict<T>),
+    /// A dictionary in static memory, which is never deallocated.
+    Static(&'static StaticDict<T>),
+}
+
 pub trait DropDict {
     /// Deallocate a dictionary.
     unsafe fn drop_dict(ptr: NonNull<u8>, layout: Layout);
@@ -120,6 +187,8 @@ pub(crate) struct DictionaryBump {
     pub(crate) start: *mut u8,
     pub(crate) cur: *mut u8,
     pub(crate) end: *mut u8,
+    /// Where allocations stop, which is before `end` if there's a quota.
+    limit: *mut u8,
 }
 
 /// Iterator over a [`Dictionary`]'s entries.
@@ -131,6 +200,7 @@ pub(crate) struct Entries<'dict, T: 'static> {
 enum CurrDict<'dict, T: 'static> {
     Leaf(&'dict Dictionary<T>),
     Parent(SharedDict<T>),
+    Static,
 }
 
 #[cfg(feature = "async")]
@@ -232,21 +302,47 @@ pub trait AsyncBuiltins<'forth, T: 'static> {
     /// stacks as it executes.
     ///
     /// This method should return a [`Future`] for each builtin function
-    /// definition in [`Self::BUILTINS`]. Typically, this is implemented by
-    /// [`match`]ing the provided `id`, and returning the appropriate [`Future`]
-    /// for each builtin name. See [the `AsyncBuiltin` trait's
-    /// documentation][impling] for details on implementing this method.
+    /// definition in [`Self::BUILTINS`], and for each builtin added with
+    /// [`AsyncForth::add_async_builtin`](crate::AsyncForth::add_async_builtin).
+    /// Typically, this is implemented by [`match`]ing the provided `id`, and
+    /// returning the appropriate [`Future`] for each builtin name. See [the
+    /// `AsyncBuiltin` trait's documentation][impling] for details on
+    /// implementing this method.
     ///
-    /// The `id` parameter is `'static`, as we will only resolve function names
-    /// from the provided [`Self::BUILTINS`], which must be `'static`.
+    /// The `id` parameter is only borrowed for the call, as the names of
+    /// builtins added at runtime live in the VM's dictionary.
     ///
     /// [`Future`]: core::future::Future
     /// [`match`]: https://doc.rust-lang.org/stable/std/keyword.match.html
     /// [impling]: #implementing-async-builtins
-    fn dispatch_async(&self, id: &'static FaStr, forth: &'forth mut crate::Forth<T>) -> Self::Future;
+    fn dispatch_async(&self, id: &FaStr, forth: &'forth mut crate::Forth<T>) -> Self::Future;
+}
+
+impl<T: 'static> EntryHeader<T> {
+    /// Returns the entry's name.
+    ///
+    /// With the `relocatable` feature, the names of entries in a dictionary
+    /// are stored relative to the entry, so they must be read with
+    /// [`DictionaryEntry::name`] instead.
+    pub fn name(&self) -> FaStr {
+        unsafe { self.name.resolve((self as *const Self).cast()) }
+    }
 }
 
 impl<T: 'static> DictionaryEntry<T> {
+    /// Returns the name of the entry at `this`.
+    ///
+    /// With the `relocatable` feature, the name is stored relative to the
+    /// entry. It's found from `this`, rather than from a reference to the
+    /// entry, which wouldn't cover the name stored before it.
+    ///
+    /// # Safety
+    ///
+    /// `this` must point to a valid entry, and be usable to read its name.
+    pub unsafe fn name(this: NonNull<Self>) -> FaStr {
+        (*this.as_ptr()).hdr.name.resolve(this.as_ptr().cast())
+    }
+
     pub unsafe fn pfa(this: NonNull<Self>) -> NonNull<Word> {
         let ptr = this.as_ptr();
         let pfp: *mut [Word; 0] = addr_of_mut!((*ptr).parameter_field);
@@ -264,20 +360,28 @@ impl<T: 'static> Dictionary<T> {
         Ok(layout.pad_to_align())
     }
 
-    pub(crate) fn add_bi_fastr(&mut self, name: FaStr, bi: WordFunc<T>) -> Result<(), BumpError> {
+    #[cfg(any(test, feature = "async"))]
+    pub(crate) fn add_bi_kind(
+        &mut self,
+        name: FaStr,
+        bi: WordFunc<T>,
+        kind: EntryKind,
+    ) -> Result<(), BumpError> {
         debug_assert_eq!(self.refs.load(Acquire), Self::MUTABLE);
         // Allocate and initialize the dictionary entry
         let dict_base = self.alloc.bump::<DictionaryEntry<T>>()?;
         unsafe {
             dict_base.as_ptr().write(DictionaryEntry {
                 hdr: EntryHeader {
-                    name,
-                    kind: EntryKind::RuntimeBuiltin,
+                    name: self.entry_name(name, dict_base),
+                    kind,
                     len: 0,
                     _pd: PhantomData,
                 },
                 func: bi,
-                link: self.tail.take(),
+                link: self.link_to(self.tail),
+                wordlist: self.current,
+                ty: EntryType::Builtin,
                 parameter_field: [],
             });
         }
@@ -301,6 +405,160 @@ impl<T: 'static> Dictionary<T> {
             dict: CurrDict::Leaf(self),
         }
     }
+
+    /// Returns `name`, to be stored as the name of `entry` in this
+    /// dictionary.
+    pub(crate) fn entry_name(&self, name: FaStr, entry: NonNull<DictionaryEntry<T>>) -> FaStr {
+        #[cfg(feature = "relocatable")]
+        if self.alloc.contains(name.as_ptr().cast_mut().cast()) {
+            return name.relative_to(entry.as_ptr().cast());
+        }
+        let _ = entry;
+        name
+    }
+
+    /// Returns a word referring to `ptr`, to be stored in this dictionary.
+    ///
+    /// With the `relocatable` feature, references to this dictionary's own
+    /// memory are stored as their offset from its start, with the lowest bit
+    /// set to tell them apart from pointers (which are always aligned), so
+    /// that the dictionary can be copied somewhere else.
+    pub(crate) fn ref_word<U>(&self, ptr: NonNull<U>) -> Word {
+        #[cfg(feature = "relocatable")]
+        if self.alloc.contains(ptr.as_ptr().cast()) {
+            let offset = (ptr.as_ptr() as usize) - (self.alloc.start as usize);
+            return Word::ptr_data(offset as isize | 1);
+        }
+        Word::ptr(ptr.as_ptr())
+    }
+
+    /// Writes a word referring to `ptr`, as returned by
+    /// [`Dictionary::ref_word`].
+    pub(crate) fn bump_ref<U>(&mut self, ptr: NonNull<U>) -> Result<(), BumpError> {
+        let word = self.ref_word(ptr);
+        self.alloc.bump_write(word)
+    }
+
+    /// Resolves a word written by [`Dictionary::ref_word`].
+    pub(crate) fn resolve_word(&self, word: Word) -> *mut () {
+        #[cfg(feature = "relocatable")]
+        {
+            let data = unsafe { word.ptr_data };
+            if data & 1 == 1 {
+                return self.alloc.start.wrapping_add((data & !1) as usize).cast();
+            }
+        }
+        unsafe { word.ptr }
+    }
+
+    /// Resolves a word written by [`Dictionary::ref_word`] in the parameter
+    /// field of `entry`, which is in this dictionary or one of its parents.
+    pub(crate) fn resolve_in(&self, entry: NonNull<EntryHeader<T>>, word: Word) -> *mut () {
+        #[cfg(feature = "relocatable")]
+        {
+            let mut dict = self;
+            while !dict.alloc.contains(entry.as_ptr().cast()) {
+                match &dict.parent {
+                    Some(ParentDict::Shared(parent)) => dict = parent,
+                    // References from anywhere else are never relative.
+                    _ => break,
+                }
+            }
+            dict.resolve_word(word)
+        }
+        #[cfg(not(feature = "relocatable"))]
+        {
+            let _ = entry;
+            self.resolve_word(word)
+        }
+    }
+
+    /// Returns whether the `len` bytes at `ptr` are all in one of this
+    /// dictionary's frozen parents.
+    pub(crate) fn parents_contain(&self, ptr: *const u8, len: usize) -> bool {
+        self.chain().skip(1).any(|dict| dict.alloc.contains_range(ptr, len))
+    }
+
+    /// Returns this dictionary, followed by the frozen parents that it was
+    /// forked from. Static parents aren't included.
+    pub(crate) fn chain(&self) -> impl Iterator<Item = &Dictionary<T>> + '_ {
+        core::iter::successors(Some(self), |dict| match &dict.parent {
+            Some(ParentDict::Shared(parent)) => Some(&**parent),
+            _ => None,
+        })
+    }
+
+    /// Returns the link to `entry`, to be stored in an entry in this
+    /// dictionary.
+    pub(crate) fn link_to(
+        &self,
+        entry: Option<NonNull<DictionaryEntry<T>>>,
+    ) -> Option<NonNull<DictionaryEntry<T>>> {
+        // With the `relocatable` feature, this may be an offset rather than a
+        // pointer, but it's never zero.
+        entry.and_then(|entry| NonNull::new(unsafe { self.ref_word(entry).ptr }.cast()))
+    }
+
+    /// Resolves a link written by [`Dictionary::link_to`].
+    pub(crate) fn resolve_link(
+        &self,
+        link: Option<NonNull<DictionaryEntry<T>>>,
+    ) -> Option<NonNull<DictionaryEntry<T>>> {
+        link.and_then(|link| NonNull::new(self.resolve_word(Word::ptr(link.as_ptr())).cast()))
+    }
+}
+
+/// The contents of a dictionary, as returned by [`Dictionary::contents`].
+#[cfg(feature = "relocatable")]
+pub struct DictContents<'a> {
+    /// The used part of the dictionary's memory, which holds its entries.
+    pub bytes: &'a [u8],
+    /// The offset of the newest entry in `bytes`, if there are any.
+    pub tail: Option<usize>,
+}
+
+#[cfg(feature = "relocatable")]
+impl<T: 'static> Dictionary<T> {
+    /// Returns the contents of this dictionary, which can be copied into
+    /// another one with [`OwnedDict::load_contents`], or persisted and loaded
+    /// later.
+    ///
+    /// References between the entries of a dictionary are stored as offsets,
+    /// so the contents don't depend on where the dictionary is in memory.
+    /// However, references to builtins and to entries in parent dictionaries
+    /// are still pointers, and so are any pointers stored in variables.
+    pub fn contents(&self) -> DictContents<'_> {
+        let used = self.alloc.used();
+        DictContents {
+            bytes: unsafe { core::slice::from_raw_parts(self.alloc.start, used) },
+            tail: self
+                .tail
+                .map(|tail| (tail.as_ptr() as usize) - (self.alloc.start as usize)),
+        }
+    }
+}
+
+#[cfg(feature = "relocatable")]
+impl<T: 'static> OwnedDict<T> {
+    /// Replaces the entries of this dictionary with `contents`, as returned by
+    /// [`Dictionary::contents`].
+    ///
+    /// # Safety
+    ///
+    /// The contents must have been taken from a dictionary in the same
+    /// program, used with the same builtins and with the same parent
+    /// dictionary (if any) as this one.
+    pub unsafe fn load_contents(&mut self, contents: DictContents<'_>) -> Result<(), BumpError> {
+        let DictContents { bytes, tail } = contents;
+        if bytes.len() > self.alloc.limit() {
+            return Err(BumpError::OutOfMemory);
+        }
+        let alloc = &mut self.alloc;
+        alloc.start.copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+        alloc.cur = alloc.start.add(bytes.len());
+        self.tail = tail.map(|tail| NonNull::new_unchecked(self.alloc.start.add(tail).cast()));
+        Ok(())
+    }
 }
 
 // === SharedDict ===
@@ -431,6 +689,7 @@ impl<T: 'static> OwnedDict<T> {
                 tail: None,
                 refs: AtomicUsize::new(Dictionary::<T>::MUTABLE),
                 parent: None,
+                current: FORTH_WORDLIST,
                 alloc: DictionaryBump::new(bump_base, size),
                 deallocate: D::drop_dict,
             });
@@ -451,14 +710,27 @@ impl<T: 'static> OwnedDict<T> {
     /// We swap `self` to the new, empty OwnedDict, and turn the old `self`
     /// into a SharedDict, both as the parent of our new self, as well as
     /// returning it for other use.
-    pub(crate) fn fork_onto(&mut self, new: OwnedDict<T>) -> SharedDict<T> {
+    pub(crate) fn fork_onto(&mut self, mut new: OwnedDict<T>) -> SharedDict<T> {
+        new.current = self.current;
+        new.alloc.set_quota(self.alloc.quota());
         let this = mem::replace(self, new).into_shared();
         self.set_parent(this.clone());
         this
     }
 
     pub(crate) fn set_parent(&mut self, parent: SharedDict<T>) {
-        let _prev = self.parent.replace(parent);
+        let _prev = self.parent.replace(ParentDict::Shared(parent));
+        debug_assert!(_prev.is_none(), "parent dictionary shouldn't be clobbered!");
+    }
+
+    /// Sets a [`StaticDict`] as the parent of this dictionary, so that its
+    /// entries can be used without taking up any space in this one.
+    ///
+    /// This must be done before the dictionary is used by a VM, as a
+    /// dictionary only has one parent, and a dictionary forked from another
+    /// VM already has one.
+    pub fn set_static_parent(&mut self, parent: &'static StaticDict<T>) {
+        let _prev = self.parent.replace(ParentDict::Static(parent));
         debug_assert!(_prev.is_none(), "parent dictionary shouldn't be clobbered!");
     }
 }
@@ -503,11 +775,16 @@ impl<T: > EntryBuilder<'_, T> {
         Self { kind, ..self }
     }
 
-    pub(crate) fn finish(self, name: FaStr, func: WordFunc<T>) -> NonNull<DictionaryEntry<T>> {
+    pub(crate) fn finish(
+        self,
+        name: FaStr,
+        ty: EntryType,
+        func: WordFunc<T>,
+    ) -> NonNull<DictionaryEntry<T>> {
         unsafe {
             self.base.as_ptr().write(DictionaryEntry {
                 hdr: EntryHeader {
-                    name,
+                    name: self.dict.entry_name(name, self.base),
                     kind: self.kind,
                     len: self.len,
                     _pd: PhantomData
@@ -519,7 +796,9 @@ impl<T: > EntryBuilder<'_, T> {
                 func,
 
                 // Don't link until we know we have a "good" entry!
-                link: self.dict.tail.take(),
+                link: self.dict.link_to(self.dict.tail),
+                wordlist: self.dict.current,
+                ty,
                 parameter_field: [],
             });
         }
@@ -540,24 +819,32 @@ impl<'dict, T: 'static> Iterator for Entries<'dict, T> {
                 Some(entry) => entry,
                 None => {
                     // try to traverse the parent link
-                    if let Some(parent) = self.dict.dict().parent.clone() {
-                        self.next = parent.tail;
-                        self.dict = CurrDict::Parent(parent);
-                        continue;
-                    } else {
-                        return None;
-                    }
+                    let (next, dict) = match self.dict.parent()? {
+                        ParentDict::Shared(parent) => {
+                            (parent.tail, CurrDict::Parent(parent.clone()))
+                        }
+                        ParentDict::Static(parent) => (parent.tail, CurrDict::Static),
+                    };
+                    self.next = next;
+                    self.dict = dict;
+                    continue;
                 }
             };
-            self.next = unsafe {
+            let link = unsafe {
                 // Safety: `self.next` must be a pointer into the VM's dictionary
                 // entries. The caller who constructs a `Entries` iterator is
                 // responsible for ensuring this.
                 entry.as_ref().link
             };
+            self.next = match &self.dict {
+                CurrDict::Leaf(dict) => dict.resolve_link(link),
+                CurrDict::Parent(parent) => parent.resolve_link(link),
+                // Links between static entries are always pointers.
+                CurrDict::Static => link,
+            };
             let found = match self.dict {
                 CurrDict::Leaf(_) => DictLocation::Current(entry),
-                CurrDict::Parent(_) => DictLocation::Parent(entry),
+                CurrDict::Parent(_) | CurrDict::Static => DictLocation::Parent(entry),
             };
             return Some(found);
         }
@@ -565,10 +852,12 @@ impl<'dict, T: 'static> Iterator for Entries<'dict, T> {
 }
 
 impl<T> CurrDict<'_, T> {
-    fn dict(&self) -> &'_ Dictionary<T> {
+    fn parent(&self) -> Option<&'_ ParentDict<T>> {
         match self {
-            Self::Leaf(dict) => dict,
-            Self::Parent(parent) => parent,
+            Self::Leaf(dict) => dict.parent.as_ref(),
+            Self::Parent(parent) => parent.parent.as_ref(),
+            // Static dictionaries don't have parents.
+            Self::Static => None,
         }
     }
 }
@@ -581,6 +870,7 @@ impl DictionaryBump {
             end,
             start: bottom,
             cur: bottom,
+            limit: end,
         }
     }
 
@@ -609,7 +899,7 @@ impl DictionaryBump {
 
         let req = self.cur.wrapping_add(n);
 
-        if req > self.end {
+        if req > self.limit {
             None
         } else {
             let ptr = self.cur;
@@ -618,8 +908,9 @@ impl DictionaryBump {
         }
     }
 
+    #[allow(dead_code)]
     pub fn bump_u8(&mut self) -> Option<NonNull<u8>> {
-        if self.cur >= self.end {
+        if self.cur >= self.limit {
             None
         } else {
             let ptr = self.cur;
@@ -639,7 +930,7 @@ impl DictionaryBump {
         let align_cur = self.cur.wrapping_add(offset);
         let new_cur = align_cur.wrapping_add(Layout::new::<T>().size());
 
-        if new_cur > self.end {
+        if new_cur > self.limit {
             Err(BumpError::OutOfMemory)
         } else {
             self.cur = new_cur;
@@ -663,6 +954,17 @@ impl DictionaryBump {
         (pau >= sau) && (pau < eau)
     }
 
+    /// Returns whether all of the `len` bytes at `ptr` are in the bump
+    /// region.
+    pub fn contains_range(&self, ptr: *const u8, len: usize) -> bool {
+        let last = len.saturating_sub(1);
+        if (ptr as usize).checked_add(last).is_none() {
+            return false;
+        }
+        self.contains(ptr.cast_mut().cast())
+            && self.contains(ptr.wrapping_add(last).cast_mut().cast())
+    }
+
     pub fn capacity(&self) -> usize {
         (self.end as usize) - (self.start as usize)
     }
@@ -670,6 +972,23 @@ impl DictionaryBump {
     pub fn used(&self) -> usize {
         (self.cur as usize) - (self.start as usize)
     }
+
+    /// Returns how many bytes may be allocated in all, which is less than
+    /// the capacity if there's a quota.
+    pub fn limit(&self) -> usize {
+        (self.limit as usize) - (self.start as usize)
+    }
+
+    pub fn quota(&self) -> Option<usize> {
+        (self.limit != self.end).then(|| self.limit())
+    }
+
+    /// Stops allocations from using more than `quota` bytes in all, or lets
+    /// them use the whole capacity if `quota` is `None`.
+    pub fn set_quota(&mut self, quota: Option<usize>) {
+        let limit = quota.map_or(self.capacity(), |q| q.min(self.capacity()));
+        self.limit = self.start.wrapping_add(limit);
+    }
 }
 
 impl<T: 'static> DictLocation<T> {
@@ -687,7 +1006,7 @@ pub mod test {
     use std::alloc::Layout;
 
     use crate::{
-        dictionary::{DictionaryBump, DictionaryEntry, BuiltinEntry, DictLocation},
+        dictionary::{DictionaryBump, DictionaryEntry, BuiltinEntry, DictLocation, EntryKind},
         leakbox::{LeakBox, alloc_dict, LeakBoxDict},
         Word, Error, Forth,
     };
@@ -746,7 +1065,7 @@ pub mod test {
         let buf_1: OwnedDict<()> = alloc_dict::<(), LeakBoxDict>(512);
         let mut buf_2: OwnedDict<()> = alloc_dict::<(), LeakBoxDict>(256);
         let buf_1 = buf_1.into_shared();
-        buf_2.parent = Some(buf_1);
+        buf_2.set_parent(buf_1);
     }
 
     // Similar to above, but making sure refcounting works properly
@@ -757,9 +1076,9 @@ pub mod test {
         let mut buf_3: OwnedDict<()> = alloc_dict::<(), LeakBoxDict>(128);
         let buf_1 = buf_1.into_shared();
         assert_eq!(buf_1.refs.load(Ordering::Relaxed), 1);
-        buf_2.parent = Some(buf_1.clone());
+        buf_2.set_parent(buf_1.clone());
         assert_eq!(buf_1.refs.load(Ordering::Relaxed), 2);
-        buf_3.parent = Some(buf_1.clone());
+        buf_3.set_parent(buf_1.clone());
         assert_eq!(buf_1.refs.load(Ordering::Relaxed), 3);
 
         drop(buf_2);
@@ -779,8 +1098,8 @@ pub mod test {
         assert!(buf.tail.is_none());
 
         let strname = buf.alloc.bump_str("stubby").unwrap();
-        buf.add_bi_fastr(strname, stubby).unwrap();
-        assert_eq!(unsafe { buf.tail.as_ref().unwrap().as_ref().hdr.name.as_str() }, "stubby");
+        buf.add_bi_kind(strname, stubby, EntryKind::RuntimeBuiltin).unwrap();
+        assert_eq!(unsafe { DictionaryEntry::name(buf.tail.unwrap()).as_str() }, "stubby");
     }
 
     #[test]
@@ -792,7 +1111,7 @@ pub mod test {
         // Put a builtin into the first slab
         let mut buf_1: OwnedDict<()> = alloc_dict::<(), LeakBoxDict>(512);
         let strname = buf_1.alloc.bump_str("stubby").unwrap();
-        buf_1.add_bi_fastr(strname, stubby).unwrap();
+        buf_1.add_bi_kind(strname, stubby, EntryKind::RuntimeBuiltin).unwrap();
 
         // Make a new dict slab, which "becomes" the mutable tip, with the original
         // slab as the parent of the new mutable tip
@@ -801,13 +1120,13 @@ pub mod test {
 
         // Find the builtin in the original slab, it should say "current" here
         let ro_find = buf_1_ro.entries().find(|e| {
-            unsafe { e.entry().as_ref() }.hdr.name.as_str() == "stubby"
+            unsafe { DictionaryEntry::name(e.entry()) }.as_str() == "stubby"
         }).unwrap();
         assert!(matches!(ro_find, DictLocation::Current(_)));
 
         // Now find the builtin in the new mutable slab, it should say "parent" here
         let rw_find = buf_1.entries().find(|e| {
-            unsafe { e.entry().as_ref() }.hdr.name.as_str() == "stubby"
+            unsafe { DictionaryEntry::name(e.entry()) }.as_str() == "stubby"
         }).unwrap();
         assert!(matches!(rw_find, DictLocation::Parent(_)));
     }
@@ -44,7 +44,7 @@ impl FaStr {
         self.ptr
     }
 
-    pub fn as_bytes(&self) -> &[u8] {
+    pub const fn as_bytes(&self) -> &[u8] {
         let len = self.len_hash.len();
         unsafe { core::slice::from_raw_parts(self.ptr, len) }
     }
@@ -75,6 +75,42 @@ impl FaStr {
             len_hash: LenHash { inner: self.len_hash.inner },
         }
     }
+
+    /// Returns a `FaStr` holding the position of this one's data relative to
+    /// `base`, rather than a pointer to it, which must be [resolved] before it
+    /// is used.
+    ///
+    /// [resolved]: FaStr::resolve
+    #[cfg(feature = "relocatable")]
+    pub(crate) fn relative_to(self, base: *const u8) -> Self {
+        Self {
+            // The offset is only ever added back to `base`, so it doesn't
+            // need (and mustn't be given) provenance.
+            ptr: core::ptr::without_provenance(self.ptr.addr().wrapping_sub(base.addr())),
+            len_hash: LenHash {
+                inner: self.len_hash.inner | LenHash::RELATIVE_BIT,
+            },
+        }
+    }
+
+    /// Returns a `FaStr` pointing to this one's data, if it was made by
+    /// [`FaStr::relative_to`] with the same `base`, or a copy of it if not.
+    ///
+    /// # Safety
+    ///
+    /// The same as [`FaStr::copy_in_child`].
+    pub(crate) unsafe fn resolve(&self, base: *const u8) -> Self {
+        let inner = self.len_hash.inner;
+        if inner & LenHash::RELATIVE_BIT == 0 {
+            return self.copy_in_child();
+        }
+        Self {
+            ptr: base.wrapping_add(self.ptr.addr()),
+            len_hash: LenHash {
+                inner: inner & !LenHash::RELATIVE_BIT,
+            },
+        }
+    }
 }
 
 impl PartialEq for FaStr {
@@ -102,6 +138,8 @@ impl LenHash {
     const HASH_MASK: u32 = 0x00FF_FFFF;
     const BITS_MASK: u32 = 0xE000_0000;
     const LEN_MASK: u32 = 0x1F00_0000;
+    /// Set in a relative [`FaStr`], see [`FaStr::relative_to`].
+    const RELATIVE_BIT: u32 = 0x2000_0000;
 
     /// Creates a new LenHash, considering UP TO 31 ascii characters.
     #[allow(clippy::should_implement_trait)]
@@ -123,7 +161,7 @@ impl LenHash {
     }
 
     #[allow(clippy::len_without_is_empty)]
-    pub fn len(&self) -> usize {
+    pub const fn len(&self) -> usize {
         let len_u32 = (self.inner & Self::LEN_MASK) >> 24;
         len_u32 as usize
     }
@@ -139,10 +177,15 @@ impl LenHash {
 }
 
 pub const fn comptime_fastr(s: &'static str) -> FaStr {
+    comptime_fastr_bytes(s.as_bytes())
+}
+
+/// Like [`comptime_fastr`], for the bytes of a string.
+pub(crate) const fn comptime_fastr_bytes(s: &'static [u8]) -> FaStr {
     let len = s.len();
     assert!(!s.is_empty());
     assert!(len <= 31);
-    let hash = comptime_hash_by(s.as_bytes(), BASIS);
+    let hash = comptime_hash_by(s, BASIS);
     FaStr {
         ptr: s.as_ptr(),
         len_hash: LenHash {
@@ -1,10 +1,107 @@
+use crate::ReplaceErr;
+
+#[cfg(feature = "async")]
+use core::task::{Context, Poll};
+
 pub struct WordStrBuf {
     start: *mut u8,
     cur: *mut u8,
     end: *mut u8,
+    filled: *mut u8,
     holding: Holding,
+    /// Has the whole buffer been written to at least once?
+    initialized: bool,
+    /// How many strings (or files) are currently being `evaluate`d (or
+    /// `include`d).
+    nesting: u16,
+}
+
+/// The state of a [`WordStrBuf`] before a nested string was pushed with
+/// [`WordStrBuf::push_nested`].
+pub(crate) struct NestedInput {
+    start: *mut u8,
+    cur: *mut u8,
+    filled: *mut u8,
+    holding: Holding,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum InputError {
+    LineTooLong,
+    NotAscii,
+    ReadFailed,
+}
+
+/// A source that the VM can pull more input from on demand.
+///
+/// Normally, all input must be placed in the VM's [`WordStrBuf`] with
+/// [`WordStrBuf::fill`] before calling `process_line`. When an `InputSource`
+/// is attached to a VM (see [`Forth::set_input_source`]), the `refill`,
+/// `key`, `key?` and `accept` words can read more input while a line is
+/// executing, and `:` definitions may span more than one line.
+///
+/// [`Forth::set_input_source`]: crate::Forth::set_input_source
+pub trait InputSource {
+    /// Reads the next line of input into `buf`, returning the number of bytes
+    /// written, or `None` if the source is exhausted.
+    fn read_line(&mut self, buf: &mut [u8]) -> Result<Option<usize>, InputError>;
+
+    /// Reads a single character, or `None` if the source is exhausted.
+    fn read_key(&mut self) -> Result<Option<u8>, InputError>;
+
+    /// Returns `true` if a character can be read with
+    /// [`InputSource::read_key`] without waiting.
+    fn key_ready(&mut self) -> bool;
+}
+
+/// The asynchronous version of [`InputSource`], for use with
+/// [`AsyncForth`](crate::AsyncForth).
+///
+/// The methods of this trait are poll-based (much like
+/// `futures::io::AsyncRead`), so that a source can be attached to a VM as a
+/// trait object.
+///
+/// Note that unlike a synchronous [`InputSource`], an `AsyncInputSource` is
+/// only used by the `refill`, `key`, `key?` and `accept` words. A `:`
+/// definition is compiled in a single step, and so must still fit on one line.
+#[cfg(feature = "async")]
+pub trait AsyncInputSource {
+    /// Attempts to read the next line of input into `buf`, returning the
+    /// number of bytes written, or `None` if the source is exhausted.
+    fn poll_read_line(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<Option<usize>, InputError>>;
+
+    /// Attempts to read a single character, or `None` if the source is
+    /// exhausted.
+    fn poll_read_key(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<u8>, InputError>>;
+
+    /// Returns `true` if a character is available without waiting.
+    fn key_ready(&mut self) -> bool;
 }
 
+/// What an input source has been asked to read.
+#[derive(Clone, Copy)]
+pub(crate) enum InputRequest {
+    /// The next line, into the VM's [`WordStrBuf`].
+    Line,
+    /// A single character.
+    Key,
+    /// The next line, into a buffer in VM memory (for `accept`).
+    Accept { addr: *mut u8, len: usize },
+}
+
+/// An [`InputSource`] reading from any [`std::io::BufRead`], such as stdin or
+/// a file.
+#[cfg(any(test, doctest, feature = "use-std"))]
+pub struct IoSource<R> {
+    reader: R,
+    line: std::vec::Vec<u8>,
+}
+
+#[derive(Clone, Copy)]
 enum Holding {
     None,
     Word((*mut u8, usize)),
@@ -19,7 +116,10 @@ impl WordStrBuf {
             end,
             start: bottom,
             cur: end,
+            filled: bottom,
             holding: Holding::None,
+            initialized: false,
+            nesting: 0,
         }
     }
 
@@ -28,31 +128,182 @@ impl WordStrBuf {
         (self.end as usize) - (self.start as usize)
     }
 
+    #[allow(clippy::result_unit_err)]
     pub fn fill(&mut self, input: &str) -> Result<(), ()> {
+        self.check_line(input.as_bytes()).replace_err(())?;
         let ilen = input.len();
-        let cap = self.capacity();
-        if ilen > cap {
-            return Err(());
+        unsafe {
+            core::ptr::copy_nonoverlapping(input.as_bytes().as_ptr(), self.start, ilen);
         }
-        if !input.is_ascii() {
+        self.set_line(ilen);
+        Ok(())
+    }
+
+    /// The whole buffer, for an [`InputSource`] to read a new line into.
+    ///
+    /// Must be followed by a call to [`WordStrBuf::commit`].
+    pub(crate) fn raw_buf_mut(&mut self) -> &mut [u8] {
+        self.ensure_initialized();
+        unsafe { core::slice::from_raw_parts_mut(self.start, self.capacity()) }
+    }
+
+    fn ensure_initialized(&mut self) {
+        if !self.initialized {
+            unsafe {
+                core::ptr::write_bytes(self.start, b' ', self.capacity());
+            }
+            self.initialized = true;
+        }
+    }
+
+    /// Makes the first `len` bytes of the buffer the current line, and resets
+    /// the cursor to the start of it.
+    ///
+    /// If the line isn't valid, the buffer is left empty, as whatever was
+    /// read has already overwritten the previous line.
+    pub(crate) fn commit(&mut self, len: usize) -> Result<(), InputError> {
+        let res = if len > self.capacity() {
+            Err(InputError::LineTooLong)
+        } else {
+            self.check_line(unsafe { core::slice::from_raw_parts(self.start, len) })
+        };
+        if let Err(e) = res {
+            self.cur = self.end;
+            self.filled = self.start;
+            self.holding = Holding::None;
+            return Err(e);
+        }
+        self.set_line(len);
+        Ok(())
+    }
+
+    /// Checks that `line` can be made the current line.
+    pub(crate) fn check_line(&self, line: &[u8]) -> Result<(), InputError> {
+        if line.len() > self.capacity() {
+            return Err(InputError::LineTooLong);
+        }
+        if !line.is_ascii() {
             // TODO: Do I care about this?
-            return Err(());
+            return Err(InputError::NotAscii);
         }
+        Ok(())
+    }
+
+    /// Makes the first `len` bytes of the buffer, which have already been
+    /// checked with [`WordStrBuf::check_line`], the current line.
+    fn set_line(&mut self, len: usize) {
+        let cap = self.capacity();
         // TODO: I probably *don't* want to lowercase everything, this also affects
         // things like string literals, which don't need to be lowercased.
+        unsafe { core::slice::from_raw_parts_mut(self.start, len) }.make_ascii_lowercase();
         unsafe {
-            let istart = input.as_bytes().as_ptr();
-            for i in 0..ilen {
-                self.start
-                    .add(i)
-                    .write((istart.add(i).read()).to_ascii_lowercase());
-            }
-            core::ptr::write_bytes(self.start.add(ilen), b' ', cap - ilen);
+            core::ptr::write_bytes(self.start.add(len), b' ', cap - len);
         }
         self.cur = self.start;
+        self.filled = self.start.wrapping_add(len);
+        self.holding = Holding::None;
+        self.initialized = true;
+    }
+
+    /// Starts parsing the `len` bytes at `src` in place of the current line,
+    /// returning the state needed to go back to the current line with
+    /// [`WordStrBuf::pop_nested`].
+    ///
+    /// The string is copied into the unused space after the current line, so
+    /// it must fit in what is left of the buffer.
+    ///
+    /// # Safety
+    ///
+    /// `src` must be valid for reads of `len` bytes. It may point into this
+    /// buffer.
+    pub(crate) unsafe fn push_nested(
+        &mut self,
+        src: *const u8,
+        len: usize,
+    ) -> Result<NestedInput, InputError> {
+        self.ensure_initialized();
+        let free = (self.end as usize) - (self.filled as usize);
+        if len > free {
+            return Err(InputError::LineTooLong);
+        }
+        let dest = self.filled;
+        core::ptr::copy(src, dest, len);
+        let line = core::slice::from_raw_parts_mut(dest, len);
+        if !line.is_ascii() {
+            line.fill(b' ');
+            return Err(InputError::NotAscii);
+        }
+        line.make_ascii_lowercase();
+
+        let saved = NestedInput {
+            start: self.start,
+            cur: self.cur,
+            filled: self.filled,
+            holding: self.holding,
+        };
+        // Everything after the nested string is still padding, so `end` can
+        // stay where it is.
+        self.start = dest;
+        self.cur = dest;
+        self.filled = dest.add(len);
+        self.holding = Holding::None;
+        self.nesting += 1;
+        Ok(saved)
+    }
+
+    /// Goes back to the line that was being parsed before the matching call to
+    /// [`WordStrBuf::push_nested`].
+    pub(crate) fn pop_nested(&mut self, saved: NestedInput) {
+        // Turn the nested string back into padding, so the outer line doesn't
+        // run on into it.
+        let len = (self.filled as usize) - (saved.filled as usize);
+        unsafe {
+            core::ptr::write_bytes(saved.filled, b' ', len);
+        }
+        self.start = saved.start;
+        self.cur = saved.cur;
+        self.filled = saved.filled;
+        self.holding = saved.holding;
+        self.nesting -= 1;
+    }
+
+    /// Returns `true` while a string is being `evaluate`d, or a file is being
+    /// `include`d.
+    pub fn is_nested(&self) -> bool {
+        self.nesting != 0
+    }
+
+    /// How many levels of nested input are currently being parsed.
+    pub(crate) fn nesting(&self) -> u16 {
+        self.nesting
+    }
+
+    /// Returns the current line, and how many bytes of it have been parsed.
+    #[cfg(feature = "use-std")]
+    pub(crate) fn line_and_cursor(&self) -> (&[u8], usize) {
+        let (start, len) = self.source();
+        let cursor = (self.cur.min(self.filled) as usize) - (start as usize);
+        (unsafe { core::slice::from_raw_parts(start, len) }, cursor)
+    }
+
+    /// Makes `line` the current line, carrying on parsing `cursor` bytes into
+    /// it, as returned by [`WordStrBuf::line_and_cursor`].
+    #[cfg(feature = "use-std")]
+    pub(crate) fn restore_line(&mut self, line: &[u8], cursor: usize) -> Result<(), InputError> {
+        self.check_line(line)?;
+        self.raw_buf_mut()[..line.len()].copy_from_slice(line);
+        self.set_line(line.len());
+        self.cur = self.start.wrapping_add(cursor.min(line.len()));
         Ok(())
     }
 
+    /// Returns the address and length of the current line, as used by the
+    /// `source` word.
+    pub fn source(&self) -> (*mut u8, usize) {
+        let len = (self.filled as usize) - (self.start as usize);
+        (self.start, len)
+    }
+
     // Move `self.cur` to the next non-whitespace character,
     // and return the value of `self.cur` after moving.
     //
@@ -92,6 +343,7 @@ impl WordStrBuf {
         self.holding = Holding::Word((start, size));
     }
 
+    #[allow(clippy::result_unit_err)]
     pub fn advance_str(&mut self) -> Result<(), ()> {
         if self.cur_word() == Some(r#".""#) {
             self.holding = Holding::None;
@@ -124,23 +376,71 @@ impl WordStrBuf {
 
     pub fn cur_str_literal(&self) -> Option<&str> {
         match &self.holding {
-            Holding::None => return None,
+            Holding::None => None,
             Holding::Str((start, len)) => Some(unsafe {
                 let u8_sli = core::slice::from_raw_parts(*start, *len);
                 core::str::from_utf8_unchecked(u8_sli)
             }),
-            Holding::Word(_) => return None,
+            Holding::Word(_) => None,
         }
     }
 
     pub fn cur_word(&self) -> Option<&str> {
         match &self.holding {
-            Holding::None => return None,
+            Holding::None => None,
             Holding::Word((start, len)) => Some(unsafe {
                 let u8_sli = core::slice::from_raw_parts(*start, *len);
                 core::str::from_utf8_unchecked(u8_sli)
             }),
-            Holding::Str(_) => return None,
+            Holding::Str(_) => None,
         }
     }
 }
+
+// === impl IoSource ===
+
+#[cfg(any(test, doctest, feature = "use-std"))]
+impl<R: std::io::BufRead> IoSource<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: std::vec::Vec::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(any(test, doctest, feature = "use-std"))]
+impl<R: std::io::BufRead> InputSource for IoSource<R> {
+    fn read_line(&mut self, buf: &mut [u8]) -> Result<Option<usize>, InputError> {
+        self.line.clear();
+        let n = self
+            .reader
+            .read_until(b'\n', &mut self.line)
+            .replace_err(InputError::ReadFailed)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        let dest = buf.get_mut(..n).ok_or(InputError::LineTooLong)?;
+        dest.copy_from_slice(&self.line);
+        Ok(Some(n))
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, InputError> {
+        let avail = self.reader.fill_buf().replace_err(InputError::ReadFailed)?;
+        let key = avail.first().copied();
+        if key.is_some() {
+            self.reader.consume(1);
+        }
+        Ok(key)
+    }
+
+    /// Note that this calls [`BufRead::fill_buf`](std::io::BufRead::fill_buf),
+    /// which may block for interactive readers such as stdin.
+    fn key_ready(&mut self) -> bool {
+        self.reader.fill_buf().map(|b| !b.is_empty()).unwrap_or(false)
+    }
+}
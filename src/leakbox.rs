@@ -6,7 +6,7 @@ use std::{
 };
 
 use crate::{
-    dictionary::{BuiltinEntry, DropDict, OwnedDict, Dictionary}, input::WordStrBuf, output::OutputBuf, word::Word, CallContext, Forth,
+    block::{BlockBuffer, BlockDevice}, dictionary::{BuiltinEntry, DropDict, OwnedDict, Dictionary}, input::{InputSource, WordStrBuf}, output::OutputBuf, rom::StaticDict, word::Word, CallContext, Error, Forth,
 };
 
 #[cfg(feature = "async")]
@@ -46,6 +46,10 @@ impl<T> LeakBox<T> {
     pub fn len(&self) -> usize {
         self.len
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
 }
 
 impl<T> Drop for LeakBox<T> {
@@ -59,7 +63,7 @@ impl<T> Drop for LeakBox<T> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct LBForthParams {
     pub data_stack_elems: usize,
     pub return_stack_elems: usize,
@@ -92,6 +96,8 @@ pub struct LBForth<T: 'static> {
     _payload_cstack: LeakBox<CallContext<T>>,
     _input_buf: LeakBox<u8>,
     _output_buf: LeakBox<u8>,
+    _input_source: Option<Box<dyn InputSource>>,
+    _block_device: Option<(Box<dyn BlockDevice>, LeakBox<BlockBuffer>)>,
 }
 
 #[cfg(feature = "async")]
@@ -139,7 +145,46 @@ impl<T: 'static> LBForth<T> {
             _payload_cstack,
             _input_buf,
             _output_buf,
+            _input_source: None,
+            _block_device: None,
+        }
+    }
+
+    /// Attaches an [`InputSource`] to the VM, replacing any previous one.
+    pub fn set_input_source<I: InputSource + 'static>(&mut self, source: I) {
+        let mut source: Box<dyn InputSource> = Box::new(source);
+        unsafe {
+            self.forth.set_input_source(Some(NonNull::from(&mut *source)));
+        }
+        self._input_source = Some(source);
+    }
+
+    /// Attaches a [`BlockDevice`] to the VM with `buffers` block buffers,
+    /// replacing any previous one (without saving its buffers).
+    pub fn set_block_device<D: BlockDevice + 'static>(
+        &mut self,
+        device: D,
+        buffers: usize,
+    ) -> Result<(), Error> {
+        if buffers == 0 {
+            return Err(Error::NoBlockBuffers);
+        }
+        let mut device: Box<dyn BlockDevice> = Box::new(device);
+        let buffers: LeakBox<BlockBuffer> = LeakBox::new(buffers);
+        unsafe {
+            self.forth
+                .set_block_device(NonNull::from(&mut *device), (buffers.ptr(), buffers.len()))?;
         }
+        self._block_device = Some((device, buffers));
+        Ok(())
+    }
+
+    /// Sets a [`StaticDict`] as the parent of the VM's dictionary.
+    ///
+    /// A forked VM's dictionary already has a parent, so this can't be used
+    /// with one.
+    pub fn set_static_parent(&mut self, parent: &'static StaticDict<T>) {
+        self.forth.dict.set_static_parent(parent);
     }
 
     /// Constructs a new VM whose dictionary is a fork of this VM's dictionary.
@@ -180,6 +225,8 @@ impl<T: 'static> LBForth<T> {
             _payload_cstack,
             _input_buf,
             _output_buf,
+            _input_source: None,
+            _block_device: None,
         }
     }
 }
@@ -3,10 +3,19 @@
 #![cfg_attr(not(any(test, doctest, feature = "use-std")), no_std)]
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 
+// So that `#[forth3::macros::builtin]` can be used within this crate.
+#[cfg(feature = "macros")]
+extern crate self as forth3;
+
+pub mod block;
+pub mod buffers;
+pub mod builtin_set;
 pub mod dictionary;
 pub mod fastr;
 pub mod input;
 pub mod output;
+pub mod rom;
+pub mod sched;
 pub mod stack;
 pub(crate) mod vm;
 pub mod word;
@@ -17,6 +26,15 @@ pub mod leakbox;
 #[cfg(any(test, doctest, feature = "_force_test_utils"))]
 pub mod testutil;
 
+/// Procedural macros, with the `macros` feature.
+///
+/// These live in their own module because [`builtin!`] is already taken by
+/// the `macro_rules!` macro used for this crate's own builtins.
+#[cfg(feature = "macros")]
+pub mod macros {
+    pub use forth3_macros::builtin;
+}
+
 use core::ptr::NonNull;
 
 use dictionary::{BuiltinEntry, EntryHeader, EntryKind, DictLocation};
@@ -24,17 +42,25 @@ use dictionary::{BuiltinEntry, EntryHeader, EntryKind, DictLocation};
 #[cfg(feature = "async")]
 use dictionary::AsyncBuiltinEntry;
 
-pub use crate::vm::Forth;
+pub use crate::vm::{Forth, Step};
+pub use crate::vm::image::ImageError;
+pub use crate::vm::stats::{DictStats, MemoryStats, StackStats};
+#[cfg(feature = "use-std")]
+pub use crate::vm::snapshot::{Frame, FrameTarget, Snapshot};
 #[cfg(feature = "async")]
 pub use crate::vm::AsyncForth;
+#[cfg(feature = "use-std")]
+pub use crate::vm::builtins::file::FileError;
 use crate::{
+    block::BlockError,
     dictionary::{BumpError, DictionaryEntry},
+    input::InputError,
     output::OutputError,
     stack::StackError,
     word::Word,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Mode {
     Run,
     Compile,
@@ -45,6 +71,9 @@ pub enum Error {
     Stack(StackError),
     Bump(BumpError),
     Output(OutputError),
+    Input(InputError),
+    Block(BlockError),
+    Image(ImageError),
     CFANotInDict(Word),
     WordNotInDict,
     ColonCompileMissingName,
@@ -80,10 +109,54 @@ pub enum Error {
     DivideByZero,
     AddrOfMissingName,
     AddrOfNotAWord,
+    NoInputSource,
+    InputExhausted,
+    AsyncBuiltinInSyncContext,
+    IncludeMissingPath,
+    IncludeFailed,
+    FileAccessDisabled,
+    NoBlockDevice,
+    NoBlockBuffers,
+    NoCurrentBlock,
+    VariableInStaticDict,
+    SnapshotWhileNested,
+    OutOfFuelWhileNested,
+    NoScheduler,
+    TooManyTasks,
+    TaskKilled,
+    ChannelFull,
+    ChannelEmpty,
+    Cancelled,
+    SearchOrderOverflow,
+    SearchOrderUnderflow,
+    BadWordlist(i32),
+    TooManyWordlists,
+    WordlistInStaticDict,
+    AccessViolation,
+    TooManyRegions,
+    SeeMissingName,
+    SeeNotAWord,
+
+    // An error that happened while interpreting line `line` of the included
+    // file at `path`.
+    #[cfg(feature = "use-std")]
+    InFile {
+        path: std::path::PathBuf,
+        line: usize,
+        error: std::boxed::Box<Error>,
+    },
 
     // Not *really* an error - but signals that a function should be called
     // again. At the moment, only used for internal interpreter functions.
     PendingCallAgain,
+
+    // Also not *really* an error - signals that a word is waiting on an
+    // `AsyncInputSource`, and should be called again once input is ready.
+    PendingInput,
+
+    // Signals that the fuel given with `Forth::set_fuel` has run out, and the
+    // line can be carried on with `Forth::resume`.
+    OutOfFuel,
 }
 
 impl From<StackError> for Error {
@@ -104,6 +177,24 @@ impl From<OutputError> for Error {
     }
 }
 
+impl From<InputError> for Error {
+    fn from(ie: InputError) -> Self {
+        Error::Input(ie)
+    }
+}
+
+impl From<BlockError> for Error {
+    fn from(be: BlockError) -> Self {
+        Error::Block(be)
+    }
+}
+
+impl From<ImageError> for Error {
+    fn from(ie: ImageError) -> Self {
+        Error::Image(ie)
+    }
+}
+
 impl From<core::fmt::Error> for Error {
     fn from(_oe: core::fmt::Error) -> Self {
         Error::Output(OutputError::FormattingErr)
@@ -118,11 +209,7 @@ pub struct CallContext<T: 'static> {
 
 impl<T: 'static> Clone for CallContext<T> {
     fn clone(&self) -> Self {
-        Self {
-            eh: self.eh,
-            idx: self.idx,
-            len: self.len,
-        }
+        *self
     }
 }
 
@@ -140,7 +227,9 @@ impl<T: 'static> CallContext<T> {
             EntryKind::StaticBuiltin => Err(Error::BuiltinHasNoNextValue),
             EntryKind::RuntimeBuiltin => Err(Error::BuiltinHasNoNextValue),
             #[cfg(feature = "async")]
-            EntryKind::AsyncBuiltin => Err(Error::BuiltinHasNoNextValue),
+            EntryKind::AsyncBuiltin | EntryKind::RuntimeAsyncBuiltin => {
+                Err(Error::BuiltinHasNoNextValue)
+            }
             EntryKind::Dictionary => unsafe {
                 let de = self.eh.cast::<DictionaryEntry<T>>();
                 let start = DictionaryEntry::pfa(de).as_ptr().add(req_start as usize);
@@ -163,7 +252,9 @@ impl<T: 'static> CallContext<T> {
             EntryKind::StaticBuiltin => Err(Error::BuiltinHasNoNextValue),
             EntryKind::RuntimeBuiltin => Err(Error::BuiltinHasNoNextValue),
             #[cfg(feature = "async")]
-            EntryKind::AsyncBuiltin => Err(Error::BuiltinHasNoNextValue),
+            EntryKind::AsyncBuiltin | EntryKind::RuntimeAsyncBuiltin => {
+                Err(Error::BuiltinHasNoNextValue)
+            }
             EntryKind::Dictionary => unsafe {
                 let de = self.eh.cast::<DictionaryEntry<T>>();
                 let val_ptr = DictionaryEntry::pfa(de).as_ptr().add(self.idx as usize);
@@ -191,7 +282,7 @@ impl<T: 'static> CallContext<T> {
             EntryKind::StaticBuiltin => None,
             EntryKind::RuntimeBuiltin => None,
             #[cfg(feature = "async")]
-            EntryKind::AsyncBuiltin => None,
+            EntryKind::AsyncBuiltin | EntryKind::RuntimeAsyncBuiltin => None,
             EntryKind::Dictionary => unsafe {
                 let de = self.eh.cast::<DictionaryEntry<T>>();
                 Some(&*DictionaryEntry::pfa(de).as_ptr().add(self.idx as usize))
@@ -206,6 +297,35 @@ impl<T: 'static> CallContext<T> {
 /// to the dictionary entry.
 type WordFunc<T> = fn(&mut Forth<T>) -> Result<(), Error>;
 
+/// A builtin word added at runtime with [`Forth::add_builtin`].
+///
+/// This is implemented for functions and closures taking the VM, so a
+/// builtin can bring its own state, rather than keeping it in the VM's host
+/// context. It's also implemented for `&'static dyn Builtin<T>`.
+///
+/// Builtins may be shared by VMs forked from the same VM, which may be on
+/// different threads, so any state must be `Send` and `Sync` (e.g. atomics
+/// rather than `Cell`s).
+pub trait Builtin<T: 'static>: Send + Sync {
+    fn call(&self, forth: &mut Forth<T>) -> Result<(), Error>;
+}
+
+impl<T, F> Builtin<T> for F
+where
+    T: 'static,
+    F: Fn(&mut Forth<T>) -> Result<(), Error> + Send + Sync,
+{
+    fn call(&self, forth: &mut Forth<T>) -> Result<(), Error> {
+        self(forth)
+    }
+}
+
+impl<T: 'static> Builtin<T> for &'static dyn Builtin<T> {
+    fn call(&self, forth: &mut Forth<T>) -> Result<(), Error> {
+        (**self).call(forth)
+    }
+}
+
 pub enum Lookup<T: 'static> {
     Dict(DictLocation<T>),
     Literal {
@@ -233,6 +353,7 @@ pub enum Lookup<T: 'static> {
     Constant,
     Variable,
     Array,
+    Channel,
 }
 
 trait ReplaceErr {
@@ -253,6 +374,7 @@ impl<T, OE> ReplaceErr for Result<T, OE> {
 
 #[cfg(test)]
 pub mod test {
+    #[cfg(feature = "async")]
     use core::{future::Future, cmp::Ordering, task::Poll};
 
     use crate::{
@@ -260,9 +382,13 @@ pub mod test {
         leakbox::{LBForth, LBForthParams},
         word::Word,
         Forth,
-        Error, testutil::{all_runtest, blocking_runtest_with},
+        testutil::{all_runtest, blocking_runtest_with, sizing_runtest},
     };
 
+    use crate::Error;
+    #[cfg(feature = "use-std")]
+    use crate::FileError;
+
     #[derive(Default)]
     struct TestContext {
         contents: Vec<i32>,
@@ -273,9 +399,9 @@ pub mod test {
     #[test]
     fn sizes() {
         use core::mem::{align_of, size_of};
-        assert_eq!(5 * size_of::<usize>(), size_of::<DictionaryEntry<()>>());
-        assert_eq!(5 * size_of::<usize>(), size_of::<DictionaryEntry<()>>());
-        assert_eq!(1 * size_of::<usize>(), align_of::<Word>());
+        assert_eq!(6 * size_of::<usize>(), size_of::<DictionaryEntry<()>>());
+        assert_eq!(6 * size_of::<usize>(), size_of::<DictionaryEntry<()>>());
+        assert_eq!(size_of::<usize>(), align_of::<Word>());
     }
 
     #[test]
@@ -415,212 +541,1965 @@ pub mod test {
     }
 
     #[test]
-    fn it_still_works_when_forked() {
-        let mut lbforth1 = LBForth::from_params(
+    fn input_source() {
+        use crate::input::IoSource;
+
+        let mut lbforth = LBForth::from_params(
             LBForthParams::default(),
             TestContext::default(),
             Forth::<TestContext>::FULL_BUILTINS,
         );
+        let source = "  2 *\n;\n5 double .\nabc";
+        lbforth.set_input_source(IoSource::new(source.as_bytes()));
 
-        // run all the tests on the first forth VM
-        println!("\n --- testing first forth VM --- \n");
-        blocking_runtest_with(&mut lbforth1.forth, r#"
-            > 2 3 + .
-            < 5 ok.
-            > : yay 2 3 + . ;
-            < ok.
-            > yay yay yay
-            < 5 5 5 ok.
-            > : boop yay yay ;
-            < ok.
-            > boop
-            < 5 5 ok.
-            > : err if boop boop boop else yay yay then ;
-            < ok.
-            > : erf if boop boop boop then yay yay ;
-            < ok.
-            > 0 err
-            < 5 5 ok.
-            > 1 err
-            < 5 5 5 5 5 5 ok.
-            > 0 erf
-            < 5 5 ok.
-            > 1 erf
-            < 5 5 5 5 5 5 5 5 ok.
-            > : one 1 . ;
-            < ok.
-            > : two 2 . ;
-            < ok.
-            > : six 6 . ;
-            < ok.
-            > : nif if one if two two else six then one then ;
-            < ok.
-            >   0 nif
-            < ok.
-            > 0 1 nif
-            < 1 6 1 ok.
-            > 1 1 nif
-            < 1 2 2 1 ok.
-            > 42 emit
-            < *ok.
-            > : star 42 emit ;
-            < ok.
-            > star star star
-            < ***ok.
-            > : sloop one 5 0 do star star loop six ;
-            < ok.
-            > sloop
-            < 1 **********6 ok.
-            > : count 10 0 do i . loop ;
-            < ok.
-            > count
-            < 0 1 2 3 4 5 6 7 8 9 ok.
-            > : smod 10 0 do i 3 mod not if star then loop ;
-            < ok.
-            > smod
-            < ****ok.
-            > : beep ." hello, world! " ;
-            < ok.
-            > beep
-            < hello, world! ok.
-            > constant x 123
-            < ok.
-            > x .
-            < 123 ok.
-            > 4 x + .
-            < 127 ok.
-            > variable y
-            < ok.
-            > y @ .
-            < 0 ok.
-            > 10 y !
+        blocking_runtest_with(&mut lbforth.forth, r#"
+            ( a definition can continue onto lines read from the source )
+            > : double
             < ok.
-            > y @ .
+
+            ( refill replaces the rest of the line with the next line )
+            > refill this is never interpreted
             < 10 ok.
-            > array z 4
-            < ok.
-            > z @ . z 1 w+ @ . z 2 w+ @ . z 3 w+ @ .
-            < 0 0 0 0 ok.
-            > 10 z ! 20 z 1 w+ ! 30 z 2 w+ ! 40 z 3 w+ !
-            < ok.
-            > z @ . z 1 w+ @ . z 2 w+ @ . z 3 w+ @ .
-            < 10 20 30 40 ok.
-            > forget z
-            < ok.
-            > variable a
-            < ok.
-            > 100 a !
-            < ok.
-            > array z 4
+            > .
+            < -1 ok.
+
+            > key emit key? .
+            < a-1 ok.
+
+            > array buf 4
             < ok.
-            > z @ . z 1 w+ @ . z 2 w+ @ . z 3 w+ @ .
-            < 0 0 0 0 ok.
-            "#
-        );
-        print_dict("forth1", &mut lbforth1.forth);
+            > buf 16 accept .
+            < 2 ok.
+            ( `+` drops a pointer's provenance, so read the byte from the cell )
+            > buf b@ emit buf @ 256 / 256 mod emit
+            < bcok.
 
-        // create a new forth VM, and deep copy the first VM's dictionary into
-        // the second.
-        let mut lbforth2 = lbforth1.fork_with_params(
+            ( the source is exhausted )
+            > refill .
+            < 0 ok.
+            x key
+            > 0 key? .
+            < 0 ok.
+
+            > source swap drop .
+            < 18 ok.
+        "#);
+    }
+
+    #[test]
+    fn fill_rejects_non_ascii() {
+        let mut buf = [0u8; 16];
+        let mut input = crate::input::WordStrBuf::new(buf.as_mut_ptr(), buf.len());
+        input.fill("abc def").unwrap();
+        input.advance();
+        assert_eq!(input.cur_word(), Some("abc"));
+
+        // A line that can't be used leaves the current one alone.
+        assert!(input.fill("d\u{e9}j\u{e0} vu").is_err());
+        assert!(input.fill("this line is too long").is_err());
+        input.advance();
+        assert_eq!(input.cur_word(), Some("def"));
+    }
+
+    #[test]
+    fn evaluate() {
+        let mut lbforth = LBForth::from_params(
             LBForthParams::default(),
             TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
         );
+        let forth = &mut lbforth.forth;
 
-        println!("\n --- testing second forth VM --- \n");
-        blocking_runtest_with(&mut lbforth2.forth, r#"
-            ( all the bindings in the old VM's dictionary should be present in the
-              new VM, and, it shouldn't segfault... :D )
-            > yay yay yay
-            < 5 5 5 ok.
-            > boop
-            < 5 5 ok.
-            > 0 err
-            < 5 5 ok.
-            > 1 err
-            < 5 5 5 5 5 5 ok.
-            > 0 erf
-            < 5 5 ok.
-            > 1 erf
-            < 5 5 5 5 5 5 5 5 ok.
-            >   0 nif
-            < ok.
-            > 0 1 nif
-            < 1 6 1 ok.
-            > 1 1 nif
-            < 1 2 2 1 ok.
-            > star star star
-            < ***ok.
-            > sloop
-            < 1 **********6 ok.
-            > count
-            < 0 1 2 3 4 5 6 7 8 9 ok.
-            > smod
-            < ****ok.
-            > beep
-            < hello, world! ok.
-            > x .
-            < 123 ok.
-            > 4 x + .
-            < 127 ok.
+        forth.evaluate(": sq dup * ;").unwrap();
+        assert!(forth.output.as_str().is_empty());
 
-            ( the existing `y` variable should have its second value from the
-              first test. )
-            > y @ .
-            < 10 ok.
+        // A builtin can evaluate Forth source, without clobbering the line
+        // that called it.
+        fn greet(forth: &mut Forth<TestContext>) -> Result<(), crate::Error> {
+            forth.evaluate("2 sq .")
+        }
+        forth.add_builtin("greet", greet).unwrap();
 
-            ( reassigning `y` is okay )
-            > 100 y !
-            < ok.
-            > y @ .
-            < 100 ok.
+        // Pushes the address and length of a string for `evaluate`.
+        fn s_lit(forth: &mut Forth<TestContext>) -> Result<(), crate::Error> {
+            let s = "10 20 + . s-refill evaluate";
+            forth.data_stack.push(Word::ptr(s.as_ptr().cast_mut()))?;
+            forth.data_stack.push(Word::data(s.len() as i32))?;
+            Ok(())
+        }
+        forth.add_builtin("s-lit", s_lit).unwrap();
+        fn s_refill(forth: &mut Forth<TestContext>) -> Result<(), crate::Error> {
+            let s = "REFILL .";
+            forth.data_stack.push(Word::ptr(s.as_ptr().cast_mut()))?;
+            forth.data_stack.push(Word::data(s.len() as i32))?;
+            Ok(())
+        }
+        forth.add_builtin("s-refill", s_refill).unwrap();
 
-            ( also reassign `a` )
-            > 500 a !
-            < ok.
+        blocking_runtest_with(forth, r#"
+            > 1 . greet 3 .
+            < 1 4 3 ok.
+            > : nested 5 sq . greet ;
+            < ok.
+            > nested nested
+            < 25 4 25 4 ok.
+
+            ( nested evaluates see their own string as the whole input )
+            > s-lit evaluate 7 .
+            < 30 0 7 ok.
+            x s-lit 0 evaluate undefined-word
+            > 6 .
+            < 6 ok.
+        "#);
 
-            > z @ . z 1 w+ @ . z 2 w+ @ . z 3 w+ @ .
-            < 0 0 0 0 ok.
+        // Errors at the top level clear the stacks, like `process_line`.
+        forth.data_stack.push(Word::data(1)).unwrap();
+        assert_eq!(forth.evaluate("2 nope"), Err(Error::LookupFailed));
+        assert!(forth.data_stack.is_empty());
+    }
 
-            ( define a new word in `forth2`. )
-            > : star3 star star star ;
-            < ok.
-            > star3
-            < ***ok.
+    #[test]
+    fn image() {
+        use crate::ImageError;
 
-            ( define a new variable in forth2 )
-            > variable foo
-            < ok.
-            > foo @ .
-            < 0 ok.
-            > 123 foo !
-            < ok.
-            > foo @ .
-            < 123 ok.
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        blocking_runtest_with(&mut lbforth.forth, r#"
+            > : sq dup * ;
+            > : quad sq sq ;
+            > : greet ." hi there " ;
+            > : count 0 do i . loop ;
+            > : abs2 dup 0< if negate else 1 + then ;
+            > constant ten 10
+            > variable v
+            > 42 v !
+            > array arr 3
+            > : foo 1 ;
+            > : bar foo ;
+            > : foo 2 ;
         "#);
-        print_dict("forth2", &mut lbforth2.forth);
+        let mut buf = [0u8; 1024];
+        let len = lbforth.forth.save_image(&mut buf).unwrap();
+        assert_eq!(
+            lbforth.forth.save_image(&mut [0; 64]),
+            Err(Error::Image(ImageError::BufferTooSmall)),
+        );
 
-        // check that forth1's bindings aren't clobbered
-        println!("\n --- retesting first VM's bindings --- \n");
-        blocking_runtest_with(&mut lbforth1.forth, r#"
-            ( the existing `y` variable should have its second value from the
-              first test. )
-            > y @ .
-            < 10 ok.
-            > a @ .
-            < 100 ok.
+        // Load the image into a fresh VM.
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        let forth = &mut lbforth.forth;
+        assert_eq!(
+            forth.load_image(&buf[..len - 1]),
+            Err(Error::Image(ImageError::Truncated)),
+        );
+        assert_eq!(forth.load_image(&buf[4..len]), Err(Error::Image(ImageError::BadMagic)));
+        forth.load_image(&buf[..len]).unwrap();
+        blocking_runtest_with(forth, r#"
+            > 3 quad .
+            < 81 ok.
+            > greet
+            < hi there ok.
+            > 3 count
+            < 0 1 2 ok.
+            > -4 abs2 . 4 abs2 .
+            < 4 5 ok.
+            > ten . v @ .
+            < 10 42 ok.
+            > 7 arr ! arr @ .
+            < 7 ok.
+            > bar . foo .
+            < 1 2 ok.
         "#);
-        // new words defined in forth2 don't exist in forth1
-        blocking_runtest_with(&mut lbforth1.forth, "x star3");
-        // and neither do new variables.
-        blocking_runtest_with(&mut lbforth1.forth, "x foo @ .");
 
-        // have forth1 change some of its bindings
-        blocking_runtest_with(&mut lbforth1.forth, r#"
-            ( change the value of `y` )
-            > 666 y !
-            < ok.
-            > y @ .
-            < 666 ok.
+        // Words in parent dictionaries are looked up again by name.
+        let mut child = lbforth.fork_with_params(LBForthParams::default(), TestContext::default());
+        blocking_runtest_with(&mut child.forth, "> : quad2 quad 2 * ;");
+        let len = child.forth.save_image(&mut buf).unwrap();
+        let mut child2 = lbforth.fork_with_params(LBForthParams::default(), TestContext::default());
+        child2.forth.load_image(&buf[..len]).unwrap();
+        blocking_runtest_with(&mut child2.forth, r#"
+            > 2 quad2 .
+            < 32 ok.
+        "#);
+
+        // ...so an image can't be loaded without them.
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        assert_eq!(
+            lbforth.forth.load_image(&buf[..len]),
+            Err(Error::Image(ImageError::MissingWord)),
+        );
+        blocking_runtest_with(&mut lbforth.forth, "x 2 quad2 .");
+
+        // A failed load doesn't leave behind the wordlists it reserved.
+        blocking_runtest_with(&mut lbforth.forth, r#"
+            > vocabulary v also v definitions
+            > : hi 42 ;
+        "#);
+        let len = lbforth.forth.save_image(&mut buf).unwrap();
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        assert_eq!(
+            lbforth.forth.load_image(&buf[..len - 1]),
+            Err(Error::Image(ImageError::Truncated)),
+        );
+        blocking_runtest_with(&mut lbforth.forth, r#"
+            > wordlist .
+            < 1 ok.
+        "#);
+    }
+
+    #[test]
+    fn fuel() {
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        let forth = &mut lbforth.forth;
+        blocking_runtest_with(forth, r#"
+            > : spin 0 do 100 0 do loop loop ;
+        "#);
+
+        // Resume with a little more fuel at a time, until the line is done.
+        forth.set_fuel(Some(100));
+        forth.input.fill("1 3 spin 2 . .").unwrap();
+        assert_eq!(forth.process_line(), Err(Error::OutOfFuel));
+        assert_eq!(forth.fuel(), Some(0));
+        let mut resumes = 0;
+        let res = loop {
+            forth.set_fuel(Some(100));
+            match forth.resume() {
+                Err(Error::OutOfFuel) => resumes += 1,
+                res => break res,
+            }
+        };
+        assert_eq!(res, Ok(()));
+        assert!(resumes >= 5);
+        assert_eq!(forth.output.as_str(), "2 1 ok.\n");
+        forth.output.clear();
+
+        // A new line abandons the old one.
+        forth.set_fuel(Some(10));
+        forth.input.fill("7 1000 spin").unwrap();
+        assert_eq!(forth.process_line(), Err(Error::OutOfFuel));
+        forth.set_fuel(None);
+        blocking_runtest_with(forth, r#"
+            > 1 2 + . .
+            < 3 7 ok.
+        "#);
+
+        // Nested input can't be resumed.
+        forth.set_fuel(Some(10));
+        assert_eq!(forth.evaluate("1000 spin"), Err(Error::OutOfFuelWhileNested));
+        assert!(forth.call_stack.is_empty());
+    }
+
+    #[test]
+    fn step() {
+        use crate::Step;
+
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        let forth = &mut lbforth.forth;
+        blocking_runtest_with(forth, r#"
+            > : spin 0 do 100 0 do loop loop ;
+        "#);
+
+        forth.input.fill("1 2 spin 2 . .").unwrap();
+        let mut steps = 1;
+        while forth.step().unwrap() == Step::NotDone {
+            assert!(!forth.output.as_str().ends_with("ok.\n"));
+            steps += 1;
+        }
+        assert!(steps > 200);
+        assert_eq!(forth.output.as_str(), "2 1 ok.\n");
+        forth.output.clear();
+
+        forth.input.fill("1 2 spin 2 . .").unwrap();
+        assert_eq!(forth.run_for(steps - 1), Ok(Step::NotDone));
+        assert_eq!(forth.run_for(1), Ok(Step::Done));
+        assert_eq!(forth.output.as_str(), "2 1 ok.\n");
+        forth.output.clear();
+
+        // Errors clear the stacks, like they do for `process_line`.
+        forth.input.fill("1 2 spin nope").unwrap();
+        assert_eq!(forth.run_for(usize::MAX), Err(Error::LookupFailed));
+        assert!(forth.data_stack.is_empty());
+        assert!(forth.call_stack.is_empty());
+    }
+
+    #[test]
+    fn scheduler() {
+        use crate::sched::{Scheduler, TaskHost, TaskId};
+
+        struct Host<'a> {
+            pool: Vec<&'a mut Forth<()>>,
+            finished: Vec<(TaskId, String, Result<(), Error>)>,
+        }
+
+        impl<'a> TaskHost<'a, ()> for Host<'a> {
+            fn spawn(&mut self, _: &mut Forth<()>) -> Result<&'a mut Forth<()>, Error> {
+                self.pool.pop().ok_or(Error::TooManyTasks)
+            }
+
+            fn finished(&mut self, id: TaskId, vm: &'a mut Forth<()>, res: Result<(), Error>) {
+                self.finished.push((id, vm.output.as_str().into(), res));
+                vm.output.clear();
+                self.pool.push(vm);
+            }
+        }
+
+        let mut parent = LBForth::from_params(
+            LBForthParams::default(),
+            (),
+            Forth::<()>::FULL_BUILTINS,
+        );
+        blocking_runtest_with(&mut parent.forth, r#"
+            > variable n
+            > : worker 3 0 do n @ 10 * task-id + n ! pause loop ;
+            > : forever 30000 0 do pause loop ;
+            > task-id .
+            < 0 ok.
+            > pause
+            < ok.
+            x ' worker spawn
+        "#);
+        assert_eq!(parent.forth.evaluate("1 kill"), Err(Error::NoScheduler));
+        let mut forks: Vec<_> = (0..6)
+            .map(|_| parent.fork_with_params(LBForthParams::default(), ()))
+            .collect();
+        let mut forks = forks.iter_mut().map(|lbforth| &mut lbforth.forth);
+        let mut fork = || forks.next().unwrap();
+        let (a, b) = (fork(), fork());
+        let host = Host {
+            pool: vec![fork(), fork()],
+            finished: Vec::new(),
+        };
+        let mut full = Scheduler::<_, _, 0>::new(Host { pool: vec![], finished: vec![] });
+        assert_eq!(full.add(fork()), Err(Error::TooManyTasks));
+        let mut sched = Scheduler::<_, _, 4>::new(host);
+
+        // Spawned workers take turns, as they `pause`.
+        a.input.fill("' worker spawn ' worker spawn task-id . . .").unwrap();
+        assert_eq!(sched.add(a), Ok(1));
+        sched.run();
+        assert!(sched.is_empty());
+        let finished = core::mem::take(&mut sched.host().finished);
+        assert_eq!(finished, [
+            (1, "1 3 2 ok.\n".into(), Ok(())),
+            (2, "ok.\n".into(), Ok(())),
+            (3, "ok.\n".into(), Ok(())),
+        ]);
+        let a = sched.host().pool.pop().unwrap();
+        blocking_runtest_with(a, r#"
+            > n @ .
+            < 232323 ok.
+        "#);
+
+        // Tasks can be killed by other tasks, or the host.
+        a.input.fill("' forever spawn ' forever spawn kill 1 .").unwrap();
+        b.input.fill("task-id kill 2 .").unwrap();
+        sched.set_time_slice(1);
+        let a_id = sched.add(a).unwrap();
+        assert_eq!(sched.add(b), Ok(a_id + 1));
+        while sched.host().finished.len() < 3 {
+            assert!(sched.run_slice());
+        }
+        let finished = core::mem::take(&mut sched.host().finished);
+        assert_eq!(finished, [
+            (a_id + 1, "".into(), Err(Error::TaskKilled)),
+            (a_id + 3, "".into(), Err(Error::TaskKilled)),
+            (a_id, "1 ok.\n".into(), Ok(())),
+        ]);
+        assert!(sched.task(a_id + 2).is_some());
+        assert!(sched.kill(a_id + 2));
+        assert!(!sched.kill(a_id + 2));
+        assert!(!sched.run_slice());
+
+        // A task that can't be started, because there's no room for it or
+        // the host can't make a VM for it, gets id 0, and the spawning task
+        // carries on.
+        let a = sched.host().pool.pop().unwrap();
+        let b = sched.host().pool.pop().unwrap();
+        a.input.fill("' worker spawn . 1 .").unwrap();
+        b.input.fill("' worker spawn . 2 .").unwrap();
+        let mut single = Scheduler::<_, _, 1>::new(Host { pool: vec![fork()], finished: vec![] });
+        assert_eq!(single.add(a), Ok(1));
+        single.run();
+        let mut no_vms = Scheduler::<_, _, 2>::new(Host { pool: vec![], finished: vec![] });
+        assert_eq!(no_vms.add(b), Ok(1));
+        no_vms.run();
+        assert_eq!(single.host().finished, [(1, "0 1 ok.\n".into(), Ok(()))]);
+        assert_eq!(no_vms.host().finished, [(1, "0 2 ok.\n".into(), Ok(()))]);
+    }
+
+    #[test]
+    fn channels() {
+        use crate::sched::{Scheduler, TaskHost, TaskId};
+
+        #[derive(Default)]
+        struct Host(Vec<(TaskId, String, Result<(), Error>)>);
+
+        impl<'a> TaskHost<'a, ()> for Host {
+            fn spawn(&mut self, _: &mut Forth<()>) -> Result<&'a mut Forth<()>, Error> {
+                Err(Error::TooManyTasks)
+            }
+
+            fn finished(&mut self, id: TaskId, vm: &'a mut Forth<()>, res: Result<(), Error>) {
+                self.0.push((id, vm.output.as_str().into(), res));
+            }
+        }
+
+        let mut parent = LBForth::from_params(
+            LBForthParams::default(),
+            (),
+            Forth::<()>::FULL_BUILTINS,
+        );
+        let forth = &mut parent.forth;
+        blocking_runtest_with(forth, r#"
+            > channel jobs 2
+            > 1 jobs send 2 jobs send
+            x 3 jobs send
+            > jobs try-recv . . jobs recv .
+            < -1 1 2 ok.
+            x jobs recv
+            > jobs try-recv .
+            < 0 ok.
+            > channel box 1
+        "#);
+
+        // Tasks wait for room in the channel, or for a value to arrive.
+        let mut fork = || parent.fork_with_params(LBForthParams::default(), ());
+        let (mut producer, mut consumer) = (fork(), fork());
+        let (producer, consumer) = (&mut producer.forth, &mut consumer.forth);
+        producer.input.fill("1 box send 2 box send 3 box send").unwrap();
+        consumer.input.fill("box recv box recv box recv + + .").unwrap();
+        let mut sched = Scheduler::<_, _, 2>::new(Host::default());
+        assert_eq!(sched.add(consumer), Ok(1));
+        assert_eq!(sched.add(producer), Ok(2));
+        sched.run();
+        let mut finished = core::mem::take(&mut sched.host().0);
+        finished.sort_by_key(|(id, ..)| *id);
+        assert_eq!(finished, [
+            (1, "6 ok.\n".into(), Ok(())),
+            (2, "ok.\n".into(), Ok(())),
+        ]);
+
+        // A sandboxed VM can't use anything else as a channel.
+        let mut sandboxed = fork();
+        let forth = &mut sandboxed.forth;
+        forth.set_sandboxed(true);
+        blocking_runtest_with(forth, r#"
+            > variable v
+            > 4 box send box try-recv . .
+            < -1 4 ok.
+            x 1 v send
+            x v try-recv
+        "#);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_channels() {
+        use crate::{
+            async_builtin,
+            dictionary::{AsyncBuiltinEntry, AsyncBuiltins},
+            fastr::FaStr,
+            leakbox::AsyncLBForth,
+        };
+        use core::pin::Pin;
+
+        #[derive(Clone)]
+        struct Dispatcher;
+
+        impl<'forth> AsyncBuiltins<'forth, ()> for Dispatcher {
+            type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + 'forth>>;
+
+            const BUILTINS: &'static [AsyncBuiltinEntry<()>] = &[
+                async_builtin!("send-async"),
+                async_builtin!("recv-async"),
+            ];
+
+            fn dispatch_async(&self, id: &FaStr, forth: &'forth mut Forth<()>) -> Self::Future {
+                match id.as_str() {
+                    "send-async" => Box::pin(forth.send_async()),
+                    "recv-async" => Box::pin(forth.recv_async()),
+                    id => panic!("Unknown async builtin {id}"),
+                }
+            }
+        }
+
+        let mut parent = AsyncLBForth::from_params(
+            LBForthParams::default(),
+            (),
+            Forth::<()>::FULL_BUILTINS,
+            Dispatcher,
+        );
+        parent.forth.input_mut().fill("channel box 1").unwrap();
+        futures::executor::block_on(parent.forth.process_line()).unwrap();
+        let mut producer = parent.fork_with_params(LBForthParams::default(), ());
+        let mut consumer = parent.fork_with_params(LBForthParams::default(), ());
+        producer.forth.input_mut().fill("1 box send-async 2 box send-async 3 box send-async").unwrap();
+        consumer.forth.input_mut().fill("box recv-async box recv-async box recv-async + + .").unwrap();
+        let (consumed, produced) = futures::executor::block_on(async {
+            futures::join!(consumer.forth.process_line(), producer.forth.process_line())
+        });
+        assert_eq!((consumed, produced), (Ok(()), Ok(())));
+        assert_eq!(consumer.forth.output().as_str(), "6 ok.\n");
+
+        // `send` is still the sync word, which fails outside of a task.
+        producer.forth.input_mut().fill("1 box send 2 box send").unwrap();
+        let res = futures::executor::block_on(producer.forth.process_line());
+        assert_eq!(res, Err(Error::ChannelFull));
+    }
+
+    #[test]
+    #[cfg(feature = "use-std")]
+    fn snapshot() {
+        use crate::{Frame, FrameTarget, ImageError, Mode, Snapshot};
+
+        fn snap(forth: &mut Forth<Vec<Snapshot>>) -> Result<(), Error> {
+            let snapshot = forth.snapshot()?;
+            forth.host_ctxt.push(snapshot);
+            Ok(())
+        }
+
+        let mut parent = LBForth::from_params(
+            LBForthParams::default(),
+            Vec::new(),
+            Forth::<Vec<Snapshot>>::FULL_BUILTINS,
+        );
+        parent.forth.add_builtin("snap", snap).unwrap();
+        let mut child = parent.fork_with_params(LBForthParams::default(), Vec::new());
+        blocking_runtest_with(&mut child.forth, r#"
+            > : sq dup * ;
+            > : foo 1 2 snap sq 3 ;
+            > foo 4 5
+            < ok.
+        "#);
+        let done = child.forth.snapshot().unwrap();
+        assert_eq!(child.forth.evaluate("snap"), Err(Error::SnapshotWhileNested));
+
+        // Snapshotted part way through `foo`, and through the line.
+        let mid = child.forth.host_ctxt.pop().unwrap();
+        assert_eq!(mid.data_stack, [1, 2]);
+        assert_eq!(mid.call_stack, [
+            Frame { target: FrameTarget::Entry(1), idx: 4, len: 7 },
+            Frame { target: FrameTarget::Parent("snap".into()), idx: 0, len: 0 },
+        ]);
+        assert_eq!(mid.mode, Mode::Run);
+        assert_eq!(&mid.input[..mid.input_cursor], b"foo");
+        assert_eq!(Snapshot::from_bytes(&mid.to_bytes()).as_ref(), Ok(&mid));
+        assert_eq!(
+            Snapshot::from_bytes(&mid.to_bytes()[1..]),
+            Err(ImageError::BadMagic),
+        );
+
+        let mut other = parent.fork_with_params(LBForthParams::default(), Vec::new());
+        other.forth.restore(&mid).unwrap();
+        assert_eq!(other.forth.snapshot().as_ref(), Ok(&mid));
+
+        // Carrying on runs `snap` again, finishes `foo`, then the rest of the
+        // line.
+        other.forth.resume().unwrap();
+        assert_eq!(other.forth.output.as_str(), "ok.\n");
+        assert_eq!(other.forth.host_ctxt.len(), 1);
+        let end = other.forth.snapshot().unwrap();
+        assert_eq!(end.data_stack, [1, 4, 3, 4, 5]);
+        assert!(end.call_stack.is_empty());
+        other.forth.output.clear();
+
+        // Restoring replaces whatever the VM had before.
+        blocking_runtest_with(&mut other.forth, "> : sq 0 ;");
+        other.forth.restore(&done).unwrap();
+        blocking_runtest_with(&mut other.forth, r#"
+            > . . . 3 sq .
+            < 5 4 3 9 ok.
+            > foo . . .
+            < 3 4 1 ok.
+        "#);
+
+        // The parent's builtins are needed to restore the snapshot.
+        let mut lone = LBForth::from_params(
+            LBForthParams::default(),
+            Vec::new(),
+            Forth::<Vec<Snapshot>>::FULL_BUILTINS,
+        );
+        assert_eq!(lone.forth.restore(&mid), Err(Error::Image(ImageError::MissingWord)));
+
+        // A snapshot that can't be restored leaves the VM as it was.
+        let mut bad = done.clone();
+        bad.call_stack.push(Frame { target: FrameTarget::Entry(99), idx: 0, len: 0 });
+        assert_eq!(other.forth.restore(&bad), Err(Error::Image(ImageError::Malformed)));
+        bad = done.clone();
+        bad.dictionary.truncate(bad.dictionary.len() - 1);
+        assert!(other.forth.restore(&bad).is_err());
+        blocking_runtest_with(&mut other.forth, r#"
+            > 3 sq .
+            < 9 ok.
+            > foo . . .
+            < 3 4 1 ok.
+        "#);
+
+        // The search order and current wordlist are restored too.
+        let mut voc = parent.fork_with_params(LBForthParams::default(), Vec::new());
+        blocking_runtest_with(&mut voc.forth, r#"
+            > vocabulary v also v definitions
+            > : hi 42 ;
+        "#);
+        let saved = voc.forth.snapshot().unwrap();
+        assert_eq!(saved.search_order, [1, 0]);
+        assert_eq!(saved.current, 1);
+        assert_eq!(Snapshot::from_bytes(&saved.to_bytes()).as_ref(), Ok(&saved));
+        let mut restored = parent.fork_with_params(LBForthParams::default(), Vec::new());
+        restored.forth.restore(&saved).unwrap();
+        blocking_runtest_with(&mut restored.forth, r#"
+            > hi .
+            < 42 ok.
+            > : bye 1 ;
+            > only
+            x hi
+            x bye
+            > also v bye . wordlist .
+            < 1 2 ok.
+        "#);
+    }
+
+    #[test]
+    fn call() {
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        let forth = &mut lbforth.forth;
+        use crate::{stack::StackError, word::FromWords};
+        blocking_runtest_with(forth, r#"
+            > : sq dup * ;
+            > : swap3 rot rot swap ;
+            > : neg? 0< ;
+        "#);
+
+        assert_eq!(forth.call::<i32>("sq", -7), Ok(49));
+        assert_eq!(forth.call::<u32>("+", (3u32, 4u32)), Ok(7));
+        assert_eq!(forth.call::<(i32, i32, i32)>("swap3", (1, 2, 3)), Ok((3, 2, 1)));
+        assert_eq!(forth.call::<bool>("neg?", -1), Ok(true));
+        assert_eq!(forth.call::<bool>("neg?", 1), Ok(false));
+        assert_eq!(forth.call::<i32>("and", (true, 5)), Ok(5));
+        assert_eq!(forth.call::<()>("drop", 1), Ok(()));
+        assert_eq!(forth.data_stack.depth(), 0);
+        #[cfg(feature = "floats")]
+        assert_eq!(forth.call::<f32>("f+", (1.5f32, 2.0f32)), Ok(3.5));
+
+        // Errors clear the stacks, just like `process_line`.
+        assert_eq!(forth.call::<i32>("nope", ()), Err(Error::LookupFailed));
+        assert_eq!(forth.call::<i32>("if", ()), Err(Error::LookupFailed));
+        assert_eq!(
+            forth.call::<(i32, i32)>("sq", 2),
+            Err(Error::Stack(StackError::StackEmpty)),
+        );
+        assert_eq!(forth.data_stack.depth(), 0);
+
+        // Builtins can call words too.
+        fn sq_plus_one(forth: &mut Forth<TestContext>) -> Result<(), Error> {
+            let n = i32::pop_words(&mut forth.data_stack)?;
+            let sq: i32 = forth.call("sq", n)?;
+            forth.data_stack.push(Word::data(sq + 1))?;
+            Ok(())
+        }
+        forth.add_builtin("sq+1", sq_plus_one).unwrap();
+        blocking_runtest_with(forth, r#"
+            > : twice sq+1 sq+1 ;
+            > 2 twice .
+            < 26 ok.
+        "#);
+    }
+
+    #[test]
+    fn closure_builtins() {
+        use core::sync::atomic::{AtomicI32, Ordering};
+        use crate::Builtin;
+
+        struct Adder(AtomicI32);
+
+        impl Builtin<TestContext> for Adder {
+            fn call(&self, forth: &mut Forth<TestContext>) -> Result<(), Error> {
+                let n = forth.data_stack.try_pop()?;
+                let total = self.0.fetch_add(unsafe { n.data }, Ordering::Relaxed);
+                forth.data_stack.push(Word::data(total))?;
+                Ok(())
+            }
+        }
+
+        static ADDER: Adder = Adder(AtomicI32::new(0));
+
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        let count = AtomicI32::new(10);
+        lbforth.forth.add_builtin("next", move |forth: &mut Forth<TestContext>| {
+            forth.data_stack.push(Word::data(count.fetch_add(1, Ordering::Relaxed)))?;
+            Ok(())
+        }).unwrap();
+        lbforth.forth.add_builtin("add", &ADDER as &dyn Builtin<TestContext>).unwrap();
+        blocking_runtest_with(&mut lbforth.forth, r#"
+            > next next . .
+            < 11 10 ok.
+            > : two-adds dup add drop add ;
+            > 5 two-adds .
+            < 5 ok.
+        "#);
+
+        // Forks share the builtins, and so their state.
+        let mut child = lbforth.fork_with_params(LBForthParams::default(), TestContext::default());
+        blocking_runtest_with(&mut child.forth, r#"
+            > next . 1 two-adds .
+            < 12 11 ok.
+        "#);
+        blocking_runtest_with(&mut lbforth.forth, r#"
+            > next . 0 add .
+            < 13 12 ok.
+        "#);
+    }
+
+    #[cfg(feature = "macros")]
+    mod macro_builtins {
+        use super::TestContext;
+        use crate::Error;
+
+        #[forth3::macros::builtin(name = "clamp")]
+        pub fn clamp(v: i32, lo: i32, hi: i32) -> i32 {
+            v.max(lo).min(hi)
+        }
+
+        #[forth3::macros::builtin(name = "u/mod")]
+        pub fn u_div_mod(a: u32, b: u32) -> Result<(u32, u32), Error> {
+            match b {
+                0 => Err(Error::DivideByZero),
+                b => Ok((a % b, a / b)),
+            }
+        }
+
+        #[forth3::macros::builtin(ctx, name = "remember")]
+        pub fn remember(ctx: &mut TestContext, val: i32, twice: bool) {
+            ctx.contents.push(val);
+            if twice {
+                ctx.contents.push(val);
+            }
+        }
+
+        #[forth3::macros::builtin(crate = crate)]
+        pub fn forty_two() -> i32 {
+            42
+        }
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn builtin_macro() {
+        use self::macro_builtins::*;
+        use crate::dictionary::BuiltinEntry;
+
+        const BUILTINS: &[BuiltinEntry<TestContext>] = &[
+            clamp::entry(),
+            u_div_mod::entry(),
+            remember::entry(),
+            forty_two::entry(),
+        ];
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            BUILTINS,
+        );
+        let forth = &mut lbforth.forth;
+        assert_eq!(forth.call::<i32>("clamp", (15, 0, 10)), Ok(10));
+        assert_eq!(forth.call::<i32>("clamp", (-5, 0, 10)), Ok(0));
+        assert_eq!(forth.call::<(u32, u32)>("u/mod", (7u32, 2u32)), Ok((1, 3)));
+        assert_eq!(forth.call::<(u32, u32)>("u/mod", (7u32, 0u32)), Err(Error::DivideByZero));
+        assert_eq!(forth.call::<()>("remember", (5, true)), Ok(()));
+        assert_eq!(forth.call::<()>("remember", (6, false)), Ok(()));
+        assert_eq!(forth.host_ctxt.contents, [5, 5, 6]);
+        assert_eq!(forth.call::<i32>("forty_two", ()), Ok(42));
+        assert_eq!(forth.data_stack.depth(), 0);
+
+        // The functions are `WordFunc`s, which can also be added at runtime.
+        forth.add_builtin("clamp2", clamp).unwrap();
+        assert_eq!(forth.call::<i32>("clamp2", (3, 4, 10)), Ok(4));
+    }
+
+    #[test]
+    fn wordlists() {
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        blocking_runtest_with(&mut lbforth.forth, r#"
+            > vocabulary greek
+            > vocabulary latin
+            > also greek definitions
+            > : hello 1 ;
+            > : shared 10 ;
+            > previous also latin definitions
+            > : hello 2 ;
+            > forth definitions
+            > : hello 3 ;
+            > hello .
+            < 3 ok.
+            > only also greek hello . shared .
+            < 1 10 ok.
+            > latin hello .
+            < 2 ok.
+            x shared
+            > get-order . . .
+            < 2 2 0 ok.
+            > previous
+            x previous
+            > forth-wordlist 1 set-order hello .
+            < 3 ok.
+            > get-current . wordlist .
+            < 0 3 ok.
+            x 7 set-current
+        "#);
+
+        let forth = &mut lbforth.forth;
+        let search = |forth: &mut Forth<TestContext>, name: &'static str, wid: i32| {
+            let addr = Word::ptr(name.as_ptr().cast_mut());
+            forth.call::<i32>("search-wordlist", (addr, name.len() as i32, wid))?;
+            forth.data_stack.try_pop().map_err(Error::from)
+        };
+        let xt = search(forth, "hello", 2).unwrap();
+        assert_eq!(forth.call::<i32>("execute", xt), Ok(2));
+        assert!(search(forth, "dup", 0).is_ok());
+        for (name, wid) in [("shared", 2), ("dup", 1)] {
+            let addr = Word::ptr(name.as_ptr().cast_mut());
+            let found = forth.call::<i32>("search-wordlist", (addr, name.len() as i32, wid));
+            assert_eq!(found, Ok(0));
+        }
+
+        // Forks start with the same search order and current wordlist.
+        blocking_runtest_with(forth, "> greek definitions : bye 4 ;");
+        let mut child = lbforth.fork_with_params(LBForthParams::default(), TestContext::default());
+        blocking_runtest_with(&mut child.forth, r#"
+            > hello bye . .
+            < 4 1 ok.
+            > : hi 5 ; forth
+            x hi
+            > get-current .
+            < 1 ok.
+        "#);
+
+        // Wordlists survive being saved as an image.
+        let new_vm = || LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        let mut saver = new_vm();
+        blocking_runtest_with(&mut saver.forth, "> vocabulary v also v definitions : w 6 ;");
+        let mut buf = [0u8; 256];
+        let len = saver.forth.save_image(&mut buf).unwrap();
+        let mut loader = new_vm();
+        loader.forth.load_image(&buf[..len]).unwrap();
+        blocking_runtest_with(&mut loader.forth, r#"
+            x w
+            > v w . wordlist .
+            < 6 2 ok.
+        "#);
+    }
+
+    #[test]
+    fn sandbox() {
+        use core::ptr::NonNull;
+
+        // A buffer outside the dictionary, which builtins must be `Send` and
+        // `Sync` to point to.
+        let mut storage = [0usize; 2];
+        #[derive(Clone, Copy)]
+        struct Buf(NonNull<usize>);
+        unsafe impl Send for Buf {}
+        unsafe impl Sync for Buf {}
+        impl Buf {
+            fn ptr(self) -> *mut usize {
+                self.0.as_ptr()
+            }
+        }
+
+        let buf = Buf(NonNull::from(&mut storage).cast());
+
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        let forth = &mut lbforth.forth;
+        forth
+            .add_builtin("buf", move |forth: &mut Forth<TestContext>| {
+                forth.data_stack.push(Word::ptr(buf.ptr()))?;
+                Ok(())
+            })
+            .unwrap();
+        blocking_runtest_with(&mut *forth, r#"
+            > variable x
+            > 5 buf !
+            > buf @ .
+            < 5 ok.
+        "#);
+
+        forth.set_sandboxed(true);
+        assert!(forth.is_sandboxed());
+        blocking_runtest_with(&mut *forth, r#"
+            > 7 x ! x @ . 65 x b! x b@ .
+            < 7 65 ok.
+            > : sq dup * ; 3 ' sq execute . 4 ' dup execute * .
+            < 9 16 ok.
+            x 0 @
+            x 1 0 !
+            x 0 b@
+            x buf @
+            x 1 buf !
+            x 0 execute
+            x x execute
+            x x 1 w+ execute
+        "#);
+
+        // Only the values of variables and arrays can be written, rather than
+        // the headers and code of dictionary entries.
+        blocking_runtest_with(&mut *forth, r#"
+            > array a 2 8 a 1 w+ ! a 1 w+ @ . x @ .
+            < 8 65 ok.
+            x 1 x -1 w+ !
+            x 1 x -3 w+ !
+            x 1 a 2 w+ !
+            x 1 ' sq !
+            x 1 ' sq b!
+        "#);
+        let fetched = forth.call::<i32>("@", Word::ptr(buf.ptr()));
+        assert_eq!(fetched, Err(Error::AccessViolation));
+
+        // Granted regions can be read and written...
+        let region = NonNull::slice_from_raw_parts(buf.0.cast::<u8>(), 16);
+        unsafe { forth.grant_region(region).unwrap() };
+        blocking_runtest_with(&mut *forth, r#"
+            > buf @ . 6 buf 1 w+ ! buf 1 w+ @ .
+            < 5 6 ok.
+            x buf 2 w+ @
+        "#);
+
+        // ...including by forks, which are sandboxed too.
+        let mut child = lbforth.fork_with_params(LBForthParams::default(), TestContext::default());
+        assert!(child.forth.is_sandboxed());
+        blocking_runtest_with(&mut child.forth, r#"
+            > x @ . buf @ . 3 sq .
+            < 65 5 9 ok.
+            x 0 @
+        "#);
+
+        let forth = &mut lbforth.forth;
+        forth.revoke_regions();
+        blocking_runtest_with(&mut *forth, "x buf @");
+        forth.set_sandboxed(false);
+        blocking_runtest_with(&mut *forth, r#"
+            > buf @ .
+            < 5 ok.
+        "#);
+    }
+
+    #[test]
+    fn memory_stats() {
+        use crate::{dictionary::BumpError, DictStats, StackStats};
+
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        let forth = &mut lbforth.forth;
+        blocking_runtest_with(&mut *forth, r#"
+            > 1 2 3 drop drop drop 4
+        "#);
+        let stats = forth.memory_stats();
+        let data_stack = StackStats { depth: 1, high_water: 3, capacity: 256 };
+        assert_eq!(stats.data_stack, data_stack);
+        assert_eq!(stats.dictionary.capacity, 4096);
+        assert_eq!(stats.dictionary.quota, None);
+        forth.data_stack.reset_high_water();
+        assert_eq!(forth.memory_stats().data_stack.high_water, 1);
+
+        // Allocating past the quota fails, even though there's still room.
+        let quota = stats.dictionary.used + 256;
+        forth.set_dict_quota(Some(quota));
+        let res = (0..).find_map(|i| {
+            forth.input.fill(&std::format!(": w{i} {i} ;")).unwrap();
+            forth.process_line().err()
+        });
+        assert_eq!(res, Some(Error::Bump(BumpError::OutOfMemory)));
+        let used = forth.dict_stats().next().unwrap().used;
+        assert!(used <= quota);
+        forth.output.clear();
+        forth.input.fill("free").unwrap();
+        forth.process_line().unwrap();
+        let free = std::format!("{}/{quota} bytes free ({used} used)\nok.\n", quota - used);
+        assert_eq!(forth.output.as_str(), free);
+
+        // Forks keep the quota, and report each level of the parent chain.
+        let mut child = lbforth.fork_with_params(LBForthParams::default(), TestContext::default());
+        assert_eq!(child.forth.dict_quota(), Some(quota));
+        assert_eq!(lbforth.forth.dict_quota(), Some(quota));
+        let frozen = DictStats { used, capacity: 4096, quota: Some(quota) };
+        for forth in [&child.forth, &lbforth.forth] {
+            let levels: std::vec::Vec<_> = forth.dict_stats().collect();
+            assert_eq!(levels, [DictStats { used: 0, ..frozen }, frozen]);
+        }
+        child.forth.set_dict_quota(None);
+        blocking_runtest_with(&mut child.forth, r#"
+            > : more 1 ;
+        "#);
+        assert_eq!(child.forth.memory_stats().dictionary.quota, None);
+    }
+
+    #[test]
+    fn stack_sizing() {
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        blocking_runtest_with(&mut lbforth.forth, r#"
+            > : deep 1 2 3 d>r d>r d>r r>d r>d r>d ;
+            > deep .stats
+            < data stack: 3/256 (max 3)
+            < return stack: 0/256 (max 3)
+            < call stack: 1/256 (max 2)
+            < ok.
+        "#);
+        let forth = &mut lbforth.forth;
+        forth.reset_stack_stats();
+        blocking_runtest_with(&mut *forth, r#"
+            > drop .stats
+            < data stack: 2/256 (max 3)
+            < return stack: 0/256 (max 0)
+            < call stack: 1/256 (max 1)
+            < ok.
+        "#);
+
+        let test = r#"
+            > : sq dup * ;
+            > 1 2 3 sq . . .
+            < 9 2 1 ok.
+            x nope
+        "#;
+        let sizes = sizing_runtest(test);
+        let expected = LBForthParams {
+            data_stack_elems: 4,
+            return_stack_elems: 0,
+            control_stack_elems: 2,
+            input_buf_elems: 14,
+            output_buf_elems: 10,
+            dict_buf_elems: sizes.dict_buf_elems,
+        };
+        assert_eq!(sizes, expected);
+
+        // The test passes with those sizes, but not with any less room in
+        // the dictionary.
+        let run_with = |params| {
+            let mut lbforth = LBForth::from_params(params, (), Forth::FULL_BUILTINS);
+            blocking_runtest_with(&mut lbforth.forth, test);
+        };
+        run_with(sizes);
+        let smaller = LBForthParams { dict_buf_elems: sizes.dict_buf_elems - 1, ..sizes };
+        assert!(std::panic::catch_unwind(|| run_with(smaller)).is_err());
+    }
+
+    #[test]
+    fn see() {
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        blocking_runtest_with(&mut lbforth.forth, r#"
+            > : sq dup * ;
+            > see sq
+            < : sq dup * ;
+            < ok.
+            > : sign dup 0 < if drop -1 else 0 > if 1 else 0 then then ;
+            > see sign
+            < : sign dup 0 < if drop -1 else 0 > if 1 else 0 then then ;
+            < ok.
+            > : stars 0 do 42 emit loop ." done" ;
+            > see stars
+            < : stars 0 do 42 emit loop ." done" ;
+            < ok.
+            > : nested 3 0 do 2 0 do i j + . loop loop ;
+            > see nested
+            < : nested 3 0 do 2 0 do i j + . loop loop ;
+            < ok.
+            > : odd if 1 if 2 else then then ( a comment ) 2d>2r ;
+            > see odd
+            < : odd if 1 if 2 else then then 2d>2r ;
+            < ok.
+            > see dup
+            < dup is a builtin
+            < ok.
+            > constant seven 7 variable v 3 v ! array a 4
+            > see seven see v see a
+            < constant seven 7
+            < variable v ( 3 )
+            < array a 4
+            < ok.
+            > vocabulary voc channel chan 5
+            > see voc see chan
+            < vocabulary voc
+            < channel chan 5
+            < ok.
+            x see nope
+            x see
+        "#);
+
+        // Words from a parent dictionary can be decompiled too, and so can
+        // calls to them.
+        let mut child = lbforth.fork_with_params(LBForthParams::default(), TestContext::default());
+        blocking_runtest_with(&mut child.forth, r#"
+            > : sq4 sq sq ;
+            > see sq4 see sq
+            < : sq4 sq sq ;
+            < : sq dup * ;
+            < ok.
+        "#);
+    }
+
+    #[test]
+    fn builtin_sets() {
+        use crate::{
+            builtin_set::BuiltinSet,
+            dictionary::{BuiltinEntry, EntryHeader, EntryKind},
+            fastr::comptime_fastr,
+            prefixed_builtins, WordFunc,
+        };
+
+        const fn entry(
+            name: &'static str,
+            func: WordFunc<TestContext>,
+        ) -> BuiltinEntry<TestContext> {
+            BuiltinEntry {
+                hdr: EntryHeader {
+                    name: comptime_fastr(name),
+                    kind: EntryKind::StaticBuiltin,
+                    len: 0,
+                    _pd: core::marker::PhantomData,
+                },
+                func,
+            }
+        }
+
+        fn sq(forth: &mut Forth<TestContext>) -> Result<(), Error> {
+            let n = forth.data_stack.try_pop()?;
+            forth.data_stack.push(Word::data(unsafe { n.data * n.data }))?;
+            Ok(())
+        }
+
+        fn dup(forth: &mut Forth<TestContext>) -> Result<(), Error> {
+            forth.data_stack.push(Word::data(-1))?;
+            Ok(())
+        }
+
+        const MINE: &[BuiltinEntry<TestContext>] = &[entry("sq", sq), entry("dup", dup)];
+        const SET: BuiltinSet<TestContext, 256> = BuiltinSet::new()
+            .with(Forth::FULL_BUILTINS)
+            .with(prefixed_builtins!("my:", MINE))
+            .without("forget")
+            .without("execute");
+        assert_eq!(SET.len(), Forth::<TestContext>::FULL_BUILTINS.len());
+
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            SET.as_slice(),
+        );
+        blocking_runtest_with(&mut lbforth.forth, r#"
+            > 3 my:sq 2 dup my:dup . . . .
+            < -1 2 2 9 ok.
+            x sq
+            x : foo ; forget foo
+            x ' foo execute
+        "#);
+    }
+
+    #[test]
+    fn buffers() {
+        use crate::buffers::ForthBuffers;
+
+        // The buffers are freed once the VM is done with them, rather than
+        // being leaked.
+        type Buffers = ForthBuffers<TestContext, 4, 8, 8, 256, 64, 512>;
+        let buffers = Box::into_raw(Box::new(Buffers::new()));
+        let mut forth = Forth::from_buffers(
+            unsafe { &mut *buffers },
+            TestContext::default(),
+            Forth::FULL_BUILTINS,
+        ).unwrap();
+        blocking_runtest_with(&mut forth, r#"
+            > : sq dup * ;
+            > variable v
+            > 3 sq v ! v @ .
+            < 9 ok.
+            x 1 2 3 4 5
+            x : too-big 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47 48 49 50 ;
+            > 4 sq .
+            < 16 ok.
+        "#);
+        drop(forth);
+        drop(unsafe { Box::from_raw(buffers) });
+
+        #[cfg(feature = "async")]
+        {
+            use crate::testutil::async_blockon_runtest_with;
+
+            type Buffers = ForthBuffers<TestContext, 16, 16, 16, 64, 64, 512>;
+            let buffers = Box::into_raw(Box::new(Buffers::new()));
+            let mut forth = crate::AsyncForth::from_buffers(
+                unsafe { &mut *buffers },
+                TestContext::default(),
+                Forth::FULL_BUILTINS,
+                TestAsyncDispatcher,
+            ).unwrap();
+            async_blockon_runtest_with(&mut forth, r#"
+                > : counted counter 1 + ;
+                > 3 counted .
+                < 4 ok.
+            "#);
+            drop(forth);
+            drop(unsafe { Box::from_raw(buffers) });
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "relocatable")]
+    fn relocatable() {
+        use crate::dictionary::DictContents;
+
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        blocking_runtest_with(&mut lbforth.forth, r#"
+            > : sq dup * ;
+            > : quad sq sq ;
+            > : greet ." hi there " ;
+            > : count 0 do i . loop ;
+            > : abs2 dup 0< if negate else 1 + then ;
+            > constant ten 10
+            > variable v
+            > 42 v !
+        "#);
+
+        // Persist the dictionary, then trash the original.
+        let contents = lbforth.forth.dict.contents();
+        let bytes = contents.bytes.to_vec();
+        let tail = contents.tail;
+        let alloc = &lbforth.forth.dict.alloc;
+        unsafe { alloc.start.write_bytes(0xAA, alloc.used()) };
+        drop(lbforth);
+
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        let forth = &mut lbforth.forth;
+        unsafe { forth.dict.load_contents(DictContents { bytes: &bytes, tail }) }.unwrap();
+        blocking_runtest_with(forth, r#"
+            > 3 quad .
+            < 81 ok.
+            > greet
+            < hi there ok.
+            > 3 count
+            < 0 1 2 ok.
+            > -4 abs2 . 4 abs2 .
+            < 4 5 ok.
+            > ten . v @ .
+            < 10 42 ok.
+            > : quad2 quad 2 * ;
+            > 2 quad2 .
+            < 32 ok.
+            > forget quad2
+            > 2 quad .
+            < 16 ok.
+        "#);
+    }
+
+    // The source of `static_dict::TEST_DICT`.
+    #[cfg(all(feature = "use-std", target_pointer_width = "64"))]
+    const STATIC_DICT_SRC: &str = r#"
+        : sq dup * ;
+        : quad sq sq ;
+        : greet ." hi there " ;
+        : count 0 do i . loop ;
+        : abs2 dup 0< if negate else ." a long string here " 1 + then ;
+        constant ten 10
+    "#;
+
+    #[cfg(all(feature = "use-std", target_pointer_width = "64"))]
+    mod static_dict {
+        include!("testutil/static_dict.rs");
+    }
+
+    #[test]
+    #[cfg(all(feature = "use-std", target_pointer_width = "64"))]
+    fn static_dict() {
+        use crate::rom::{generate, StaticDictParams};
+
+        let params = StaticDictParams {
+            name: "TEST_DICT",
+            builtins: "crate::Forth::<()>::FULL_BUILTINS",
+            word_size: 8,
+            crate_path: "crate",
+            ..Default::default()
+        };
+        // Make sure the generated dictionary is up to date.
+        let code = generate(STATIC_DICT_SRC, &params).unwrap();
+        assert_eq!(code, include_str!("testutil/static_dict.rs"));
+
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            (),
+            Forth::<()>::FULL_BUILTINS,
+        );
+        lbforth.set_static_parent(&static_dict::TEST_DICT);
+        let forth = &mut lbforth.forth;
+        blocking_runtest_with(forth, r#"
+            > 3 quad .
+            < 81 ok.
+            > greet
+            < hi there ok.
+            > 3 count
+            < 0 1 2 ok.
+            > -4 abs2 . 4 abs2 .
+            < 4 a long string here 5 ok.
+            > ten .
+            < 10 ok.
+        "#);
+        // Running words from a static dictionary doesn't take up any RAM.
+        assert_eq!(0, forth.dict.alloc.used());
+        blocking_runtest_with(forth, r#"
+            > : quad2 quad 2 * ;
+            > 2 quad2 .
+            < 32 ok.
+        "#);
+
+        // Variables can't be put in a static dictionary, and nor can anything
+        // else that isn't a `:` definition or a constant.
+        assert_eq!(generate("variable v", &params), Err(Error::VariableInStaticDict));
+        assert_eq!(generate("array a 4", &params), Err(Error::VariableInStaticDict));
+        assert_eq!(generate("vocabulary v", &params), Err(Error::WordlistInStaticDict));
+
+        // Builtins the source calls are looked up by name, even if the VM
+        // compiling the source doesn't have them.
+        let extra = StaticDictParams { extra_builtins: &["squirrel"], ..params };
+        let code = generate(": tens 10 squirrel ;", &extra).unwrap();
+        assert!(code.contains(r#"rom::builtin(BUILTINS, "squirrel")"#));
+
+        // Strings take up more words on targets with smaller words, so jumps
+        // over them are moved.
+        let small = StaticDictParams { word_size: 4, ..extra };
+        let code = generate(STATIC_DICT_SRC, &small).unwrap();
+        assert!(code.contains("size_of::<Word>() == 4"));
+        assert!(code.contains("\"(jmp)\"),\n        Word::data(10),"));
+    }
+
+    #[test]
+    fn blocks() {
+        use crate::block::{BlockDevice, BlockError, MemBlockDevice, BLOCK_LINE_LEN, BLOCK_SIZE};
+
+        let mut storage = vec![b' '; 4 * BLOCK_SIZE];
+        let src = [": sq dup * ;", "7 sq .", ": cube dup", "  sq * ;"];
+        for (i, line) in src.iter().enumerate() {
+            let start = i * BLOCK_LINE_LEN;
+            storage[start..start + line.len()].copy_from_slice(line.as_bytes());
+        }
+        let mut device = MemBlockDevice::new(storage.as_mut_ptr(), storage.len());
+
+        let mut lbforth = LBForth::from_params(
+            LBForthParams {
+                output_buf_elems: 2048,
+                ..Default::default()
+            },
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        let forth = &mut lbforth.forth;
+        assert_eq!(forth.evaluate("0 block"), Err(Error::NoBlockDevice));
+        let buffers = forth.alloc_block_buffers(1).unwrap();
+        let device = core::ptr::NonNull::from(&mut device as &mut dyn BlockDevice);
+        unsafe { forth.set_block_device(device, buffers).unwrap() };
+
+        blocks_ui(forth);
+        forth.remove_block_device().unwrap();
+
+        // Modified blocks are written back to the device when their buffer is
+        // reused.
+        assert_eq!(storage[BLOCK_SIZE], b'h');
+        assert_eq!(storage[2 * BLOCK_SIZE], b' ');
+        assert_eq!(storage[3 * BLOCK_SIZE], b'!');
+
+        // Forgetting a word defined before the buffers reuses their memory, so
+        // the device is saved and detached first.
+        forth.evaluate(": scratch ;").unwrap();
+        let buffers = forth.alloc_block_buffers(1).unwrap();
+        unsafe { forth.set_block_device(device, buffers).unwrap() };
+        blocking_runtest_with(&mut *forth, r#"
+            > 105 2 block b! update
+            > forget scratch
+            x 1 block
+            > : again 3 ;
+        "#);
+        assert_eq!(storage[2 * BLOCK_SIZE], b'i');
+
+        // The same again with buffers outside of the dictionary.
+        lbforth
+            .set_block_device(MemBlockDevice::new(storage.as_mut_ptr(), storage.len()), 2)
+            .unwrap();
+        blocks_ui(&mut lbforth.forth);
+
+        // A sandboxed VM can use its block buffers, but not what's around them.
+        lbforth.forth.set_sandboxed(true);
+        blocking_runtest_with(&mut lbforth.forth, r#"
+            > 66 1 buffer b! update 1 block b@ .
+            < 66 ok.
+            x 1 1 block 1024 + b!
+            > flush
+        "#);
+        lbforth.forth.set_sandboxed(false);
+        lbforth.forth.remove_block_device().unwrap();
+        assert_eq!(storage[BLOCK_SIZE], b'B');
+
+        fn blocks_ui(forth: &mut Forth<TestContext>) {
+            blocking_runtest_with(forth, r#"
+                x update
+                > 0 load
+                < 49 ok.
+                > 3 cube .
+                < 27 ok.
+                > 104 1 block b! update
+                < ok.
+                ( not updated, so never written back )
+                > 120 2 block b!
+                < ok.
+                > 33 3 buffer b! update flush
+                < ok.
+                x 4 block
+                x -1 block
+            "#);
+            forth.evaluate("0 list").unwrap();
+            assert!(forth.output.as_str().starts_with("Block 0\n 0 : sq dup * ;\n 1 7 sq .\n"));
+            assert!(forth.output.as_str().ends_with("15 \n"));
+            forth.output.clear();
+        }
+        assert_eq!(
+            MemBlockDevice::new(storage.as_mut_ptr(), storage.len())
+                .read_block(4, &mut [0; BLOCK_SIZE]),
+            Err(BlockError::OutOfRange),
+        );
+    }
+
+    #[cfg(feature = "use-std")]
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn file_block_device() {
+        use crate::block::{BlockDevice, FileBlockDevice, BLOCK_SIZE};
+
+        let path = std::env::temp_dir().join(format!("forth3-blocks-{}", std::process::id()));
+        let file = std::fs::File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        let mut device = FileBlockDevice::new(file);
+        let mut buf = [b'a'; BLOCK_SIZE];
+        device.write_block(2, &buf).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 3 * BLOCK_SIZE as u64);
+
+        // Blocks past the end of the file read as spaces.
+        device.read_block(3, &mut buf).unwrap();
+        assert_eq!(buf, [b' '; BLOCK_SIZE]);
+        device.read_block(2, &mut buf).unwrap();
+        assert_eq!(buf, [b'a'; BLOCK_SIZE]);
+
+        drop(device);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "use-std")]
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn include() {
+        let dir = std::env::temp_dir().join(format!("forth3-include-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.fs");
+        std::fs::write(&lib, ": double\n  2 * ;\n42 .\n").unwrap();
+        let bad = dir.join("bad.fs");
+        std::fs::write(&bad, "1 .\n: broken\n  nope ;\n").unwrap();
+
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        let forth = &mut lbforth.forth;
+
+        // Forth code can only include files if file access is enabled, but
+        // the host can always include them.
+        let lib_str = lib.to_str().unwrap();
+        forth.data_stack.push(Word::ptr(lib_str.as_ptr().cast_mut())).unwrap();
+        forth.data_stack.push(Word::data(lib_str.len() as i32)).unwrap();
+        assert_eq!(forth.evaluate("included"), Err(Error::FileAccessDisabled));
+        assert_eq!(forth.evaluate("include lib.fs"), Err(Error::FileAccessDisabled));
+        assert_eq!(forth.output.as_str(), "");
+        forth.data_stack.clear();
+        forth.set_file_access(true);
+
+        forth.include_file(&lib).unwrap();
+        assert_eq!(forth.output.as_str(), "42 ");
+        forth.output.clear();
+
+        // `required` skips files that have already been included, but
+        // `included` doesn't.
+        for (word, output) in [("required", ""), ("included", "42 ")] {
+            forth.data_stack.push(Word::ptr(lib_str.as_ptr().cast_mut())).unwrap();
+            forth.data_stack.push(Word::data(lib_str.len() as i32)).unwrap();
+            forth.evaluate(word).unwrap();
+            assert_eq!(forth.output.as_str(), output);
+            forth.output.clear();
+        }
+
+        blocking_runtest_with(forth, r#"
+            > 3 double .
+            < 6 ok.
+            x include
+            x include no-such-file.fs
+        "#);
+
+        // Errors say where in the file they happened.
+        assert_eq!(
+            forth.include_file(&bad),
+            Err(Error::InFile {
+                path: bad.clone(),
+                line: 3,
+                error: std::boxed::Box::new(Error::LookupFailed),
+            }),
+        );
+        assert_eq!(forth.output.as_str(), "1 ");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "use-std")]
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn file_access() {
+        let path = std::env::temp_dir().join(format!("forth3-file-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut buf = [0u8; 16];
+
+        let mut lbforth = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+        let forth = &mut lbforth.forth;
+        fn push_bytes(forth: &mut Forth<TestContext>, bytes: &[u8]) {
+            forth.data_stack.push(Word::ptr(bytes.as_ptr().cast_mut())).unwrap();
+            forth.data_stack.push(Word::data(bytes.len() as i32)).unwrap();
+        }
+        fn push_buf(forth: &mut Forth<TestContext>, buf: &mut [u8]) {
+            forth.data_stack.push(Word::ptr(buf.as_mut_ptr())).unwrap();
+            forth.data_stack.push(Word::data(buf.len() as i32)).unwrap();
+        }
+        fn eval(forth: &mut Forth<TestContext>, src: &str) -> std::string::String {
+            forth.evaluate(src).unwrap();
+            let output = forth.output.as_str().into();
+            forth.output.clear();
+            output
+        }
+
+        // File access must be enabled by the host.
+        push_bytes(forth, path.as_bytes());
+        assert_eq!(forth.evaluate("r/o open-file"), Err(Error::FileAccessDisabled));
+        forth.set_file_access(true);
+
+        push_bytes(forth, path.as_bytes());
+        assert_eq!(eval(forth, "w/o create-file . variable wfid wfid !"), "0 ");
+        push_bytes(forth, b"hello");
+        assert_eq!(eval(forth, "wfid @ write-line ."), "0 ");
+        push_bytes(forth, b"world");
+        assert_eq!(eval(forth, "wfid @ write-file ."), "0 ");
+        assert_eq!(eval(forth, "wfid @ file-size . . ."), "0 0 11 ");
+        assert_eq!(eval(forth, "wfid @ close-file ."), "0 ");
+
+        push_bytes(forth, path.as_bytes());
+        assert_eq!(eval(forth, "r/o bin open-file . variable rfid rfid !"), "0 ");
+        for (line, output) in [(&b"hello"[..], "0 -1 5 "), (b"world", "0 -1 5 "), (b"", "0 0 0 ")] {
+            push_buf(forth, &mut buf);
+            assert_eq!(eval(forth, "rfid @ read-line . . ."), output);
+            assert_eq!(&buf[..line.len()], line);
+        }
+        assert_eq!(eval(forth, "rfid @ close-file ."), "0 ");
+
+        push_bytes(forth, path.as_bytes());
+        assert_eq!(eval(forth, "r/o open-file . variable rfid2 rfid2 !"), "0 ");
+        push_buf(forth, &mut buf[..3]);
+        assert_eq!(eval(forth, "rfid2 @ read-file . ."), "0 3 ");
+        assert_eq!(&buf[..3], b"hel");
+        assert_eq!(eval(forth, "rfid2 @ close-file ."), "0 ");
+
+        // Failures are reported with an ior, not an error.
+        push_bytes(forth, path.as_bytes());
+        assert_eq!(eval(forth, "delete-file ."), "0 ");
+        push_bytes(forth, path.as_bytes());
+        assert_eq!(eval(forth, "delete-file ."), "1 ");
+        push_bytes(forth, path.as_bytes());
+        assert_eq!(eval(forth, "r/o open-file . ."), "1 0 ");
+        assert_eq!(FileError::from_ior(1), Some(FileError::NotFound));
+        assert_eq!(eval(forth, "wfid @ close-file ."), "5 ");
+        assert_eq!(eval(forth, "wfid @ file-size . . ."), "5 0 0 ");
+        push_bytes(forth, b"hello");
+        assert_eq!(eval(forth, "wfid @ write-file ."), "5 ");
+        assert_eq!(FileError::from_ior(5), Some(FileError::BadFileId));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_input_source() {
+        use std::collections::VecDeque;
+        use core::{ptr::NonNull, task::Context};
+        use crate::{
+            dictionary::{AsyncBuiltinEntry, AsyncBuiltins},
+            fastr::FaStr,
+            input::{AsyncInputSource, InputError},
+            leakbox::AsyncLBForth,
+            testutil::async_blockon_runtest_with,
+        };
+
+        struct NoAsyncBuiltins;
+        impl<'forth> AsyncBuiltins<'forth, ()> for NoAsyncBuiltins {
+            type Future = futures::future::Ready<Result<(), Error>>;
+            const BUILTINS: &'static [AsyncBuiltinEntry<()>] = &[];
+            fn dispatch_async(&self, _id: &FaStr, _forth: &'forth mut Forth<()>) -> Self::Future {
+                unreachable!("no async builtins should be called in this test")
+            }
+        }
+
+        // Yields once before every read.
+        struct SlowSource {
+            bytes: VecDeque<u8>,
+            yielded: bool,
+        }
+
+        impl SlowSource {
+            fn yield_once(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+                self.yielded = !self.yielded;
+                if !self.yielded {
+                    Poll::Ready(())
+                } else {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            }
+        }
+
+        impl AsyncInputSource for SlowSource {
+            fn poll_read_line(
+                &mut self,
+                cx: &mut Context<'_>,
+                buf: &mut [u8],
+            ) -> Poll<Result<Option<usize>, InputError>> {
+                futures::ready!(self.yield_once(cx));
+                if self.bytes.is_empty() {
+                    return Poll::Ready(Ok(None));
+                }
+                let mut n = 0;
+                while let Some(b) = self.bytes.pop_front() {
+                    *buf.get_mut(n).ok_or(InputError::LineTooLong)? = b;
+                    n += 1;
+                    if b == b'\n' {
+                        break;
+                    }
+                }
+                Poll::Ready(Ok(Some(n)))
+            }
+
+            fn poll_read_key(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<u8>, InputError>> {
+                futures::ready!(self.yield_once(cx));
+                Poll::Ready(Ok(self.bytes.pop_front()))
+            }
+
+            fn key_ready(&mut self) -> bool {
+                !self.bytes.is_empty()
+            }
+        }
+
+        let mut source = SlowSource {
+            bytes: b"1 2 + .\nxyz".iter().copied().collect(),
+            yielded: false,
+        };
+        let mut lbforth = AsyncLBForth::from_params(
+            LBForthParams::default(),
+            (),
+            Forth::FULL_BUILTINS,
+            NoAsyncBuiltins,
+        );
+        unsafe {
+            lbforth.forth.set_input_source(Some(NonNull::from(&mut source as &mut dyn AsyncInputSource)));
+        }
+
+        async_blockon_runtest_with(&mut lbforth.forth, r#"
+            > refill
+            < 3 ok.
+            > . key emit key? .
+            < -1 x-1 ok.
+            > array buf 4
+            < ok.
+            > buf 16 accept . buf b@ emit
+            < 2 yok.
+            > refill . key? .
+            < 0 0 ok.
+        "#);
+
+        unsafe {
+            lbforth.forth.set_input_source(None);
+        }
+    }
+
+    #[test]
+    fn it_still_works_when_forked() {
+        let mut lbforth1 = LBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::<TestContext>::FULL_BUILTINS,
+        );
+
+        // run all the tests on the first forth VM
+        println!("\n --- testing first forth VM --- \n");
+        blocking_runtest_with(&mut lbforth1.forth, r#"
+            > 2 3 + .
+            < 5 ok.
+            > : yay 2 3 + . ;
+            < ok.
+            > yay yay yay
+            < 5 5 5 ok.
+            > : boop yay yay ;
+            < ok.
+            > boop
+            < 5 5 ok.
+            > : err if boop boop boop else yay yay then ;
+            < ok.
+            > : erf if boop boop boop then yay yay ;
+            < ok.
+            > 0 err
+            < 5 5 ok.
+            > 1 err
+            < 5 5 5 5 5 5 ok.
+            > 0 erf
+            < 5 5 ok.
+            > 1 erf
+            < 5 5 5 5 5 5 5 5 ok.
+            > : one 1 . ;
+            < ok.
+            > : two 2 . ;
+            < ok.
+            > : six 6 . ;
+            < ok.
+            > : nif if one if two two else six then one then ;
+            < ok.
+            >   0 nif
+            < ok.
+            > 0 1 nif
+            < 1 6 1 ok.
+            > 1 1 nif
+            < 1 2 2 1 ok.
+            > 42 emit
+            < *ok.
+            > : star 42 emit ;
+            < ok.
+            > star star star
+            < ***ok.
+            > : sloop one 5 0 do star star loop six ;
+            < ok.
+            > sloop
+            < 1 **********6 ok.
+            > : count 10 0 do i . loop ;
+            < ok.
+            > count
+            < 0 1 2 3 4 5 6 7 8 9 ok.
+            > : smod 10 0 do i 3 mod not if star then loop ;
+            < ok.
+            > smod
+            < ****ok.
+            > : beep ." hello, world! " ;
+            < ok.
+            > beep
+            < hello, world! ok.
+            > constant x 123
+            < ok.
+            > x .
+            < 123 ok.
+            > 4 x + .
+            < 127 ok.
+            > variable y
+            < ok.
+            > y @ .
+            < 0 ok.
+            > 10 y !
+            < ok.
+            > y @ .
+            < 10 ok.
+            > array z 4
+            < ok.
+            > z @ . z 1 w+ @ . z 2 w+ @ . z 3 w+ @ .
+            < 0 0 0 0 ok.
+            > 10 z ! 20 z 1 w+ ! 30 z 2 w+ ! 40 z 3 w+ !
+            < ok.
+            > z @ . z 1 w+ @ . z 2 w+ @ . z 3 w+ @ .
+            < 10 20 30 40 ok.
+            > forget z
+            < ok.
+            > variable a
+            < ok.
+            > 100 a !
+            < ok.
+            > array z 4
+            < ok.
+            > z @ . z 1 w+ @ . z 2 w+ @ . z 3 w+ @ .
+            < 0 0 0 0 ok.
+            "#
+        );
+        print_dict("forth1", &mut lbforth1.forth);
+
+        // create a new forth VM, and deep copy the first VM's dictionary into
+        // the second.
+        let mut lbforth2 = lbforth1.fork_with_params(
+            LBForthParams::default(),
+            TestContext::default(),
+        );
+
+        println!("\n --- testing second forth VM --- \n");
+        blocking_runtest_with(&mut lbforth2.forth, r#"
+            ( all the bindings in the old VM's dictionary should be present in the
+              new VM, and, it shouldn't segfault... :D )
+            > yay yay yay
+            < 5 5 5 ok.
+            > boop
+            < 5 5 ok.
+            > 0 err
+            < 5 5 ok.
+            > 1 err
+            < 5 5 5 5 5 5 ok.
+            > 0 erf
+            < 5 5 ok.
+            > 1 erf
+            < 5 5 5 5 5 5 5 5 ok.
+            >   0 nif
+            < ok.
+            > 0 1 nif
+            < 1 6 1 ok.
+            > 1 1 nif
+            < 1 2 2 1 ok.
+            > star star star
+            < ***ok.
+            > sloop
+            < 1 **********6 ok.
+            > count
+            < 0 1 2 3 4 5 6 7 8 9 ok.
+            > smod
+            < ****ok.
+            > beep
+            < hello, world! ok.
+            > x .
+            < 123 ok.
+            > 4 x + .
+            < 127 ok.
+
+            ( the existing `y` variable should have its second value from the
+              first test. )
+            > y @ .
+            < 10 ok.
+
+            ( reassigning `y` is okay )
+            > 100 y !
+            < ok.
+            > y @ .
+            < 100 ok.
+
+            ( also reassign `a` )
+            > 500 a !
+            < ok.
+
+            > z @ . z 1 w+ @ . z 2 w+ @ . z 3 w+ @ .
+            < 0 0 0 0 ok.
+
+            ( define a new word in `forth2`. )
+            > : star3 star star star ;
+            < ok.
+            > star3
+            < ***ok.
+
+            ( define a new variable in forth2 )
+            > variable foo
+            < ok.
+            > foo @ .
+            < 0 ok.
+            > 123 foo !
+            < ok.
+            > foo @ .
+            < 123 ok.
+        "#);
+        print_dict("forth2", &mut lbforth2.forth);
+
+        // check that forth1's bindings aren't clobbered
+        println!("\n --- retesting first VM's bindings --- \n");
+        blocking_runtest_with(&mut lbforth1.forth, r#"
+            ( the existing `y` variable should have its second value from the
+              first test. )
+            > y @ .
+            < 10 ok.
+            > a @ .
+            < 100 ok.
+        "#);
+        // new words defined in forth2 don't exist in forth1
+        blocking_runtest_with(&mut lbforth1.forth, "x star3");
+        // and neither do new variables.
+        blocking_runtest_with(&mut lbforth1.forth, "x foo @ .");
+
+        // have forth1 change some of its bindings
+        blocking_runtest_with(&mut lbforth1.forth, r#"
+            ( change the value of `y` )
+            > 666 y !
+            < ok.
+            > y @ .
+            < 666 ok.
 
             (redefine `beep` )
             > : beep ." goodbye, world! " ;
@@ -663,12 +2542,14 @@ pub mod test {
         forth.output.clear();
     }
 
+    #[cfg(feature = "async")]
     struct CountingFut<'forth> {
         target: usize,
         ctr: usize,
         forth: &'forth mut Forth<TestContext>,
     }
 
+    #[cfg(feature = "async")]
     impl<'forth> Future for CountingFut<'forth> {
         type Output = Result<(), Error>;
 
@@ -692,35 +2573,148 @@ pub mod test {
         }
     }
 
+    #[cfg(feature = "async")]
+    struct TestAsyncDispatcher;
+
+    #[cfg(feature = "async")]
+    impl<'forth> crate::dictionary::AsyncBuiltins<'forth, TestContext> for TestAsyncDispatcher {
+        type Future = CountingFut<'forth>;
+
+        const BUILTINS: &'static [crate::dictionary::AsyncBuiltinEntry<TestContext>] = &[
+            crate::async_builtin!("counter"),
+        ];
+
+        fn dispatch_async(
+            &self,
+            id: &crate::fastr::FaStr,
+            forth: &'forth mut Forth<TestContext>,
+        ) -> Self::Future {
+            match id.as_str() {
+                "counter" => {
+                    // Get value from top of stack
+                    let val: usize = forth.data_stack.pop().unwrap().try_into().unwrap();
+                    CountingFut { ctr: 0, target: val, forth }
+                }
+                id => panic!("Unknown async builtin {id}")
+            }
+        }
+    }
+
     #[cfg(feature = "async")]
     #[test]
-    fn async_forth() {
-        use crate::{dictionary::{AsyncBuiltins, AsyncBuiltinEntry}, fastr::FaStr, async_builtin, testutil::async_blockon_runtest_with_dispatcher};
+    fn async_cancellation() {
+        use crate::{leakbox::AsyncLBForth, testutil::async_cancel_runtest_with_dispatcher};
 
-        struct TestAsyncDispatcher;
-        impl<'forth> AsyncBuiltins<'forth, TestContext> for TestAsyncDispatcher {
-            type Future = CountingFut<'forth>;
+        async_cancel_runtest_with_dispatcher(
+            TestContext::default(),
+            TestAsyncDispatcher, r#"
+                > : twice 2 counter 3 counter + ;
+                > 1 twice + .
+                < 6 ok.
+                > 4 counter 1 counter . .
+                < 1 4 ok.
+            "#
+        );
 
-            const BUILTINS: &'static [AsyncBuiltinEntry<TestContext>] = &[
-                async_builtin!("counter"),
-            ];
+        let mut lbforth = AsyncLBForth::from_params(
+            LBForthParams::default(),
+            TestContext::default(),
+            Forth::FULL_BUILTINS,
+            TestAsyncDispatcher,
+        );
+        let forth = &mut lbforth.forth;
+        let mut polls = 0;
+        let deadline = futures::future::poll_fn(|_| {
+            polls += 1;
+            if polls > 10 { Poll::Ready(()) } else { Poll::Pending }
+        });
+        forth.input_mut().fill("1 2 1000 counter").unwrap();
+        let res = futures::executor::block_on(forth.process_line_until(deadline));
+        assert_eq!(res, Err(Error::Cancelled));
+        assert_eq!(forth.vm_mut().data_stack.depth(), 0);
+        assert_eq!(forth.vm_mut().call_stack.depth(), 0);
+
+        // The VM can carry on as normal afterwards.
+        forth.input_mut().fill("1 2 3 counter + + .").unwrap();
+        let res = futures::executor::block_on(forth.process_line_until(core::future::pending()));
+        assert_eq!(res, Ok(()));
+        assert_eq!(forth.output().as_str(), "6 ok.\n");
+    }
 
-            fn dispatch_async(
-                &self,
-                id: &FaStr,
-                forth: &'forth mut Forth<TestContext>,
-            ) -> Self::Future {
-                match id.as_str() {
-                    "counter" => {
-                        // Get value from top of stack
-                        let val: usize = forth.data_stack.pop().unwrap().try_into().unwrap();
-                        CountingFut { ctr: 0, target: val, forth }
-                    }
-                    id => panic!("Unknown async builtin {id}")
-                }
+    #[cfg(feature = "async")]
+    #[test]
+    fn runtime_async_builtins() {
+        use crate::{
+            dictionary::{AsyncBuiltinEntry, AsyncBuiltins},
+            fastr::FaStr,
+            leakbox::AsyncLBForth,
+            testutil::async_blockon_runtest_with,
+        };
+        use core::pin::Pin;
+
+        // Plugin words push their value, after waiting for a poll.
+        #[derive(Default)]
+        struct Plugins(Vec<(String, i32)>);
+
+        impl<'forth> AsyncBuiltins<'forth, ()> for Plugins {
+            type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + 'forth>>;
+
+            const BUILTINS: &'static [AsyncBuiltinEntry<()>] = &[];
+
+            fn dispatch_async(&self, id: &FaStr, forth: &'forth mut Forth<()>) -> Self::Future {
+                let (_, val) = self.0.iter().find(|(name, _)| name == id.as_str()).unwrap();
+                let val = *val;
+                Box::pin(async move {
+                    let mut polled = false;
+                    core::future::poll_fn(|cx| {
+                        if polled {
+                            return Poll::Ready(());
+                        }
+                        polled = true;
+                        cx.waker().wake_by_ref();
+                        Poll::Pending
+                    }).await;
+                    forth.data_stack.push(Word::data(val))?;
+                    Ok(())
+                })
             }
         }
 
+        let mut lbforth = AsyncLBForth::from_params(
+            LBForthParams::default(),
+            (),
+            Forth::FULL_BUILTINS,
+            Plugins::default(),
+        );
+        let forth = &mut lbforth.forth;
+        async_blockon_runtest_with(forth, "x answer .");
+
+        forth.add_async_builtin("answer").unwrap();
+        forth.async_builtins_mut().0.push(("answer".into(), 42));
+        forth.add_async_builtin_static_name("seven").unwrap();
+        forth.async_builtins_mut().0.push(("seven".into(), 7));
+        async_blockon_runtest_with(forth, r#"
+            > answer .
+            < 42 ok.
+            > : both answer seven + ;
+            > both .
+            < 49 ok.
+            > ' seven execute .
+            < 7 ok.
+        "#);
+
+        // A sync VM can't run them.
+        assert_eq!(
+            forth.vm_mut().evaluate("seven"),
+            Err(Error::AsyncBuiltinInSyncContext),
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_forth() {
+        use crate::testutil::async_blockon_runtest_with_dispatcher;
+
         async_blockon_runtest_with_dispatcher(
             TestContext::default(),
             TestAsyncDispatcher, r#"
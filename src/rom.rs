@@ -0,0 +1,470 @@
+//! Dictionaries in static memory.
+//!
+//! Forking a VM freezes its dictionary so that it can be shared, but the
+//! frozen dictionary still lives in (and takes up) RAM. A [`StaticDict`] is a
+//! frozen dictionary that is built ahead of time and placed in a `static`, so
+//! that on targets that run from flash, a library of common words takes up no
+//! RAM at all. A VM uses one by setting it as the parent of its dictionary
+//! with [`OwnedDict::set_static_parent`](crate::dictionary::OwnedDict::set_static_parent).
+//!
+//! A static dictionary is made of [`StaticEntry`]s, which are generated from
+//! Forth source, typically in a build script, with [`generate`]:
+//!
+//! ```rust,ignore
+//! // build.rs
+//! let word_size = std::env::var("CARGO_CFG_TARGET_POINTER_WIDTH").unwrap();
+//! let params = forth3::rom::StaticDictParams {
+//!     name: "LIBRARY",
+//!     context: "crate::Context",
+//!     builtins: "crate::BUILTINS",
+//!     word_size: word_size.parse::<usize>().unwrap() / 8,
+//!     ..Default::default()
+//! };
+//! let code = forth3::rom::generate(include_str!("src/library.fth"), &params).unwrap();
+//! let out = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+//! std::fs::write(out.join("library.rs"), code).unwrap();
+//!
+//! // src/main.rs
+//! include!(concat!(env!("OUT_DIR"), "/library.rs"));
+//! ```
+//!
+//! Variables and arrays can't be placed in a static dictionary, as they may
+//! be written to. Nor can vocabularies, as every entry in a static dictionary
+//! is in `forth-wordlist`.
+
+use core::{mem, ptr::NonNull};
+
+use crate::{
+    dictionary::{BuiltinEntry, DictionaryEntry, EntryHeader, EntryKind, EntryType, FORTH_WORDLIST},
+    fastr::comptime_fastr,
+    word::Word,
+    WordFunc,
+};
+
+/// A frozen dictionary in static memory.
+pub struct StaticDict<T: 'static> {
+    pub(crate) tail: Option<NonNull<DictionaryEntry<T>>>,
+}
+
+/// A dictionary entry in static memory, with a parameter field of `N` words.
+#[repr(C)]
+pub struct StaticEntry<T: 'static, const N: usize> {
+    entry: DictionaryEntry<T>,
+    parameter_field: [Word; N],
+}
+
+// Safety: static dictionaries are never written to.
+unsafe impl<T: 'static> Sync for StaticDict<T> {}
+unsafe impl<T: 'static, const N: usize> Sync for StaticEntry<T, N> {}
+
+impl<T: 'static> StaticDict<T> {
+    /// Creates a static dictionary whose newest entry is `tail`.
+    pub const fn new(tail: Option<&'static DictionaryEntry<T>>) -> Self {
+        Self {
+            tail: match tail {
+                Some(entry) => Some(unsafe { NonNull::new_unchecked(entry as *const _ as *mut _) }),
+                None => None,
+            },
+        }
+    }
+}
+
+impl<T: 'static, const N: usize> StaticEntry<T, N> {
+    /// Creates an entry named `name` of type `ty`, run by `func`, which links
+    /// to the previous entry in its dictionary, `link`.
+    pub const fn new(
+        name: &'static str,
+        ty: EntryType,
+        func: WordFunc<T>,
+        link: Option<&'static DictionaryEntry<T>>,
+        parameter_field: [Word; N],
+    ) -> Self {
+        // The parameter field must be where the entry's header says it is.
+        assert!(
+            mem::offset_of!(Self, parameter_field)
+                == mem::offset_of!(DictionaryEntry<T>, parameter_field)
+        );
+        assert!(N <= u16::MAX as usize);
+        Self {
+            entry: DictionaryEntry {
+                hdr: EntryHeader {
+                    name: comptime_fastr(name),
+                    kind: EntryKind::Dictionary,
+                    len: N as u16,
+                    _pd: core::marker::PhantomData,
+                },
+                func,
+                link: match link {
+                    Some(entry) => {
+                        Some(unsafe { NonNull::new_unchecked(entry as *const _ as *mut _) })
+                    }
+                    None => None,
+                },
+                wordlist: FORTH_WORDLIST,
+                ty,
+                parameter_field: [],
+            },
+            parameter_field,
+        }
+    }
+
+    /// The entry's header, for linking to it.
+    pub const fn entry(&'static self) -> &'static DictionaryEntry<T> {
+        &self.entry
+    }
+
+    /// A word pointing to the entry, for calling it from a definition.
+    pub const fn word(&'static self) -> Word {
+        Word::ptr(&self.entry as *const DictionaryEntry<T> as *mut DictionaryEntry<T>)
+    }
+}
+
+/// A word pointing to the builtin named `name`, for calling it from a
+/// definition.
+///
+/// # Panics
+///
+/// If there is no such builtin. When used in a `static`, this is a compile
+/// time error.
+pub const fn builtin<T: 'static>(builtins: &'static [BuiltinEntry<T>], name: &str) -> Word {
+    let bi = find_builtin(builtins, name);
+    Word::ptr(bi as *const BuiltinEntry<T> as *mut BuiltinEntry<T>)
+}
+
+/// The function of the builtin named `name`, for entries that run it (such
+/// as constants).
+///
+/// # Panics
+///
+/// If there is no such builtin. When used in a `static`, this is a compile
+/// time error.
+pub const fn builtin_func<T: 'static>(
+    builtins: &'static [BuiltinEntry<T>],
+    name: &str,
+) -> WordFunc<T> {
+    find_builtin(builtins, name).func
+}
+
+/// A word holding (up to a word's worth of) the bytes of a string compiled
+/// by `."`.
+pub const fn str_word(bytes: &[u8]) -> Word {
+    assert!(bytes.len() <= mem::size_of::<Word>());
+    let mut word = Word::data(0);
+    let dest = &mut word as *mut Word as *mut u8;
+    let mut i = 0;
+    while i < bytes.len() {
+        unsafe { dest.add(i).write(bytes[i]) };
+        i += 1;
+    }
+    word
+}
+
+const fn find_builtin<T: 'static>(
+    builtins: &'static [BuiltinEntry<T>],
+    name: &str,
+) -> &'static BuiltinEntry<T> {
+    let mut i = 0;
+    while i < builtins.len() {
+        if bytes_eq(builtins[i].hdr.name.as_bytes(), name.as_bytes()) {
+            return &builtins[i];
+        }
+        i += 1;
+    }
+    panic!("no builtin with that name");
+}
+
+pub(crate) const fn bytes_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+#[cfg(feature = "use-std")]
+pub use self::generate::{generate, StaticDictParams};
+
+#[cfg(feature = "use-std")]
+mod generate {
+    use core::{fmt::Write, mem::size_of};
+    use std::{format, string::String, vec, vec::Vec};
+
+    use crate::{
+        dictionary::{EntryType, FORTH_WORDLIST},
+        vm::image::{Reader, CELL_BUILTIN, CELL_DATA, CELL_ENTRY, CELL_STR},
+        input::IoSource,
+        leakbox::{LBForth, LBForthParams},
+        word::Word,
+        Error, Forth, ImageError,
+    };
+
+    /// What [`generate`] should generate.
+    #[derive(Debug)]
+    pub struct StaticDictParams<'a> {
+        /// The name of the generated `static`.
+        pub name: &'a str,
+        /// The path of the host context type (`T`) of the VMs that will use
+        /// the dictionary.
+        pub context: &'a str,
+        /// An expression for the `&'static [BuiltinEntry<T>]` builtins of the
+        /// VMs that will use the dictionary.
+        pub builtins: &'a str,
+        /// The names of any builtins the source uses that aren't in
+        /// [`Forth::FULL_BUILTINS`]. The source can compile calls to them,
+        /// but can't run them.
+        pub extra_builtins: &'a [&'a str],
+        /// The size of a [`Word`] on the target, in bytes.
+        pub word_size: usize,
+        /// The path of this crate.
+        pub crate_path: &'a str,
+    }
+
+    /// A cell of an entry's parameter field, as read from an image.
+    enum Cell {
+        Data(i64),
+        Entry(usize),
+        Builtin(String),
+        Str(Vec<u8>),
+    }
+
+    struct Entry {
+        name: String,
+        ty: EntryType,
+        cells: Vec<Cell>,
+        /// Whether the entry is one of the `extra_builtins`.
+        extra: bool,
+    }
+
+    impl Default for StaticDictParams<'_> {
+        fn default() -> Self {
+            Self {
+                name: "DICT",
+                context: "()",
+                builtins: "forth3::Forth::<()>::FULL_BUILTINS",
+                extra_builtins: &[],
+                word_size: size_of::<Word>(),
+                crate_path: "forth3",
+            }
+        }
+    }
+
+    /// Compiles `source`, and returns Rust code defining a `static`
+    /// [`StaticDict`](super::StaticDict) holding the words it defines.
+    ///
+    /// The source is compiled with [`Forth::FULL_BUILTINS`] (and the
+    /// `extra_builtins`), and the builtins it calls are looked up by name in
+    /// the `builtins` the generated code is compiled with.
+    pub fn generate(source: &str, params: &StaticDictParams<'_>) -> Result<String, Error> {
+        let vm_params = LBForthParams {
+            output_buf_elems: 4096,
+            dict_buf_elems: 64 * 1024,
+            ..Default::default()
+        };
+        let mut lbf = LBForth::from_params(vm_params, (), Forth::FULL_BUILTINS);
+        for name in params.extra_builtins {
+            lbf.forth.add_builtin(name, extra_builtin)?;
+        }
+        let mut lines = IoSource::new(std::io::Cursor::new(source.as_bytes().to_vec()));
+        lbf.forth.interpret_lines(&mut lines)?;
+
+        let mut image = vec![0; 4096];
+        let len = loop {
+            match lbf.forth.save_image(&mut image) {
+                Ok(len) => break len,
+                Err(Error::Image(ImageError::BufferTooSmall)) => {
+                    image.resize(image.len() * 2, 0);
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        let entries = read_entries(&image[..len])?;
+        emit(&entries, params)
+    }
+
+    fn extra_builtin(_forth: &mut Forth<()>) -> Result<(), Error> {
+        Err(Error::WordNotInDict)
+    }
+
+    fn read_entries(image: &[u8]) -> Result<Vec<Entry>, Error> {
+        let mut r = Reader::new(image)?;
+        let mut entries = Vec::new();
+        for _ in 0..r.u32()? {
+            let name = String::from(r.name_str()?);
+            let kind = r.u8()?;
+            let ty = EntryType::from_u8(r.u8()?).ok_or(ImageError::Malformed)?;
+            if r.u16()? != FORTH_WORDLIST {
+                return Err(Error::WordlistInStaticDict);
+            }
+            let mut cells = Vec::new();
+            for _ in 0..r.u16()? {
+                let cell = match r.u8()? {
+                    CELL_DATA => Cell::Data(r.i64()?),
+                    CELL_ENTRY => Cell::Entry(r.u32()? as usize),
+                    CELL_BUILTIN => Cell::Builtin(String::from(r.name_str()?)),
+                    CELL_STR => {
+                        let len = r.u16()?;
+                        Cell::Str(r.bytes(usize::from(len))?.to_vec())
+                    }
+                    // There are no parent dictionaries or async builtins.
+                    _ => return Err(ImageError::Malformed.into()),
+                };
+                cells.push(cell);
+            }
+            entries.push(Entry {
+                name,
+                ty,
+                cells,
+                // Only the extra builtins are runtime builtins.
+                extra: kind == 1,
+            });
+        }
+        Ok(entries)
+    }
+
+    fn emit(entries: &[Entry], params: &StaticDictParams<'_>) -> Result<String, Error> {
+        let StaticDictParams {
+            name,
+            context: ctx,
+            builtins,
+            word_size,
+            crate_path: krate,
+            ..
+        } = *params;
+        let mut out = String::new();
+        writeln!(out, "// @generated by `{krate}::rom::generate`. Do not edit.")?;
+        writeln!(out, "pub static {name}: {krate}::rom::StaticDict<{ctx}> = {{")?;
+        writeln!(
+            out,
+            "    use {krate}::{{dictionary::EntryType, rom::{{self, StaticEntry}}, word::Word}};"
+        )?;
+        writeln!(out, "    const _: () = assert!(core::mem::size_of::<Word>() == {word_size});")?;
+        writeln!(
+            out,
+            "    const BUILTINS: &[{krate}::dictionary::BuiltinEntry<{ctx}>] = {builtins};"
+        )?;
+
+        let mut prev = None;
+        for (idx, entry) in entries.iter().enumerate() {
+            if entry.extra {
+                continue;
+            }
+            let func = match entry.ty {
+                EntryType::Colon => format!("{krate}::Forth::<{ctx}>::interpret"),
+                EntryType::Constant => String::from("rom::builtin_func(BUILTINS, \"(constant)\")"),
+                EntryType::Variable | EntryType::Array | EntryType::Channel => {
+                    return Err(Error::VariableInStaticDict)
+                }
+                EntryType::Vocabulary => return Err(Error::WordlistInStaticDict),
+                // The only runtime builtins are the extra builtins, which
+                // were skipped above.
+                EntryType::Builtin => return Err(ImageError::Malformed.into()),
+            };
+            let words = if entry.ty == EntryType::Colon {
+                code_words(entries, &entry.cells, word_size)?
+            } else {
+                entry
+                    .cells
+                    .iter()
+                    .map(|cell| match cell {
+                        Cell::Data(val) => Ok(data_word(*val)),
+                        _ => Err(Error::from(ImageError::Malformed)),
+                    })
+                    .collect::<Result<_, _>>()?
+            };
+            let link = match prev {
+                Some(prev) => format!("Some(E{prev}.entry())"),
+                None => String::from("None"),
+            };
+            writeln!(
+                out,
+                "    static E{idx}: StaticEntry<{ctx}, {}> = StaticEntry::new({:?}, EntryType::{:?}, {func}, {link}, [",
+                words.len(),
+                entry.name,
+                entry.ty,
+            )?;
+            for word in words {
+                writeln!(out, "        {word},")?;
+            }
+            writeln!(out, "    ]);")?;
+            prev = Some(idx);
+        }
+
+        match prev {
+            Some(prev) => writeln!(out, "    rom::StaticDict::new(Some(E{prev}.entry()))")?,
+            None => writeln!(out, "    rom::StaticDict::new(None)")?,
+        }
+        writeln!(out, "}};")?;
+        Ok(out)
+    }
+
+    /// Returns the words of a `:` definition, laid out for the target's word
+    /// size.
+    fn code_words(entries: &[Entry], cells: &[Cell], word_size: usize) -> Result<Vec<String>, Error> {
+        // Strings take up a different number of words on the target, which
+        // moves jump targets, so work out where each cell starts.
+        let cell_words = |cell: &Cell, size: usize| match cell {
+            Cell::Str(bytes) => 1 + bytes.len().div_ceil(size),
+            _ => 1,
+        };
+        let mut host = vec![0];
+        let mut target = vec![0];
+        for cell in cells {
+            host.push(host[host.len() - 1] + cell_words(cell, size_of::<Word>()));
+            target.push(target[target.len() - 1] + cell_words(cell, word_size));
+        }
+
+        let mut words = Vec::new();
+        for (idx, cell) in cells.iter().enumerate() {
+            match cell {
+                Cell::Data(val) => {
+                    let is_jump = matches!(
+                        idx.checked_sub(1).map(|prev| &cells[prev]),
+                        Some(Cell::Builtin(bi)) if matches!(bi.as_str(), "(jump-zero)" | "(jmp)" | "(jmp-doloop)")
+                    );
+                    if is_jump {
+                        // Jumps are relative to the offset's own word.
+                        let dest = host[idx] as i64 + *val as i32 as i64;
+                        let dest_cell = host
+                            .iter()
+                            .position(|&pos| pos as i64 == dest)
+                            .ok_or(ImageError::Malformed)?;
+                        let offset = target[dest_cell] as i64 - target[idx] as i64;
+                        words.push(format!("Word::data({offset})"));
+                    } else {
+                        words.push(data_word(*val));
+                    }
+                }
+                Cell::Entry(target) => {
+                    let entry = entries.get(*target).ok_or(ImageError::Malformed)?;
+                    if entry.extra {
+                        words.push(format!("rom::builtin(BUILTINS, {:?})", entry.name));
+                    } else {
+                        words.push(format!("E{target}.word()"));
+                    }
+                }
+                Cell::Builtin(name) => words.push(format!("rom::builtin(BUILTINS, {name:?})")),
+                Cell::Str(bytes) => {
+                    words.push(format!("Word::data({})", bytes.len()));
+                    for chunk in bytes.chunks(word_size) {
+                        words.push(format!("rom::str_word(b\"{}\")", chunk.escape_ascii()));
+                    }
+                }
+            }
+        }
+        Ok(words)
+    }
+
+    fn data_word(val: i64) -> String {
+        // Data is written as an `i32`, leaving the rest of the word zeroed.
+        match u32::try_from(val) {
+            Ok(data) => format!("Word::data({})", data as i32),
+            Err(_) => format!("Word::ptr_data({val})"),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! Cooperative multitasking.
+//!
+//! A [`Scheduler`] runs several VMs (usually forks of the same VM, so that
+//! they share its dictionary) together, round-robin, a few steps at a time
+//! (see [`Forth::step`]). Each task runs the line in its VM's input, and is
+//! finished once the line is done.
+//!
+//! Tasks can interact with the scheduler using these words:
+//!
+//! - `pause ( -- )` ends the task's time slice early.
+//! - `task-id ( -- id )` is the task's id, or 0 if the VM isn't a task.
+//! - `spawn ( xt -- id )` starts a new task running the word `xt`. If the
+//!   task can't be started, `id` is 0, and the spawning task carries on.
+//! - `kill ( id -- )` stops a task, which may be the task itself.
+//!
+//! Creating a VM for a spawned task, and cleaning up after a finished one, is
+//! left to the host's [`TaskHost`].
+
+use core::ptr::NonNull;
+
+use crate::{dictionary::EntryHeader, word::Word, CallContext, Error, Forth, ReplaceErr, Step};
+
+/// A task's id. Ids start at 1.
+pub type TaskId = u16;
+
+/// The host's side of a [`Scheduler`].
+pub trait TaskHost<'a, T: 'static> {
+    /// Creates the VM for a task started by `parent` with `spawn`.
+    ///
+    /// The task runs a word from `parent`'s dictionary, so the VM must have
+    /// access to it, e.g. by being a fork of `parent` (or of a VM that
+    /// `parent` is a fork of).
+    fn spawn(&mut self, parent: &mut Forth<T>) -> Result<&'a mut Forth<T>, Error>;
+
+    /// Hands back a task's VM once the task has finished, along with the
+    /// result of running its line. Killed tasks finish with
+    /// [`Error::TaskKilled`].
+    fn finished(&mut self, id: TaskId, vm: &'a mut Forth<T>, res: Result<(), Error>);
+}
+
+/// Round-robin scheduling of up to `N` tasks.
+pub struct Scheduler<'a, T: 'static, H, const N: usize> {
+    host: H,
+    tasks: [Option<(TaskId, &'a mut Forth<T>)>; N],
+    next_id: TaskId,
+    /// The slot of the task that ran last.
+    current: usize,
+    time_slice: usize,
+}
+
+/// The scheduler-related state of a VM.
+#[derive(Default)]
+pub(crate) struct TaskState {
+    /// The VM's task id, or 0 if it isn't a task.
+    pub(crate) id: TaskId,
+    /// What the task has asked of the scheduler, to be handled after the step
+    /// that asked for it.
+    pub(crate) request: Option<TaskRequest>,
+}
+
+pub(crate) enum TaskRequest {
+    Pause,
+    Spawn(Word),
+    Kill(TaskId),
+}
+
+// === impl Scheduler ===
+
+impl<'a, T: 'static, H: TaskHost<'a, T>, const N: usize> Scheduler<'a, T, H, N> {
+    /// How many steps a task runs for at a time, unless it `pause`s first.
+    pub const DEFAULT_TIME_SLICE: usize = 64;
+
+    pub fn new(host: H) -> Self {
+        Self {
+            host,
+            tasks: [const { None }; N],
+            next_id: 1,
+            current: N.saturating_sub(1),
+            time_slice: Self::DEFAULT_TIME_SLICE,
+        }
+    }
+
+    pub fn set_time_slice(&mut self, steps: usize) {
+        self.time_slice = steps;
+    }
+
+    pub fn host(&mut self) -> &mut H {
+        &mut self.host
+    }
+
+    /// Adds `vm` as a new task, which will run the line in its input.
+    pub fn add(&mut self, vm: &'a mut Forth<T>) -> Result<TaskId, Error> {
+        let slot = self.tasks.iter().position(Option::is_none).ok_or(Error::TooManyTasks)?;
+        let id = self.new_id();
+        vm.task = TaskState { id, request: None };
+        self.tasks[slot] = Some((id, vm));
+        Ok(id)
+    }
+
+    /// Returns the VM of task `id`, if it's still running.
+    pub fn task(&mut self, id: TaskId) -> Option<&mut Forth<T>> {
+        let slot = self.slot_of(id)?;
+        self.tasks[slot].as_mut().map(|(_, vm)| &mut **vm)
+    }
+
+    /// Stops task `id`, returning `false` if there is no such task.
+    pub fn kill(&mut self, id: TaskId) -> bool {
+        match self.slot_of(id) {
+            Some(slot) => {
+                self.finish(slot, Err(Error::TaskKilled));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `true` if there are no tasks left to run.
+    pub fn is_empty(&self) -> bool {
+        self.tasks.iter().all(Option::is_none)
+    }
+
+    /// Runs all of the tasks until they have finished.
+    pub fn run(&mut self) {
+        while self.run_slice() {}
+    }
+
+    /// Runs the next task for one time slice, returning `false` if there are
+    /// no tasks to run.
+    pub fn run_slice(&mut self) -> bool {
+        let Some(slot) = (1..=N)
+            .map(|n| (self.current + n) % N)
+            .find(|&slot| self.tasks[slot].is_some())
+        else {
+            return false;
+        };
+        self.current = slot;
+
+        for _ in 0..self.time_slice {
+            let Some((_, vm)) = self.tasks[slot].as_mut() else {
+                break;
+            };
+            let res = vm.step();
+            let request = vm.task.request.take();
+            let res = match (res, request) {
+                (Ok(Step::NotDone), None) => continue,
+                (Ok(Step::NotDone), Some(TaskRequest::Pause)) => break,
+                (Ok(Step::NotDone), Some(TaskRequest::Spawn(xt))) => {
+                    // The spawning task carries on even if the new task
+                    // can't be started, and is told so by getting id 0.
+                    let id = self.spawn(slot, xt).unwrap_or(0);
+                    match self.push_id(slot, id) {
+                        Ok(()) => continue,
+                        Err(e) => Err(e),
+                    }
+                }
+                (Ok(Step::NotDone), Some(TaskRequest::Kill(id))) => {
+                    self.kill(id);
+                    continue;
+                }
+                (Ok(Step::Done), _) => Ok(()),
+                (Err(e), _) => Err(e),
+            };
+            self.finish(slot, res);
+            break;
+        }
+        true
+    }
+
+    /// Starts a new task, spawned by the task in `slot`, running `xt`.
+    fn spawn(&mut self, slot: usize, xt: Word) -> Result<TaskId, Error> {
+        let free = self.tasks.iter().position(Option::is_none).ok_or(Error::TooManyTasks)?;
+        let eh = NonNull::new(unsafe { xt.ptr.cast::<EntryHeader<T>>() })
+            .ok_or(Error::NullPointerInCFA)?;
+        let (_, parent) = self.tasks[slot].as_mut().ok_or(Error::InternalError)?;
+        let child = self.host.spawn(parent)?;
+        let id = self.new_id();
+        child.task = TaskState { id, request: None };
+        let res = (|| {
+            child.input.fill("").replace_err(Error::InternalError)?;
+            child.call_stack.push(CallContext {
+                eh,
+                idx: 0,
+                len: unsafe { eh.as_ref() }.len,
+            })?;
+            Ok(())
+        })();
+        self.tasks[free] = Some((id, child));
+        if let Err(e) = res {
+            // The spawning task carries on, as it's the new task that failed.
+            self.finish(free, Err(e));
+        }
+        Ok(id)
+    }
+
+    /// Pushes the id returned by `spawn` to the task in `slot`.
+    fn push_id(&mut self, slot: usize, id: TaskId) -> Result<(), Error> {
+        let (_, vm) = self.tasks[slot].as_mut().ok_or(Error::InternalError)?;
+        vm.data_stack.push(Word::data(i32::from(id)))?;
+        Ok(())
+    }
+
+    fn finish(&mut self, slot: usize, res: Result<(), Error>) {
+        if let Some((id, vm)) = self.tasks[slot].take() {
+            vm.task = TaskState::default();
+            self.host.finished(id, vm, res);
+        }
+    }
+
+    fn slot_of(&self, id: TaskId) -> Option<usize> {
+        self.tasks
+            .iter()
+            .position(|task| matches!(task, Some((task_id, _)) if *task_id == id))
+    }
+
+    fn new_id(&mut self) -> TaskId {
+        loop {
+            let id = self.next_id;
+            self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+            if self.slot_of(id).is_none() {
+                return id;
+            }
+        }
+    }
+}
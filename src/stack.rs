@@ -4,6 +4,13 @@ pub struct Stack<T: Copy> {
     top: *mut T,
     cur: *mut T,
     bot: *mut T,
+    /// The deepest that `cur` has been, for [`Stack::high_water`].
+    ///
+    /// This is always tracked, rather than behind a feature, as it's only a
+    /// comparison in [`Stack::push`], next to the bounds check there, and
+    /// the high-water mark is most useful for a VM that's already been
+    /// built without expecting to need it.
+    high: *mut T,
 }
 
 #[derive(Debug, PartialEq)]
@@ -21,6 +28,7 @@ impl<T: Copy> Stack<T> {
             top,
             bot: bottom,
             cur: top,
+            high: top,
         }
     }
 
@@ -31,6 +39,9 @@ impl<T: Copy> Stack<T> {
             return Err(StackError::StackFull);
         }
         self.cur = next_cur;
+        if next_cur < self.high {
+            self.high = next_cur;
+        }
         unsafe {
             self.cur.write(item);
         }
@@ -42,6 +53,25 @@ impl<T: Copy> Stack<T> {
         ((self.top as usize) - (self.cur as usize)) / size_of::<T>()
     }
 
+    /// Returns the most items that have been on the stack at once, since it
+    /// was created or [`Stack::reset_high_water`] was called.
+    #[inline]
+    pub fn high_water(&self) -> usize {
+        ((self.top as usize) - (self.high as usize)) / size_of::<T>()
+    }
+
+    /// Starts measuring [`Stack::high_water`] again from the current depth.
+    #[inline]
+    pub fn reset_high_water(&mut self) {
+        self.high = self.cur;
+    }
+
+    /// Returns the most items that fit on the stack.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        ((self.top as usize) - (self.bot as usize)) / size_of::<T>()
+    }
+
     #[inline]
     pub fn try_pop(&mut self) -> Result<T, StackError> {
         match self.pop() {
@@ -175,5 +205,15 @@ pub mod test {
             }
             assert!(stack.pop().is_none());
         }
+        assert_eq!(stack.capacity(), ITEMS);
+        assert_eq!(stack.high_water(), ITEMS);
+
+        stack.reset_high_water();
+        assert_eq!(stack.high_water(), 0);
+        stack.push(Word::data(1)).unwrap();
+        stack.push(Word::data(2)).unwrap();
+        stack.pop();
+        assert_eq!(stack.depth(), 1);
+        assert_eq!(stack.high_water(), 2);
     }
 }
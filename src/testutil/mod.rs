@@ -34,7 +34,8 @@
 //!
 //! This is a forth ui-test doctest. It will be run with `cargo test --all-features`.
 //!
-//! ```rust
+#![cfg_attr(feature = "_force_test_utils", doc = "```rust")]
+#![cfg_attr(not(feature = "_force_test_utils"), doc = "```rust,ignore")]
 //! # use forth3::testutil::blocking_runtest;
 //! #
 //! # blocking_runtest(r#"
@@ -83,6 +84,45 @@ pub fn blocking_runtest_with<T>(forth: &mut Forth<T>, contents: &str) {
     blocking_steps_with(tokd.steps.as_slice(), forth);
 }
 
+/// Run the given forth ui test against the default forth vm, like
+/// [`blocking_runtest`], and return the smallest [`LBForthParams`] it would
+/// have passed with.
+///
+/// The stacks are sized by their high-water marks, the dictionary by how
+/// much of it was used, and the input and output buffers by the longest line
+/// of input and the most output from one line. Their sizes are also printed,
+/// so that they can be copied into frontmatter or a VM's configuration.
+pub fn sizing_runtest(contents: &str) -> LBForthParams {
+    let tokd = tokenize(contents, true).unwrap();
+    let mut forth = LBForth::from_params(tokd.settings, (), Forth::FULL_BUILTINS);
+    let forth = &mut forth.forth;
+    let mut input_buf_elems = 0;
+    let mut output_buf_elems = 0;
+    for Step { input, output: outcome } in &tokd.steps {
+        #[cfg(not(miri))]
+        println!("> {input}");
+        forth.input.fill(input).unwrap();
+        let res = forth.process_line();
+        input_buf_elems = input_buf_elems.max(input.len());
+        output_buf_elems = output_buf_elems.max(forth.output.as_str().len());
+        check_output(res, outcome, forth.output.as_str());
+        forth.output.clear();
+    }
+
+    let stats = forth.memory_stats();
+    let sizes = LBForthParams {
+        data_stack_elems: stats.data_stack.high_water,
+        return_stack_elems: stats.return_stack.high_water,
+        control_stack_elems: stats.call_stack.high_water,
+        input_buf_elems,
+        output_buf_elems,
+        dict_buf_elems: stats.dictionary.used,
+    };
+    #[cfg(not(miri))]
+    println!("minimum sizes: {sizes:#?}");
+    sizes
+}
+
 /// Run the given forth ui test against the async forth vm
 ///
 /// Does accept any/all/none of the following configuration frontmatter (see above
@@ -143,6 +183,97 @@ where
     }
 }
 
+/// Like `async_blockon_runtest_with_dispatcher`, but first cancels each line
+/// at every async builtin it calls.
+///
+/// For each async builtin called by a line, the line is run and then
+/// cancelled (with [`AsyncForth::process_line_until`](crate::AsyncForth::process_line_until))
+/// while that builtin is waiting, checking that the VM reports
+/// [`Error::Cancelled`] and is reset. The line is then run to completion and
+/// checked as usual, so lines must give the same result when they are re-run.
+#[cfg(feature = "async")]
+pub fn async_cancel_runtest_with_dispatcher<T, D>(context: T, dispatcher: D, contents: &str)
+where
+    T: 'static,
+    D: for<'forth> crate::dictionary::AsyncBuiltins<'forth, T>,
+{
+    use crate::leakbox::AsyncLBForth;
+    use std::{rc::Rc, task::Poll};
+
+    let tokd = tokenize(contents, true).unwrap();
+    let state = Rc::new(CancelState::default());
+    let dispatcher = CancelAt { inner: dispatcher, state: state.clone() };
+    let mut forth = AsyncLBForth::from_params(tokd.settings, context, Forth::FULL_BUILTINS, dispatcher);
+    let forth = &mut forth.forth;
+
+    for Step { ref input, output: ref outcome } in tokd.steps {
+        for cancel_at in 0.. {
+            #[cfg(not(miri))]
+            println!("> {input} (cancelling at async builtin {cancel_at})");
+            state.calls.set(0);
+            state.cancel_at.set(cancel_at);
+            state.stuck.set(false);
+            forth.input_mut().fill(input).unwrap();
+            let deadline = futures::future::poll_fn(|_| match state.stuck.get() {
+                true => Poll::Ready(()),
+                false => Poll::Pending,
+            });
+            let res = futures::executor::block_on(forth.process_line_until(deadline));
+            if !state.stuck.get() {
+                // The line didn't get as far as `cancel_at`, so it's done.
+                check_output(res, outcome, forth.output().as_str());
+                forth.output_mut().clear();
+                break;
+            }
+            assert_eq!(res, Err(Error::Cancelled));
+            let vm = forth.vm_mut();
+            assert_eq!(vm.data_stack.depth(), 0);
+            assert_eq!(vm.return_stack.depth(), 0);
+            assert_eq!(vm.call_stack.depth(), 0);
+            forth.output_mut().clear();
+        }
+    }
+}
+
+/// Async builtins that never finish the `cancel_at`th call to one of them.
+#[cfg(feature = "async")]
+struct CancelAt<D> {
+    inner: D,
+    state: std::rc::Rc<CancelState>,
+}
+
+#[cfg(feature = "async")]
+#[derive(Default)]
+struct CancelState {
+    /// How many async builtins the current line has called.
+    calls: core::cell::Cell<usize>,
+    cancel_at: core::cell::Cell<usize>,
+    /// Whether the `cancel_at`th call has been reached.
+    stuck: core::cell::Cell<bool>,
+}
+
+#[cfg(feature = "async")]
+impl<'forth, T, D> crate::dictionary::AsyncBuiltins<'forth, T> for CancelAt<D>
+where
+    T: 'static,
+    D: crate::dictionary::AsyncBuiltins<'forth, T>,
+{
+    type Future = futures::future::Either<D::Future, futures::future::Pending<Result<(), Error>>>;
+
+    const BUILTINS: &'static [crate::dictionary::AsyncBuiltinEntry<T>] = D::BUILTINS;
+
+    fn dispatch_async(&self, id: &crate::fastr::FaStr, forth: &'forth mut Forth<T>) -> Self::Future {
+        let call = self.state.calls.get();
+        self.state.calls.set(call + 1);
+        if call == self.state.cancel_at.get() {
+            self.state.stuck.set(true);
+            futures::future::Either::Right(futures::future::pending())
+        } else {
+            futures::future::Either::Left(self.inner.dispatch_async(id, forth))
+        }
+    }
+}
+
 fn check_output(res: Result<(), Error>, outcome: &Outcome, output: &str) {
     #[cfg(not(miri))]
     println!("< {output}");
@@ -202,11 +333,11 @@ struct Tokenized {
 }
 
 fn tokenize(contents: &str, allow_frontmatter: bool) -> Result<Tokenized, ()> {
-    let mut lines = contents.lines();
+    let lines = contents.lines();
     let mut output = Tokenized::default();
     let mut frontmatter_done = !allow_frontmatter;
 
-    while let Some(line) = lines.next() {
+    for line in lines {
         let (tok, remain) = if let Some(t) = line.trim_start().split_once(" ") {
             t
         } else {
@@ -0,0 +1,48 @@
+// @generated by `crate::rom::generate`. Do not edit.
+pub static TEST_DICT: crate::rom::StaticDict<()> = {
+    use crate::{dictionary::EntryType, rom::{self, StaticEntry}, word::Word};
+    const _: () = assert!(core::mem::size_of::<Word>() == 8);
+    const BUILTINS: &[crate::dictionary::BuiltinEntry<()>] = crate::Forth::<()>::FULL_BUILTINS;
+    static E0: StaticEntry<(), 2> = StaticEntry::new("sq", EntryType::Colon, crate::Forth::<()>::interpret, None, [
+        rom::builtin(BUILTINS, "dup"),
+        rom::builtin(BUILTINS, "*"),
+    ]);
+    static E1: StaticEntry<(), 2> = StaticEntry::new("quad", EntryType::Colon, crate::Forth::<()>::interpret, Some(E0.entry()), [
+        E0.word(),
+        E0.word(),
+    ]);
+    static E2: StaticEntry<(), 4> = StaticEntry::new("greet", EntryType::Colon, crate::Forth::<()>::interpret, Some(E1.entry()), [
+        rom::builtin(BUILTINS, "(write-str)"),
+        Word::data(9),
+        rom::str_word(b"hi there"),
+        rom::str_word(b" "),
+    ]);
+    static E3: StaticEntry<(), 6> = StaticEntry::new("count", EntryType::Colon, crate::Forth::<()>::interpret, Some(E2.entry()), [
+        rom::builtin(BUILTINS, "0"),
+        rom::builtin(BUILTINS, "2d>2r"),
+        rom::builtin(BUILTINS, "i"),
+        rom::builtin(BUILTINS, "."),
+        rom::builtin(BUILTINS, "(jmp-doloop)"),
+        Word::data(-3),
+    ]);
+    static E4: StaticEntry<(), 14> = StaticEntry::new("abs2", EntryType::Colon, crate::Forth::<()>::interpret, Some(E3.entry()), [
+        rom::builtin(BUILTINS, "dup"),
+        rom::builtin(BUILTINS, "0<"),
+        rom::builtin(BUILTINS, "(jump-zero)"),
+        Word::data(4),
+        rom::builtin(BUILTINS, "negate"),
+        rom::builtin(BUILTINS, "(jmp)"),
+        Word::data(8),
+        rom::builtin(BUILTINS, "(write-str)"),
+        Word::data(19),
+        rom::str_word(b"a long s"),
+        rom::str_word(b"tring he"),
+        rom::str_word(b"re "),
+        rom::builtin(BUILTINS, "1"),
+        rom::builtin(BUILTINS, "+"),
+    ]);
+    static E5: StaticEntry<(), 1> = StaticEntry::new("ten", EntryType::Constant, rom::builtin_func(BUILTINS, "(constant)"), Some(E4.entry()), [
+        Word::data(10),
+    ]);
+    rom::StaticDict::new(Some(E5.entry()))
+};
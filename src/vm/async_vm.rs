@@ -1,4 +1,5 @@
 use super::*;
+use core::{future::{poll_fn, Future}, pin::pin};
 
 /// A Forth VM in which some builtin words are implemented by `async fn`s (or
 /// [`Future`]s).
@@ -59,6 +60,7 @@ where
         Self { vm, builtins }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub unsafe fn new(
         dstack_buf: (*mut Word, usize),
         rstack_buf: (*mut Word, usize),
@@ -92,6 +94,7 @@ where
     ///
     /// This method requires the same invariants be upheld as
     /// [`AsyncForth::new`].
+    #[allow(clippy::too_many_arguments)]
     pub unsafe fn fork(
         &mut self,
         new_dict: OwnedDict<T>,
@@ -144,52 +147,125 @@ where
         &mut self.vm.host_ctxt
     }
 
+    /// Attaches an [`AsyncInputSource`] that the VM can pull more input
+    /// from, or detaches the current one if `source` is `None`.
+    ///
+    /// See the [`AsyncInputSource`] documentation for details.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `source` lives at least as long as it is
+    /// attached to the VM, and that it is not accessed other than through the
+    /// VM while attached.
+    pub unsafe fn set_input_source(&mut self, source: Option<NonNull<dyn AsyncInputSource>>) {
+        self.vm.async_input_source = source;
+    }
+
     pub fn add_sync_builtin_static_name(
         &mut self,
         name: &'static str,
-        bi: WordFunc<T>,
+        bi: impl Builtin<T> + 'static,
     ) -> Result<(), Error> {
         self.vm.add_builtin_static_name(name, bi)
     }
 
-    pub fn add_sync_builtin(&mut self, name: &str, bi: WordFunc<T>) -> Result<(), Error> {
+    pub fn add_sync_builtin(
+        &mut self,
+        name: &str,
+        bi: impl Builtin<T> + 'static,
+    ) -> Result<(), Error> {
         self.vm.add_builtin(name, bi)
     }
 
-    #[cfg(test)]
+    /// Adds an async builtin named `name` to the VM's dictionary.
+    ///
+    /// Like the builtins in [`AsyncBuiltins::BUILTINS`], it's run by passing
+    /// its name to [`AsyncBuiltins::dispatch_async`], so the async builtins
+    /// must be able to handle names added at runtime, e.g. by looking them up
+    /// in a table of handlers (see [`AsyncForth::async_builtins_mut`]).
+    pub fn add_async_builtin_static_name(&mut self, name: &'static str) -> Result<(), Error> {
+        let name = unsafe { FaStr::new(name.as_ptr(), name.len()) };
+        self.vm.add_async_builtin_fastr(name)
+    }
+
+    /// Like [`AsyncForth::add_async_builtin_static_name`], but copies `name`
+    /// into the dictionary.
+    pub fn add_async_builtin(&mut self, name: &str) -> Result<(), Error> {
+        let name = self.vm.dict.alloc.bump_str(name)?;
+        self.vm.add_async_builtin_fastr(name)
+    }
+
+    /// Borrows this VM's async builtins.
+    #[inline]
+    #[must_use]
+    pub fn async_builtins(&self) -> &A {
+        &self.builtins
+    }
+
+    /// Mutably borrows this VM's async builtins.
+    #[inline]
+    #[must_use]
+    pub fn async_builtins_mut(&mut self) -> &mut A {
+        &mut self.builtins
+    }
+
+    #[cfg(any(test, feature = "_force_test_utils"))]
+    #[allow(dead_code)]
     pub(crate) fn vm_mut(&mut self) -> &mut Forth<T> {
         &mut self.vm
     }
 
+    /// Interprets the line in the VM's input.
+    ///
+    /// This is cancellation-safe: if the returned future is dropped before
+    /// the line is done (e.g. while an async builtin is waiting), the VM is
+    /// reset just as if the line had failed, and the rest of the line is
+    /// abandoned. See also [`AsyncForth::process_line_until`].
     pub async fn process_line(&mut self) -> Result<(), Error> {
+        let Self { vm, builtins } = self;
+        let mut guard = LineGuard { vm, done: false };
         let res = async {
             loop {
-                match self.vm.start_processing_line()? {
+                match guard.vm.start_processing_line()? {
                     ProcessAction::Done => {
-                        self.vm.output.push_str("ok.\n")?;
+                        guard.vm.output.push_str("ok.\n")?;
                         break Ok(());
                     },
                     ProcessAction::Continue => {},
                     ProcessAction::Execute =>
-                        while self.async_pig().await? != Step::Done {},
+                        while Self::async_pig(guard.vm, builtins).await? != Step::Done {},
                 }
             }
         }.await;
-        match res {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                self.vm.data_stack.clear();
-                self.vm.return_stack.clear();
-                self.vm.call_stack.clear();
-                Err(e)
+        // On error, let the guard reset the VM.
+        guard.done = res.is_ok();
+        res
+    }
+
+    /// Like [`AsyncForth::process_line`], but gives up on the line with
+    /// [`Error::Cancelled`] once `deadline` resolves (e.g. a timer future).
+    ///
+    /// The VM is then reset just as if the line had failed.
+    pub async fn process_line_until<D>(&mut self, deadline: D) -> Result<(), Error>
+    where
+        D: Future<Output = ()>,
+    {
+        let mut line = pin!(self.process_line());
+        let mut deadline = pin!(deadline);
+        poll_fn(|cx| {
+            if let Poll::Ready(res) = line.as_mut().poll(cx) {
+                return Poll::Ready(res);
             }
-        }
+            match deadline.as_mut().poll(cx) {
+                Poll::Ready(()) => Poll::Ready(Err(Error::Cancelled)),
+                Poll::Pending => Poll::Pending,
+            }
+        })
+        .await
     }
 
     // Single step execution (async version).
-    async fn async_pig(&mut self) -> Result<Step, Error> {
-        let Self { ref mut vm, ref builtins } = self;
-
+    async fn async_pig(vm: &mut Forth<T>, builtins: &A) -> Result<Step, Error> {
         let top = match vm.call_stack.try_peek() {
             Ok(t) => t,
             Err(StackError::StackEmpty) => return Ok(Step::Done),
@@ -202,7 +278,11 @@ where
             EntryKind::RuntimeBuiltin => (top.eh.cast::<BuiltinEntry<T>>().as_ref().func)(vm),
             EntryKind::Dictionary => (top.eh.cast::<DictionaryEntry<T>>().as_ref().func)(vm),
             EntryKind::AsyncBuiltin => {
-                builtins.dispatch_async(&top.eh.as_ref().name, vm).await
+                builtins.dispatch_async(&top.eh.as_ref().name(), vm).await
+            },
+            EntryKind::RuntimeAsyncBuiltin => {
+                let name = DictionaryEntry::<T>::name(top.eh.cast());
+                builtins.dispatch_async(&name, vm).await
             },
         }};
 
@@ -213,6 +293,11 @@ where
             Err(Error::PendingCallAgain) => {
                 // ok, just don't pop
             }
+            Err(Error::PendingInput) => {
+                // Wait for the input, then call the word again to pick it up.
+                let res = core::future::poll_fn(|cx| vm.poll_input(cx)).await;
+                vm.input_response = Some(res);
+            }
             Err(e) => return Err(e),
         }
 
@@ -223,3 +308,22 @@ where
         self.vm.release()
     }
 }
+
+/// Resets the VM when a line is abandoned, either because it failed or
+/// because the future running it was dropped.
+struct LineGuard<'a, T: 'static> {
+    vm: &'a mut Forth<T>,
+    done: bool,
+}
+
+impl<T: 'static> Drop for LineGuard<'_, T> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.vm.data_stack.clear();
+            self.vm.return_stack.clear();
+            self.vm.call_stack.clear();
+            self.vm.input_request = None;
+            self.vm.input_response = None;
+        }
+    }
+}
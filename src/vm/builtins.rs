@@ -1,19 +1,40 @@
-use core::{fmt::Write, mem::size_of, ptr::NonNull, marker::PhantomData};
+use core::{fmt::Write, mem::size_of, marker::PhantomData};
 
 use crate::{
-    dictionary::{BuiltinEntry, DictionaryEntry, EntryHeader, EntryKind, DictLocation},
+    dictionary::{BuiltinEntry, DictionaryEntry, EntryHeader, EntryKind, EntryType, DictLocation},
     fastr::comptime_fastr,
-    vm::TmpFaStr,
+    vm::{sandbox::Access, TmpFaStr},
     word::Word,
     Error, Forth, Mode, ReplaceErr, Lookup,
 };
 
+pub mod block;
+
+pub mod channel;
+
 #[cfg(feature = "floats")]
 pub mod floats;
 
+#[cfg(feature = "use-std")]
+pub mod file;
+
+#[cfg(feature = "use-std")]
+pub mod include;
+
+pub mod input;
+
+pub mod see;
+
+pub mod task;
+
+pub mod wordlist;
+
 // NOTE: This macro exists because we can't have const constructors that include
 // "mut" items, which unfortunately covers things like `fn(&mut T)`. Use a macro
 // until this is resolved.
+//
+// Builtins can also be defined with `#[forth3::macros::builtin]` (with the
+// `macros` feature).
 #[macro_export]
 macro_rules! builtin {
     ($name:literal, $func:expr) => {
@@ -129,11 +150,79 @@ impl<T: 'static> Forth<T> {
         builtin!("u.", Self::unsigned_pop_print),
         builtin_if_feature!("floats", "f.", Self::float_pop_print),
         //
+        // Input operations
+        //
+        builtin!("refill", Self::refill),
+        builtin!("key", Self::key),
+        builtin!("key?", Self::key_avail),
+        builtin!("accept", Self::accept),
+        builtin!("source", Self::source),
+        builtin!("evaluate", Self::evaluate_word),
+        builtin_if_feature!("use-std", "include", Self::include),
+        builtin_if_feature!("use-std", "included", Self::included),
+        builtin_if_feature!("use-std", "require", Self::require),
+        builtin_if_feature!("use-std", "required", Self::required),
+        //
+        // Task operations
+        //
+        builtin!("pause", Self::pause),
+        builtin!("task-id", Self::task_id),
+        builtin!("spawn", Self::spawn),
+        builtin!("kill", Self::kill),
+        //
+        // Channel operations
+        //
+        builtin!("send", Self::send),
+        builtin!("recv", Self::recv),
+        builtin!("try-recv", Self::try_recv_word),
+        //
+        // Block operations
+        //
+        builtin!("block", Self::block),
+        builtin!("buffer", Self::buffer),
+        builtin!("update", Self::update),
+        builtin!("save-buffers", Self::save_buffers),
+        builtin!("flush", Self::flush),
+        builtin!("load", Self::load),
+        builtin!("list", Self::list),
+        //
+        // File access operations
+        //
+        builtin_if_feature!("use-std", "r/o", Self::fam_read_only),
+        builtin_if_feature!("use-std", "w/o", Self::fam_write_only),
+        builtin_if_feature!("use-std", "r/w", Self::fam_read_write),
+        builtin_if_feature!("use-std", "bin", Self::fam_bin),
+        builtin_if_feature!("use-std", "open-file", Self::open_file),
+        builtin_if_feature!("use-std", "create-file", Self::create_file),
+        builtin_if_feature!("use-std", "close-file", Self::close_file),
+        builtin_if_feature!("use-std", "delete-file", Self::delete_file),
+        builtin_if_feature!("use-std", "file-size", Self::file_size),
+        builtin_if_feature!("use-std", "read-file", Self::read_file),
+        builtin_if_feature!("use-std", "read-line", Self::read_line),
+        builtin_if_feature!("use-std", "write-file", Self::write_file),
+        builtin_if_feature!("use-std", "write-line", Self::write_line),
+        //
         // Define/forget
         //
         builtin!(":", Self::colon),
         builtin!("forget", Self::forget),
         //
+        // Wordlists
+        //
+        builtin!("forth-wordlist", Self::forth_wordlist),
+        builtin!("wordlist", Self::wordlist),
+        builtin!("get-order", Self::get_order),
+        builtin!("set-order", Self::set_order),
+        builtin!("get-current", Self::get_current),
+        builtin!("set-current", Self::set_current),
+        builtin!("definitions", Self::definitions),
+        builtin!("search-wordlist", Self::search_wordlist),
+        builtin!("only", Self::only),
+        builtin!("also", Self::also),
+        builtin!("previous", Self::previous),
+        builtin!("forth", Self::forth),
+        builtin!("vocabulary", Self::create_vocabulary),
+        //
         // Stack/Retstack operations
         //
         builtin!("d>r", Self::data_to_return_stack),
@@ -169,6 +258,8 @@ impl<T: 'static> Forth<T> {
         builtin!("dict", Self::list_dict),
         builtin!(".s", Self::list_stack),
         builtin!("free", Self::dict_free),
+        builtin!(".stats", Self::dot_stats),
+        builtin!("see", Self::see),
         //
         // Other
         //
@@ -186,10 +277,14 @@ impl<T: 'static> Forth<T> {
         builtin!("(constant)", Self::constant),
         // NOTE: REQUIRED for `variable` or `array`
         builtin!("(variable)", Self::variable),
+        // NOTE: REQUIRED for `channel`
+        builtin!("(channel)", Self::channel),
+        // NOTE: REQUIRED for `vocabulary`
+        builtin!("(vocabulary)", Self::vocabulary),
     ];
 
     pub fn dict_free(&mut self) -> Result<(), Error> {
-        let capa = self.dict.alloc.capacity();
+        let capa = self.dict.alloc.limit();
         let used = self.dict.alloc.used();
         let free = capa - used;
         writeln!(
@@ -228,7 +323,7 @@ impl<T: 'static> Forth<T> {
         let Self { output, dict, .. } = self;
         output.write_str("dictionary: ")?;
         for item in dict.entries() {
-            output.write_str(unsafe { item.entry().as_ref() }.hdr.name.as_str())?;
+            output.write_str(unsafe { DictionaryEntry::name(item.entry()) }.as_str())?;
             if let DictLocation::Parent(_) = item {
                 // indicate that this binding is inherited from a parent.
                 // XXX(eliza): i was initially gonna add "(inherited)" but that
@@ -256,6 +351,7 @@ impl<T: 'static> Forth<T> {
     pub fn byte_var_load(&mut self) -> Result<(), Error> {
         let w = self.data_stack.try_pop()?;
         let ptr = unsafe { w.ptr.cast::<u8>() };
+        self.check_access(ptr, 1, Access::Read)?;
         let val = unsafe { Word::data(i32::from(ptr.read())) };
         self.data_stack.push(val)?;
         Ok(())
@@ -264,8 +360,10 @@ impl<T: 'static> Forth<T> {
     pub fn byte_var_store(&mut self) -> Result<(), Error> {
         let w_addr = self.data_stack.try_pop()?;
         let w_val = self.data_stack.try_pop()?;
+        let ptr = unsafe { w_addr.ptr.cast::<u8>() };
+        self.check_access(ptr, 1, Access::Write)?;
         unsafe {
-            w_addr.ptr.cast::<u8>().write((w_val.data & 0xFF) as u8);
+            ptr.write((w_val.data & 0xFF) as u8);
         }
         Ok(())
     }
@@ -274,6 +372,7 @@ impl<T: 'static> Forth<T> {
     pub fn var_load(&mut self) -> Result<(), Error> {
         let w = self.data_stack.try_pop()?;
         let ptr = unsafe { w.ptr.cast::<Word>() };
+        self.check_word_access(ptr, Access::Read)?;
         let val = unsafe { ptr.read() };
         self.data_stack.push(val)?;
         Ok(())
@@ -283,8 +382,10 @@ impl<T: 'static> Forth<T> {
     pub fn var_store(&mut self) -> Result<(), Error> {
         let w_addr = self.data_stack.try_pop()?;
         let w_val = self.data_stack.try_pop()?;
+        let ptr = unsafe { w_addr.ptr.cast::<Word>() };
+        self.check_word_access(ptr, Access::Write)?;
         unsafe {
-            w_addr.ptr.cast::<Word>().write(w_val);
+            ptr.write(w_val);
         }
         Ok(())
     }
@@ -344,8 +445,9 @@ impl<T: 'static> Forth<T> {
             // forget it by zeroing out the entry in the current dictionary.
             DictLocation::Current(defn) => {
                 // NOTE: We use the *name* pointer for rewinding, as we allocate the name before the item.
-                let name_ptr = unsafe { defn.as_ref().hdr.name.as_ptr().cast_mut() };
-                self.dict.tail = unsafe { defn.as_ref().link };
+                let name_ptr = unsafe { DictionaryEntry::name(defn).as_ptr().cast_mut() };
+                self.release_block_buffers(name_ptr)?;
+                self.dict.tail = self.dict.resolve_link(unsafe { defn.as_ref().link });
                 let addr = defn.as_ptr();
                 let name_contains = self.dict.alloc.contains(name_ptr.cast());
                 let contains = self.dict.alloc.contains(addr.cast());
@@ -674,7 +776,8 @@ impl<T: 'static> Forth<T> {
 
     /// # Add (`+`)
     ///
-    /// ```rust
+    #[cfg_attr(feature = "_force_test_utils", doc = "```rust")]
+    #[cfg_attr(not(feature = "_force_test_utils"), doc = "```rust,ignore")]
     /// # use forth3::testutil::blocking_runtest;
     /// #
     /// # blocking_runtest(r#"
@@ -682,6 +785,7 @@ impl<T: 'static> Forth<T> {
     /// > .
     /// < 3 ok.
     /// # "#)
+    /// ```
     pub fn add(&mut self) -> Result<(), Error> {
         let a = self.data_stack.try_pop()?;
         let b = self.data_stack.try_pop()?;
@@ -801,7 +905,7 @@ impl<T: 'static> Forth<T> {
                         unsafe {
                             dict_base.as_ptr().write(DictionaryEntry {
                                 hdr: EntryHeader {
-                                    name,
+                                    name: self.dict.entry_name(name, dict_base),
                                     kind: EntryKind::Dictionary,
                                     len,
                                     _pd: PhantomData,
@@ -810,7 +914,9 @@ impl<T: 'static> Forth<T> {
                                 // Use `find_word`?
                                 func: Self::interpret,
                                 // Don't link until we know we have a "good" entry!
-                                link: self.dict.tail.take(),
+                                link: self.dict.link_to(self.dict.tail),
+                                wordlist: self.dict.current,
+                                ty: EntryType::Colon,
                                 parameter_field: [],
                             });
                         }
@@ -836,7 +942,7 @@ impl<T: 'static> Forth<T> {
 
         // Now we need to figure out how many words our inline string takes up
         let word_size = size_of::<Word>();
-        let len_words = 1 + ((usize::from(len_u16) + (word_size - 1)) / word_size);
+        let len_words = 1 + usize::from(len_u16).div_ceil(word_size);
         let len_and_str = parent.get_next_n_words(len_words as u16)?;
         unsafe {
             // Skip the "len" word
@@ -890,17 +996,15 @@ impl<T: 'static> Forth<T> {
 
     pub fn execute(&mut self) -> Result<(), Error> {
         let w = self.data_stack.try_pop()?;
+        // Safety: only checked if the VM is sandboxed, otherwise YOLO :D
+        let eh = self.check_xt(w)?;
         // pop the execute word off the stack
         self.call_stack.pop();
-        unsafe {
-            // Safety: YOLO :D
-            let eh = w.ptr.cast::<EntryHeader<T>>();
-            self.call_stack.push(crate::vm::CallContext {
-                eh: NonNull::new_unchecked(eh),
-                len: (*eh).len,
-                idx: 0,
-            })?;
-        };
+        self.call_stack.push(crate::vm::CallContext {
+            eh,
+            len: unsafe { eh.as_ref() }.len,
+            idx: 0,
+        })?;
 
         Err(Error::PendingCallAgain)
     }
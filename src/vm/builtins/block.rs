@@ -0,0 +1,176 @@
+use core::{fmt::Write, ptr::NonNull};
+
+use crate::{
+    block::{BlockBuffer, BlockDevice, BlockError, Blocks, BLOCK_LINE_LEN, BLOCK_SIZE},
+    input::InputError,
+    vm::LineSource,
+    word::Word,
+    Error, Forth, ReplaceErr,
+};
+
+impl<T: 'static> Forth<T> {
+    /// Attaches a [`BlockDevice`] for the block words to use, replacing any
+    /// previous one (without saving its buffers).
+    ///
+    /// `buffers` is where blocks are held in memory while in use. It needn't
+    /// be initialized, and may be allocated from the VM's dictionary with
+    /// [`Forth::alloc_block_buffers`].
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `device` and `buffers` live at least as
+    /// long as they are attached to the VM, and that they are not accessed
+    /// other than through the VM while attached.
+    pub unsafe fn set_block_device(
+        &mut self,
+        device: NonNull<dyn BlockDevice>,
+        buffers: (*mut BlockBuffer, usize),
+    ) -> Result<(), Error> {
+        self.blocks = Some(Blocks::new(device, buffers).ok_or(Error::NoBlockBuffers)?);
+        Ok(())
+    }
+
+    /// Saves any modified block buffers, then detaches the block device.
+    pub fn remove_block_device(&mut self) -> Result<(), Error> {
+        if let Some(blocks) = self.blocks.as_mut() {
+            blocks.flush()?;
+        }
+        self.blocks = None;
+        Ok(())
+    }
+
+    /// Allocates `count` block buffers from the VM's dictionary, to pass to
+    /// [`Forth::set_block_device`].
+    ///
+    /// The buffers don't belong to any dictionary entry. If the dictionary is
+    /// rewound past them, by `forget`ting a word defined before them or by
+    /// [`Forth::restore`], the block device is saved and detached first.
+    pub fn alloc_block_buffers(
+        &mut self,
+        count: usize,
+    ) -> Result<(*mut BlockBuffer, usize), Error> {
+        let mut first = None;
+        for _ in 0..count {
+            let buf = self.dict.alloc.bump::<BlockBuffer>()?;
+            first.get_or_insert(buf);
+        }
+        let first = first.ok_or(Error::NoBlockBuffers)?;
+        Ok((first.as_ptr(), count))
+    }
+
+    /// Saves and detaches the block device if its buffers are in the part of
+    /// the dictionary from `from` on, before that part is reused.
+    pub(crate) fn release_block_buffers(&mut self, from: *const u8) -> Result<(), Error> {
+        let Some(blocks) = self.blocks.as_ref() else {
+            return Ok(());
+        };
+        let (start, len) = blocks.buffers();
+        let (start, from, end) = (start as usize, from as usize, self.dict.alloc.end as usize);
+        if start + len > from && start < end {
+            self.remove_block_device()?;
+        }
+        Ok(())
+    }
+
+    fn blocks(&mut self) -> Result<&mut Blocks, Error> {
+        self.blocks.as_mut().ok_or(Error::NoBlockDevice)
+    }
+
+    fn pop_block_number(&mut self) -> Result<u32, Error> {
+        let block = unsafe { self.data_stack.try_pop()?.data };
+        u32::try_from(block).replace_err(Error::Block(BlockError::OutOfRange))
+    }
+
+    /// `block ( u -- addr )`
+    pub fn block(&mut self) -> Result<(), Error> {
+        let block = self.pop_block_number()?;
+        let addr = self.blocks()?.assign(block, true)?;
+        self.data_stack.push(Word::ptr(addr))?;
+        Ok(())
+    }
+
+    /// `buffer ( u -- addr )`
+    ///
+    /// Like `block`, but doesn't read the block from the device if it isn't
+    /// already in a buffer, so the buffer's contents are unspecified.
+    pub fn buffer(&mut self) -> Result<(), Error> {
+        let block = self.pop_block_number()?;
+        let addr = self.blocks()?.assign(block, false)?;
+        self.data_stack.push(Word::ptr(addr))?;
+        Ok(())
+    }
+
+    /// `update ( -- )`
+    pub fn update(&mut self) -> Result<(), Error> {
+        if self.blocks()?.update() {
+            Ok(())
+        } else {
+            Err(Error::NoCurrentBlock)
+        }
+    }
+
+    /// `save-buffers ( -- )`
+    pub fn save_buffers(&mut self) -> Result<(), Error> {
+        self.blocks()?.save_all()?;
+        Ok(())
+    }
+
+    /// `flush ( -- )`
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.blocks()?.flush()?;
+        Ok(())
+    }
+
+    /// `load ( u -- )`
+    ///
+    /// Interprets block `u` as Forth source, one [`BLOCK_LINE_LEN`] character
+    /// line at a time.
+    pub fn load(&mut self) -> Result<(), Error> {
+        let block = self.pop_block_number()?;
+        self.interpret_line_source(LineSource::Block { block, line: 0 })
+    }
+
+    /// Reads line `line` of the block being `load`ed into the input buffer.
+    pub(crate) fn read_block_line(
+        &mut self,
+        block: u32,
+        line: usize,
+    ) -> Result<Option<usize>, Error> {
+        if line >= BLOCK_SIZE / BLOCK_LINE_LEN {
+            return Ok(None);
+        }
+        // Find the block's buffer again for each line, as the lines before
+        // may have reused it for another block.
+        let addr = self.blocks()?.assign(block, true)?;
+        let src = unsafe {
+            core::slice::from_raw_parts(addr.add(line * BLOCK_LINE_LEN), BLOCK_LINE_LEN)
+        };
+        self.input
+            .raw_buf_mut()
+            .get_mut(..BLOCK_LINE_LEN)
+            .ok_or(InputError::LineTooLong)?
+            .copy_from_slice(src);
+        if let Some((LineSource::Block { line, .. }, _)) = self.nested_source.as_mut() {
+            *line += 1;
+        }
+        Ok(Some(BLOCK_LINE_LEN))
+    }
+
+    /// `list ( u -- )`
+    pub fn list(&mut self) -> Result<(), Error> {
+        let block = self.pop_block_number()?;
+        let addr = self.blocks()?.assign(block, true)?;
+        let data = unsafe { core::slice::from_raw_parts(addr.cast_const(), BLOCK_SIZE) };
+        writeln!(&mut self.output, "Block {block}")?;
+        for (i, line) in data.chunks(BLOCK_LINE_LEN).enumerate() {
+            let len = line.iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1);
+            write!(&mut self.output, "{i:2} ")?;
+            for &b in &line[..len] {
+                let b = if b.is_ascii_graphic() { b } else { b' ' };
+                self.output.push_bstr(&[b])?;
+            }
+            self.output.push_str("\n")?;
+        }
+        Ok(())
+    }
+}
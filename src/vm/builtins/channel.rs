@@ -0,0 +1,222 @@
+use core::{mem::size_of, num::NonZeroU16};
+
+use portable_atomic::{AtomicUsize, Ordering};
+
+use crate::{dictionary::EntryType, sched::TaskRequest, word::Word, Error, Forth, ReplaceErr};
+
+/// The start of a channel's memory, which is followed by `capacity` words
+/// holding the values in the channel.
+///
+/// Channels are usually created before a VM is forked, so they may be used
+/// by several VMs (and threads) at once.
+#[repr(C)]
+struct Channel {
+    lock: AtomicUsize,
+    capacity: usize,
+    /// The slot of the oldest value.
+    head: usize,
+    len: usize,
+}
+
+/// How many words a channel's header takes up.
+const HEADER_WORDS: usize = size_of::<Channel>() / size_of::<Word>();
+
+impl Channel {
+    // Safety: for all of these, `chan` must point to a channel created by
+    // `channel`. Other than `lock`, its fields are only accessed through the
+    // pointer while it's locked, as other VMs may be spinning on the lock.
+
+    unsafe fn lock(chan: *mut Channel) {
+        while (*chan)
+            .lock
+            .compare_exchange_weak(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+    }
+
+    unsafe fn unlock(chan: *mut Channel) {
+        (*chan).lock.store(0, Ordering::Release);
+    }
+
+    unsafe fn slot(chan: *mut Channel, idx: usize) -> *mut Word {
+        chan.cast::<Word>().add(HEADER_WORDS + idx % (*chan).capacity)
+    }
+
+    unsafe fn try_send(chan: *mut Channel, val: Word) -> bool {
+        Self::lock(chan);
+        let sent = (*chan).len < (*chan).capacity;
+        if sent {
+            Self::slot(chan, (*chan).head + (*chan).len).write(val);
+            (*chan).len += 1;
+        }
+        Self::unlock(chan);
+        sent
+    }
+
+    unsafe fn try_recv(chan: *mut Channel) -> Option<Word> {
+        Self::lock(chan);
+        let val = if (*chan).len > 0 {
+            let val = Self::slot(chan, (*chan).head).read();
+            (*chan).head = ((*chan).head + 1) % (*chan).capacity;
+            (*chan).len -= 1;
+            Some(val)
+        } else {
+            None
+        };
+        Self::unlock(chan);
+        val
+    }
+}
+
+impl<T: 'static> Forth<T> {
+    /// `channel NAME CAPACITY`
+    pub(crate) fn munch_channel(&mut self, _len: &mut u16) -> Result<u16, Error> {
+        let name = self.munch_name()?;
+
+        self.input.advance();
+        let capacity = self
+            .input
+            .cur_word()
+            .ok_or(Error::ColonCompileMissingName)?
+            .parse::<NonZeroU16>()
+            .replace_err(Error::BadArrayLength)?;
+        if usize::from(capacity.get()) + HEADER_WORDS > usize::from(u16::MAX) {
+            return Err(Error::BadArrayLength);
+        }
+
+        let header = [0, usize::from(capacity.get()), 0, 0];
+        let mut entry = self.dict.build_entry()?;
+        for word in header {
+            entry = entry.write_word(Word::ptr_data(word as isize))?;
+        }
+        for _ in 0..capacity.get() {
+            entry = entry.write_word(Word::data(0))?;
+        }
+        // Unlike a variable, a channel isn't copied into a fork's dictionary
+        // when it's used, so that it's shared with the parent.
+        entry.finish(name, EntryType::Channel, Self::channel);
+        Ok(0)
+    }
+
+    /// The runtime of a channel created by `channel`, which pushes its
+    /// address.
+    pub fn channel(&mut self) -> Result<(), Error> {
+        self.variable()
+    }
+
+    /// `send ( val chan -- )`
+    ///
+    /// If the channel is full, a task (see [`crate::sched`]) waits until it
+    /// isn't, and any other VM fails with [`Error::ChannelFull`].
+    pub fn send(&mut self) -> Result<(), Error> {
+        if self.try_send()? {
+            return Ok(());
+        }
+        self.wait_for_channel(Error::ChannelFull)
+    }
+
+    /// `recv ( chan -- val )`
+    ///
+    /// If the channel is empty, a task (see [`crate::sched`]) waits until it
+    /// isn't, and any other VM fails with [`Error::ChannelEmpty`].
+    pub fn recv(&mut self) -> Result<(), Error> {
+        if self.try_recv()? {
+            return Ok(());
+        }
+        self.wait_for_channel(Error::ChannelEmpty)
+    }
+
+    /// `try-recv ( chan -- val true | false )`
+    pub fn try_recv_word(&mut self) -> Result<(), Error> {
+        if self.try_recv()? {
+            self.data_stack.push(Word::data(-1))?;
+        } else {
+            self.data_stack.try_pop()?;
+            self.data_stack.push(Word::data(0))?;
+        }
+        Ok(())
+    }
+
+    /// `send-async ( val chan -- )`, waiting for there to be room in the
+    /// channel.
+    ///
+    /// This is for an [`AsyncForth`](crate::AsyncForth)'s
+    /// [`AsyncBuiltins`](crate::dictionary::AsyncBuiltins) to call, so that
+    /// other tasks can run while it waits. It needs a name of its own, as
+    /// `send` is always found first.
+    ///
+    /// Note that this busy-waits: it yields to the executor and asks to be
+    /// polled again straight away, trying again each time it's polled, rather
+    /// than being woken by a `recv`. Channels live in the dictionary, which
+    /// may be saved in an image, so they can't hold a waker.
+    #[cfg(feature = "async")]
+    pub async fn send_async(&mut self) -> Result<(), Error> {
+        while !self.try_send()? {
+            yield_now().await;
+        }
+        Ok(())
+    }
+
+    /// `recv-async ( chan -- val )`, waiting for there to be a value in the
+    /// channel. Like [`Forth::send_async`], this busy-waits.
+    #[cfg(feature = "async")]
+    pub async fn recv_async(&mut self) -> Result<(), Error> {
+        while !self.try_recv()? {
+            yield_now().await;
+        }
+        Ok(())
+    }
+
+    /// Sends the value under the channel on the stack, popping both if there
+    /// was room for it.
+    fn try_send(&mut self) -> Result<bool, Error> {
+        let chan = unsafe { self.data_stack.try_peek_back_n(0)?.ptr.cast::<Channel>() };
+        self.check_channel(chan.cast())?;
+        let val = self.data_stack.try_peek_back_n(1)?;
+        if !unsafe { Channel::try_send(chan, val) } {
+            return Ok(false);
+        }
+        self.data_stack.try_pop()?;
+        self.data_stack.try_pop()?;
+        Ok(true)
+    }
+
+    /// Replaces the channel on the stack with a value received from it, if
+    /// there was one.
+    fn try_recv(&mut self) -> Result<bool, Error> {
+        let chan = unsafe { self.data_stack.try_peek()?.ptr.cast::<Channel>() };
+        self.check_channel(chan.cast())?;
+        let Some(val) = (unsafe { Channel::try_recv(chan) }) else {
+            return Ok(false);
+        };
+        self.data_stack.try_pop()?;
+        self.data_stack.push(val)?;
+        Ok(true)
+    }
+
+    /// Makes a task try the current word again after letting other tasks run,
+    /// or fails with `error` if the VM isn't a task.
+    fn wait_for_channel(&mut self, error: Error) -> Result<(), Error> {
+        if self.task.id == 0 {
+            return Err(error);
+        }
+        self.task.request = Some(TaskRequest::Pause);
+        Err(Error::PendingCallAgain)
+    }
+}
+
+/// Returns `Pending` once, after asking to be polled again.
+#[cfg(feature = "async")]
+fn yield_now() -> impl core::future::Future<Output = ()> {
+    let mut yielded = false;
+    core::future::poll_fn(move |cx| {
+        if yielded {
+            return core::task::Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        core::task::Poll::Pending
+    })
+}
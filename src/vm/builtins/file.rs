@@ -0,0 +1,358 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    vec::Vec,
+};
+
+use crate::{vm::sandbox::Access, word::Word, Error, Forth, ReplaceErr};
+
+/// File access methods, as pushed by `r/o`, `w/o` and `r/w`.
+const FAM_READ: i32 = 1;
+const FAM_WRITE: i32 = 2;
+
+/// Why a file-access word failed.
+///
+/// The file-access words report failures by pushing a nonzero "ior" rather
+/// than returning an [`Error`], so that Forth programs can handle them. The
+/// ior is the value of one of these variants.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(i32)]
+pub enum FileError {
+    NotFound = 1,
+    PermissionDenied = 2,
+    AlreadyExists = 3,
+    Other = 4,
+    /// The fileid isn't one of the VM's open files.
+    BadFileId = 5,
+}
+
+/// The host-side table of files opened by Forth code.
+///
+/// A file's id is its index in the table plus one, so that a fileid is never
+/// zero.
+#[derive(Default)]
+pub(crate) struct FileTable {
+    files: Vec<Option<File>>,
+}
+
+impl FileError {
+    /// The ior pushed for this error.
+    pub fn ior(self) -> i32 {
+        self as i32
+    }
+
+    /// Returns the error an `ior` was pushed for, or `None` if it isn't one.
+    pub fn from_ior(ior: i32) -> Option<Self> {
+        [
+            Self::NotFound,
+            Self::PermissionDenied,
+            Self::AlreadyExists,
+            Self::Other,
+            Self::BadFileId,
+        ]
+        .into_iter()
+        .find(|e| e.ior() == ior)
+    }
+}
+
+impl From<io::Error> for FileError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => Self::NotFound,
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            io::ErrorKind::AlreadyExists => Self::AlreadyExists,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl FileTable {
+    fn insert(&mut self, file: File) -> Result<i32, Error> {
+        let idx = match self.files.iter().position(Option::is_none) {
+            Some(idx) => {
+                self.files[idx] = Some(file);
+                idx
+            }
+            None => {
+                self.files.push(Some(file));
+                self.files.len() - 1
+            }
+        };
+        i32::try_from(idx + 1).replace_err(Error::UsizeToWordInvalid(idx + 1))
+    }
+
+    fn slot(&mut self, fileid: i32) -> Option<&mut Option<File>> {
+        let idx = usize::try_from(fileid).ok()?.checked_sub(1)?;
+        self.files.get_mut(idx)
+    }
+
+    fn get(&mut self, fileid: i32) -> Result<&mut File, FileError> {
+        self.slot(fileid)
+            .and_then(Option::as_mut)
+            .ok_or(FileError::BadFileId)
+    }
+
+    fn remove(&mut self, fileid: i32) -> Result<File, FileError> {
+        self.slot(fileid)
+            .and_then(Option::take)
+            .ok_or(FileError::BadFileId)
+    }
+}
+
+impl<T: 'static> Forth<T> {
+    /// Allows (or disallows) Forth code running on this VM to access the
+    /// host's filesystem with the file-access words (`open-file` and
+    /// friends).
+    ///
+    /// File access is disabled by default, and the file-access words (and
+    /// `include` and friends) fail with [`Error::FileAccessDisabled`] until it
+    /// is enabled. Disabling file access closes any files that are still open.
+    ///
+    /// This only limits Forth code: the host can still call
+    /// [`Forth::include_file`] itself.
+    pub fn set_file_access(&mut self, enabled: bool) {
+        self.files = enabled.then(|| self.files.take().unwrap_or_default());
+    }
+
+    pub(crate) fn file_table(&mut self) -> Result<&mut FileTable, Error> {
+        self.files.as_mut().ok_or(Error::FileAccessDisabled)
+    }
+
+    /// Pops a `( c-addr u )` pair naming a file.
+    fn pop_file_name(&mut self) -> Result<std::string::String, Error> {
+        let bytes = self.pop_bytes(Access::Read)?;
+        let name = core::str::from_utf8(bytes.as_slice()).replace_err(Error::BadStrLiteral)?;
+        Ok(name.into())
+    }
+
+    /// Pops a `( c-addr u )` pair, which is going to be accessed as `access`.
+    fn pop_bytes(&mut self, access: Access) -> Result<ForthBytes, Error> {
+        let len: usize = self.data_stack.try_pop()?.try_into()?;
+        let addr = unsafe { self.data_stack.try_pop()?.ptr.cast::<u8>() };
+        self.check_access(addr, len, access)?;
+        Ok(ForthBytes { addr, len, access })
+    }
+
+    fn push_ior(&mut self, res: Result<(), FileError>) -> Result<(), Error> {
+        let ior = res.err().map_or(0, FileError::ior);
+        self.data_stack.push(Word::data(ior))?;
+        Ok(())
+    }
+
+    fn push_fileid(&mut self, res: io::Result<File>) -> Result<(), Error> {
+        match res {
+            Ok(file) => {
+                let fileid = self.file_table()?.insert(file)?;
+                self.data_stack.push(Word::data(fileid))?;
+                self.push_ior(Ok(()))
+            }
+            Err(e) => {
+                self.data_stack.push(Word::data(0))?;
+                self.push_ior(Err(e.into()))
+            }
+        }
+    }
+
+    /// `r/o ( -- fam )`
+    pub fn fam_read_only(&mut self) -> Result<(), Error> {
+        self.data_stack.push(Word::data(FAM_READ))?;
+        Ok(())
+    }
+
+    /// `w/o ( -- fam )`
+    pub fn fam_write_only(&mut self) -> Result<(), Error> {
+        self.data_stack.push(Word::data(FAM_WRITE))?;
+        Ok(())
+    }
+
+    /// `r/w ( -- fam )`
+    pub fn fam_read_write(&mut self) -> Result<(), Error> {
+        self.data_stack.push(Word::data(FAM_READ | FAM_WRITE))?;
+        Ok(())
+    }
+
+    /// `bin ( fam1 -- fam2 )`
+    ///
+    /// Files are always accessed in binary mode, so this does nothing.
+    pub fn fam_bin(&mut self) -> Result<(), Error> {
+        self.data_stack.try_peek()?;
+        Ok(())
+    }
+
+    fn pop_fam(&mut self) -> Result<OpenOptions, Error> {
+        let fam = unsafe { self.data_stack.try_pop()?.data };
+        let mut opts = OpenOptions::new();
+        opts.read(fam & FAM_READ != 0).write(fam & FAM_WRITE != 0);
+        Ok(opts)
+    }
+
+    /// `open-file ( c-addr u fam -- fileid ior )`
+    pub fn open_file(&mut self) -> Result<(), Error> {
+        self.file_table()?;
+        let opts = self.pop_fam()?;
+        let name = self.pop_file_name()?;
+        self.push_fileid(opts.open(name))
+    }
+
+    /// `create-file ( c-addr u fam -- fileid ior )`
+    ///
+    /// Creates the file, or truncates it if it already exists.
+    pub fn create_file(&mut self) -> Result<(), Error> {
+        self.file_table()?;
+        let mut opts = self.pop_fam()?;
+        let name = self.pop_file_name()?;
+        self.push_fileid(opts.write(true).create(true).truncate(true).open(name))
+    }
+
+    /// `close-file ( fileid -- ior )`
+    pub fn close_file(&mut self) -> Result<(), Error> {
+        let fileid = unsafe { self.data_stack.try_pop()?.data };
+        // Writes aren't buffered, so there's nothing left to fail on close.
+        let res = self.file_table()?.remove(fileid).map(drop);
+        self.push_ior(res)
+    }
+
+    /// `delete-file ( c-addr u -- ior )`
+    pub fn delete_file(&mut self) -> Result<(), Error> {
+        self.file_table()?;
+        let name = self.pop_file_name()?;
+        let res = fs::remove_file(name).map_err(FileError::from);
+        self.push_ior(res)
+    }
+
+    /// `file-size ( fileid -- ud ior )`
+    pub fn file_size(&mut self) -> Result<(), Error> {
+        let fileid = unsafe { self.data_stack.try_pop()?.data };
+        let (size, res) = match self.file_table()?.get(fileid).map(|f| f.metadata()) {
+            Ok(Ok(meta)) => (meta.len(), Ok(())),
+            Ok(Err(e)) => (0, Err(e.into())),
+            Err(e) => (0, Err(e)),
+        };
+        // `ud` is a double-cell number: low cell first, then high cell.
+        self.data_stack.push(Word::data(size as u32 as i32))?;
+        self.data_stack.push(Word::data((size >> 32) as u32 as i32))?;
+        self.push_ior(res)
+    }
+
+    /// `read-file ( c-addr u1 fileid -- u2 ior )`
+    pub fn read_file(&mut self) -> Result<(), Error> {
+        let fileid = unsafe { self.data_stack.try_pop()?.data };
+        let mut buf = self.pop_bytes(Access::Write)?;
+        let (read, res) = match self.file_table()?.get(fileid) {
+            Ok(file) => read_fully(file, buf.as_mut_slice()),
+            Err(e) => (0, Err(e)),
+        };
+        self.data_stack.push(Word::try_from(read)?)?;
+        self.push_ior(res)
+    }
+
+    /// `read-line ( c-addr u1 fileid -- u2 flag ior )`
+    ///
+    /// Reads at most `u1` characters of the next line, not including the line
+    /// terminator. `flag` is false if the end of the file was reached.
+    pub fn read_line(&mut self) -> Result<(), Error> {
+        let fileid = unsafe { self.data_stack.try_pop()?.data };
+        let mut buf = self.pop_bytes(Access::Write)?;
+        let file = match self.file_table()?.get(fileid) {
+            Ok(file) => file,
+            Err(e) => {
+                self.data_stack.push(Word::data(0))?;
+                self.data_stack.push(Word::data(0))?;
+                return self.push_ior(Err(e));
+            }
+        };
+        let buf = buf.as_mut_slice();
+
+        // Read enough to find a "\r\n" after a full buffer, then seek back
+        // to just after the line terminator.
+        let mut chunk = std::vec![0; buf.len() + 2];
+        let (read, res) = read_fully(file, &mut chunk);
+        let line_end = chunk[..read].iter().position(|&b| b == b'\n').map(|nl| {
+            let end = if nl > 0 && chunk[nl - 1] == b'\r' { nl - 1 } else { nl };
+            (end, nl + 1)
+        });
+        let (len, consumed) = match line_end {
+            Some((end, consumed)) if end <= buf.len() => (end, consumed),
+            // The line doesn't fit, so the rest is read by the next `read-line`.
+            _ => {
+                let len = read.min(buf.len());
+                (len, len)
+            }
+        };
+        let res = res.and_then(|()| {
+            file.seek(SeekFrom::Current(consumed as i64 - read as i64))
+                .map(drop)
+                .map_err(FileError::from)
+        });
+        buf[..len].copy_from_slice(&chunk[..len]);
+
+        self.data_stack.push(Word::try_from(len)?)?;
+        self.data_stack.push(Word::data(if read == 0 { 0 } else { -1 }))?;
+        self.push_ior(res)
+    }
+
+    /// `write-file ( c-addr u fileid -- ior )`
+    pub fn write_file(&mut self) -> Result<(), Error> {
+        let fileid = unsafe { self.data_stack.try_pop()?.data };
+        let buf = self.pop_bytes(Access::Read)?;
+        let res = self.file_table()?.get(fileid).and_then(|file| {
+            file.write_all(buf.as_slice()).map_err(FileError::from)
+        });
+        self.push_ior(res)
+    }
+
+    /// `write-line ( c-addr u fileid -- ior )`
+    pub fn write_line(&mut self) -> Result<(), Error> {
+        let fileid = unsafe { self.data_stack.try_pop()?.data };
+        let buf = self.pop_bytes(Access::Read)?;
+        let res = self.file_table()?.get(fileid).and_then(|file| {
+            file.write_all(buf.as_slice())
+                .and_then(|()| file.write_all(b"\n"))
+                .map_err(FileError::from)
+        });
+        self.push_ior(res)
+    }
+}
+
+/// A `( c-addr u )` pair popped by [`Forth::pop_bytes`].
+///
+/// The memory isn't owned by the VM's Rust code, so slices of it are only
+/// handed out for as long as this is borrowed, rather than as `'static`.
+struct ForthBytes {
+    addr: *mut u8,
+    len: usize,
+    access: Access,
+}
+
+impl ForthBytes {
+    fn as_slice(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // Safety: `pop_bytes` checked that the bytes can be read.
+        unsafe { core::slice::from_raw_parts(self.addr, self.len) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        debug_assert_eq!(self.access, Access::Write);
+        if self.len == 0 {
+            return &mut [];
+        }
+        // Safety: `pop_bytes` checked that the bytes can be written.
+        unsafe { core::slice::from_raw_parts_mut(self.addr, self.len) }
+    }
+}
+
+/// Reads into `buf` until it is full or the end of the file is reached.
+fn read_fully(file: &mut File, buf: &mut [u8]) -> (usize, Result<(), FileError>) {
+    let mut read = 0;
+    while read < buf.len() {
+        match file.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return (read, Err(e.into())),
+        }
+    }
+    (read, Ok(()))
+}
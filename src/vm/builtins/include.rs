@@ -0,0 +1,132 @@
+use std::{
+    boxed::Box,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    input::{InputError, InputSource, IoSource},
+    vm::sandbox::Access,
+    Error, Forth, ReplaceErr,
+};
+
+/// The lines of a file being `include`d, counted so that errors can say where
+/// they happened.
+struct FileSource {
+    io: IoSource<BufReader<File>>,
+    line: usize,
+}
+
+impl InputSource for FileSource {
+    fn read_line(&mut self, buf: &mut [u8]) -> Result<Option<usize>, InputError> {
+        let read = self.io.read_line(buf)?;
+        if read.is_some() {
+            self.line += 1;
+        }
+        Ok(read)
+    }
+
+    fn read_key(&mut self) -> Result<Option<u8>, InputError> {
+        self.io.read_key()
+    }
+
+    fn key_ready(&mut self) -> bool {
+        self.io.key_ready()
+    }
+}
+
+impl<T: 'static> Forth<T> {
+    /// Interprets the Forth source file at `path`, one line at a time, then
+    /// goes back to the current line.
+    ///
+    /// Just like [`Forth::evaluate`], this may be called by builtins, and
+    /// nothing is written to the output other than what the file's words
+    /// print. `:` definitions in the file may span more than one line. Each
+    /// line must fit in the unused space after the current line in
+    /// [`Forth::input`].
+    ///
+    /// If interpreting the file fails, the error is returned wrapped in an
+    /// [`Error::InFile`] with the path and line number it happened on.
+    pub fn include_file(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let file = File::open(path).replace_err(Error::IncludeFailed)?;
+        let canonical = path.canonicalize().replace_err(Error::IncludeFailed)?;
+        if !self.included_files.contains(&canonical) {
+            self.included_files.push(canonical);
+        }
+        let mut source = FileSource {
+            io: IoSource::new(BufReader::new(file)),
+            line: 0,
+        };
+
+        let res = self.interpret_lines(&mut source);
+        res.map_err(|error| Error::InFile {
+            path: path.to_path_buf(),
+            line: source.line,
+            error: Box::new(error),
+        })
+    }
+
+    /// Like [`Forth::include_file`], but does nothing if the file at `path`
+    /// has already been included.
+    pub fn require_file(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let canonical = path.canonicalize().replace_err(Error::IncludeFailed)?;
+        if self.included_files.contains(&canonical) {
+            return Ok(());
+        }
+        self.include_file(path)
+    }
+
+    /// The files that have been included so far, as canonical paths.
+    pub fn included_files(&self) -> &[PathBuf] {
+        &self.included_files
+    }
+
+    /// `include ( "path" -- )`
+    ///
+    /// Note that, like all input, the path is lowercased. Use `included` to
+    /// include a file whose path has uppercase characters in it.
+    pub fn include(&mut self) -> Result<(), Error> {
+        self.file_table()?;
+        let path = self.parse_path()?;
+        self.include_file(path)
+    }
+
+    /// `included ( addr len -- )`
+    pub fn included(&mut self) -> Result<(), Error> {
+        self.file_table()?;
+        let path = self.pop_path()?;
+        self.include_file(path)
+    }
+
+    /// `require ( "path" -- )`
+    pub fn require(&mut self) -> Result<(), Error> {
+        self.file_table()?;
+        let path = self.parse_path()?;
+        self.require_file(path)
+    }
+
+    /// `required ( addr len -- )`
+    pub fn required(&mut self) -> Result<(), Error> {
+        self.file_table()?;
+        let path = self.pop_path()?;
+        self.require_file(path)
+    }
+
+    fn parse_path(&mut self) -> Result<PathBuf, Error> {
+        self.input.advance();
+        let path = self.input.cur_word().ok_or(Error::IncludeMissingPath)?;
+        Ok(PathBuf::from(path))
+    }
+
+    fn pop_path(&mut self) -> Result<PathBuf, Error> {
+        let len: usize = self.data_stack.try_pop()?.try_into()?;
+        let addr = unsafe { self.data_stack.try_pop()?.ptr.cast::<u8>() };
+        self.check_access(addr, len, Access::Read)?;
+        let bytes = unsafe { core::slice::from_raw_parts(addr, len) };
+        let path = core::str::from_utf8(bytes).replace_err(Error::IncludeFailed)?;
+        Ok(PathBuf::from(path))
+    }
+}
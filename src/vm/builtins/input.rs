@@ -0,0 +1,79 @@
+use crate::{input::InputRequest, vm::sandbox::Access, word::Word, Error, Forth};
+
+impl<T: 'static> Forth<T> {
+    /// `refill ( -- flag )`
+    ///
+    /// Replaces the rest of the current line with the next line from the
+    /// input source, pushing `-1` if a line was read, or `0` if the source is
+    /// exhausted (or there is no input source).
+    pub fn refill(&mut self) -> Result<(), Error> {
+        let flag = match self.pull_input(InputRequest::Line) {
+            Ok(Some(_)) => -1,
+            Ok(None) | Err(Error::NoInputSource) => 0,
+            Err(e) => return Err(e),
+        };
+        self.data_stack.push(Word::data(flag))?;
+        Ok(())
+    }
+
+    /// `key ( -- char )`
+    pub fn key(&mut self) -> Result<(), Error> {
+        let key = self
+            .pull_input(InputRequest::Key)?
+            .ok_or(Error::InputExhausted)?;
+        self.data_stack.push(Word::data(key as i32))?;
+        Ok(())
+    }
+
+    /// `key? ( -- flag )`
+    pub fn key_avail(&mut self) -> Result<(), Error> {
+        let flag = if self.input_key_ready() { -1 } else { 0 };
+        self.data_stack.push(Word::data(flag))?;
+        Ok(())
+    }
+
+    /// `accept ( addr len -- len2 )`
+    ///
+    /// Reads the next line from the input source into the buffer at `addr`,
+    /// and pushes the number of characters read, not including the line
+    /// terminator.
+    pub fn accept(&mut self) -> Result<(), Error> {
+        // Only peek here: if we have to wait on an async input source, this
+        // word will be called again.
+        let len: usize = self.data_stack.try_peek()?.try_into()?;
+        let addr = unsafe { self.data_stack.try_peek_back_n(1)?.ptr.cast::<u8>() };
+        self.check_access(addr, len, Access::Write)?;
+        let read = self
+            .pull_input(InputRequest::Accept { addr, len })?
+            .unwrap_or(0);
+        let _ = self.data_stack.try_pop()?;
+        let _ = self.data_stack.try_pop()?;
+
+        let line = unsafe { core::slice::from_raw_parts(addr, read) };
+        let trimmed = line
+            .iter()
+            .rposition(|b| !matches!(b, b'\n' | b'\r'))
+            .map_or(0, |i| i + 1);
+        self.data_stack.push(Word::try_from(trimmed)?)?;
+        Ok(())
+    }
+
+    /// `evaluate ( addr len -- )`
+    ///
+    /// Interprets the string at `addr`, then continues with the rest of the
+    /// current line. See [`Forth::evaluate`].
+    pub fn evaluate_word(&mut self) -> Result<(), Error> {
+        let len: usize = self.data_stack.try_pop()?.try_into()?;
+        let addr = unsafe { self.data_stack.try_pop()?.ptr.cast::<u8>() };
+        self.check_access(addr, len, Access::Read)?;
+        unsafe { self.evaluate_raw(addr, len) }
+    }
+
+    /// `source ( -- addr len )`
+    pub fn source(&mut self) -> Result<(), Error> {
+        let (addr, len) = self.input.source();
+        self.data_stack.push(Word::ptr(addr))?;
+        self.data_stack.push(Word::try_from(len)?)?;
+        Ok(())
+    }
+}
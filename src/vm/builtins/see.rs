@@ -0,0 +1,295 @@
+//! Decompiling dictionary entries with `see`.
+//!
+//! A `:` definition's parameter field is a list of references to the words
+//! it calls, with control flow compiled into `(jump-zero)`, `(jmp)` and
+//! `(jmp-doloop)` followed by an offset, so `see` works backwards from the
+//! offsets to the `if`, `else`, `then`, `do` and `loop` that produced them.
+
+use core::{fmt::Write, mem::size_of, ptr::NonNull};
+
+use crate::{
+    dictionary::{DictionaryEntry, EntryHeader, EntryKind, EntryType},
+    fastr::FaStr,
+    word::Word,
+    Error, Forth, Lookup,
+};
+
+/// One compiled item of a `:` definition, whose strings borrow from it.
+enum Cell<'a> {
+    Call(Option<FaStr>),
+    Literal(Word),
+    JumpZero(i32),
+    Jump(i32),
+    DoLoop(i32),
+    Str(&'a str),
+}
+
+impl<T: 'static> Forth<T> {
+    /// `see NAME`
+    ///
+    /// Prints the source of a dictionary entry, reconstructed from what was
+    /// compiled. Comments and names that were shadowed when the entry was
+    /// compiled are lost, and a floating-point literal is printed as the
+    /// integer with the same bits.
+    pub fn see(&mut self) -> Result<(), Error> {
+        self.input.advance();
+        let name = self.input.cur_word().ok_or(Error::SeeMissingName)?;
+        let de = match self.lookup(name)? {
+            Lookup::Dict(loc) => loc.entry(),
+            Lookup::Builtin { bi } => {
+                let name = unsafe { bi.as_ref() }.hdr.name();
+                writeln!(&mut self.output, "{} is a builtin", name.as_str())?;
+                return Ok(());
+            }
+            #[cfg(feature = "async")]
+            Lookup::Async { bi } => {
+                let name = unsafe { bi.as_ref() }.hdr.name();
+                writeln!(&mut self.output, "{} is an async builtin", name.as_str())?;
+                return Ok(());
+            }
+            _ => return Err(Error::SeeNotAWord),
+        };
+
+        let entry = unsafe { de.as_ref() };
+        let name = unsafe { DictionaryEntry::name(de) };
+        let name = name.as_str();
+        let pfa = unsafe { DictionaryEntry::pfa(de).as_ptr() };
+        let words = unsafe { core::slice::from_raw_parts(pfa, usize::from(entry.hdr.len)) };
+
+        if !matches!(entry.hdr.kind, EntryKind::Dictionary) {
+            writeln!(&mut self.output, "{name} is a builtin")?;
+            return Ok(());
+        }
+        match entry.ty {
+            EntryType::Colon => {
+                write!(&mut self.output, ": {name} ")?;
+                self.see_code(de.cast(), words, 0, words.len(), false)?;
+                self.output.push_str(";\n")?;
+            }
+            EntryType::Constant => {
+                let val = unsafe { words[0].data };
+                writeln!(&mut self.output, "constant {name} {val}")?;
+            }
+            EntryType::Variable => {
+                let val = unsafe { words[0].data };
+                writeln!(&mut self.output, "variable {name} ( {val} )")?;
+            }
+            EntryType::Array => {
+                writeln!(&mut self.output, "array {name} {}", words.len())?;
+            }
+            EntryType::Channel => {
+                let capacity = unsafe { words[1].ptr_data };
+                writeln!(&mut self.output, "channel {name} {capacity}")?;
+            }
+            EntryType::Vocabulary => {
+                writeln!(&mut self.output, "vocabulary {name}")?;
+            }
+            EntryType::Builtin => {
+                writeln!(&mut self.output, "{name} is a builtin")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the cells of `words` from `start` up to `end`, returning where
+    /// it stopped, which is past `end` if a cell doesn't end there.
+    ///
+    /// If `dry_run` is set, nothing is printed, and any jump that isn't part
+    /// of an `if` or `do` also counts as not ending at `end`. This is used to
+    /// check a guess at where an `if` or `do` ends.
+    fn see_code(
+        &mut self,
+        eh: NonNull<EntryHeader<T>>,
+        words: &[Word],
+        start: usize,
+        end: usize,
+        dry_run: bool,
+    ) -> Result<usize, Error> {
+        let mut pos = start;
+        while pos < end {
+            let Some((cell, next)) = self.see_cell(eh, words, pos) else {
+                // A truncated cell, which shouldn't happen.
+                return Ok(words.len() + 1);
+            };
+            match &cell {
+                &Cell::JumpZero(off) => {
+                    if let Some(then) = self.see_if(eh, words, pos, off, end, dry_run)? {
+                        pos = then;
+                        continue;
+                    }
+                }
+                Cell::Call(Some(name)) if name.as_str() == "2d>2r" => {
+                    if let Some(after) = self.see_do(eh, words, next, end, dry_run)? {
+                        pos = after;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            if dry_run {
+                if matches!(cell, Cell::JumpZero(_) | Cell::Jump(_) | Cell::DoLoop(_)) {
+                    return Ok(usize::MAX);
+                }
+            } else {
+                self.see_cell_raw(&cell)?;
+            }
+            pos = next;
+        }
+        Ok(pos)
+    }
+
+    /// Prints the `if` whose `(jump-zero)` is at `pos`, returning where its
+    /// `then` is, or `None` if `off` isn't from an `if`.
+    fn see_if(
+        &mut self,
+        eh: NonNull<EntryHeader<T>>,
+        words: &[Word],
+        pos: usize,
+        off: i32,
+        end: usize,
+        dry_run: bool,
+    ) -> Result<Option<usize>, Error> {
+        let body = pos + 2;
+        let Some(target) = jump_target(pos, off).filter(|&t| t >= body && t <= end) else {
+            return Ok(None);
+        };
+
+        // If there's an `else`, the `if` part ends with a `(jmp)` over it,
+        // and the `(jump-zero)` jumps to just after that.
+        let else_jmp = target.checked_sub(2).filter(|&e| e >= body);
+        let else_part = else_jmp.and_then(|e| match self.see_cell(eh, words, e) {
+            Some((Cell::Jump(off), _)) => {
+                jump_target(e, off).filter(|&t| t >= target && t <= end).map(|t| (e, t))
+            }
+            _ => None,
+        });
+        if let Some((jmp, then)) = else_part {
+            // Make sure that the `(jmp)` isn't part of something inside the
+            // `if` part.
+            if self.see_code(eh, words, body, jmp, true)? == jmp
+                && self.see_code(eh, words, target, then, true)? == then
+            {
+                if !dry_run {
+                    self.output.push_str("if ")?;
+                    self.see_code(eh, words, body, jmp, false)?;
+                    self.output.push_str("else ")?;
+                    self.see_code(eh, words, target, then, false)?;
+                    self.output.push_str("then ")?;
+                }
+                return Ok(Some(then));
+            }
+        }
+
+        if self.see_code(eh, words, body, target, true)? != target {
+            return Ok(None);
+        }
+        if !dry_run {
+            self.output.push_str("if ")?;
+            self.see_code(eh, words, body, target, false)?;
+            self.output.push_str("then ")?;
+        }
+        Ok(Some(target))
+    }
+
+    /// Prints the `do` whose `2d>2r` is just before `body`, returning where
+    /// its `loop` ends, or `None` if the `2d>2r` isn't from a `do`.
+    fn see_do(
+        &mut self,
+        eh: NonNull<EntryHeader<T>>,
+        words: &[Word],
+        body: usize,
+        end: usize,
+        dry_run: bool,
+    ) -> Result<Option<usize>, Error> {
+        // Find the `(jmp-doloop)` that jumps back to the start of the body.
+        let mut pos = body;
+        let the_loop = loop {
+            if pos >= end {
+                return Ok(None);
+            }
+            let Some((cell, next)) = self.see_cell(eh, words, pos) else {
+                return Ok(None);
+            };
+            if let Cell::DoLoop(off) = cell {
+                if jump_target(pos, off) == Some(body) {
+                    break pos;
+                }
+            }
+            pos = next;
+        };
+        if self.see_code(eh, words, body, the_loop, true)? != the_loop {
+            return Ok(None);
+        }
+        if !dry_run {
+            self.output.push_str("do ")?;
+            self.see_code(eh, words, body, the_loop, false)?;
+            self.output.push_str("loop ")?;
+        }
+        Ok(Some(the_loop + 2))
+    }
+
+    /// Prints a cell as it was compiled, for cells that aren't part of
+    /// control flow (or when the control flow can't be worked out).
+    fn see_cell_raw(&mut self, cell: &Cell<'_>) -> Result<(), Error> {
+        match cell {
+            Cell::Call(Some(name)) => write!(&mut self.output, "{} ", name.as_str())?,
+            Cell::Call(None) => self.output.push_str("<unknown> ")?,
+            Cell::Literal(val) => write!(&mut self.output, "{} ", unsafe { val.data })?,
+            Cell::JumpZero(off) => write!(&mut self.output, "(jump-zero) {off} ")?,
+            Cell::Jump(off) => write!(&mut self.output, "(jmp) {off} ")?,
+            Cell::DoLoop(off) => write!(&mut self.output, "(jmp-doloop) {off} ")?,
+            Cell::Str(s) => write!(&mut self.output, ".\" {s}\" ")?,
+        }
+        Ok(())
+    }
+
+    /// Decodes the cell at `pos`, returning it and the position of the next
+    /// cell.
+    fn see_cell<'a>(
+        &self,
+        eh: NonNull<EntryHeader<T>>,
+        words: &'a [Word],
+        pos: usize,
+    ) -> Option<(Cell<'a>, usize)> {
+        let ptr = self.dict.resolve_in(eh, *words.get(pos)?);
+        let name = self.see_name(ptr);
+        let arg = || words.get(pos + 1).copied();
+        let data = || Some(unsafe { arg()?.data });
+        let cell = match name.as_ref().map(FaStr::as_str) {
+            Some("(literal)") => (Cell::Literal(arg()?), pos + 2),
+            Some("(jump-zero)") => (Cell::JumpZero(data()?), pos + 2),
+            Some("(jmp)") => (Cell::Jump(data()?), pos + 2),
+            Some("(jmp-doloop)") => (Cell::DoLoop(data()?), pos + 2),
+            Some("(write-str)") => {
+                let len = usize::try_from(data()?).ok()?;
+                let len_words = len.div_ceil(size_of::<Word>());
+                let str_words = words.get(pos + 2..pos + 2 + len_words)?;
+                let bytes =
+                    unsafe { core::slice::from_raw_parts(str_words.as_ptr().cast::<u8>(), len) };
+                (Cell::Str(core::str::from_utf8(bytes).ok()?), pos + 2 + len_words)
+            }
+            _ => (Cell::Call(name), pos + 1),
+        };
+        Some(cell)
+    }
+
+    /// Returns the name of the builtin or dictionary entry at `ptr`.
+    fn see_name(&self, ptr: *mut ()) -> Option<FaStr> {
+        if let Some(bi) = self.builtin_at(ptr) {
+            return Some(bi.hdr.name());
+        }
+        #[cfg(feature = "async")]
+        if let Some(bi) = self.async_builtin_at(ptr) {
+            return Some(bi.hdr.name());
+        }
+        self.dict.entries().find_map(|loc| {
+            let de = loc.entry();
+            (de.as_ptr().cast() == ptr).then(|| unsafe { DictionaryEntry::name(de) })
+        })
+    }
+}
+
+/// Returns where a jump with offset `off` goes, when its builtin is at `pos`.
+fn jump_target(pos: usize, off: i32) -> Option<usize> {
+    (pos + 1).checked_add_signed(isize::try_from(off).ok()?)
+}
@@ -0,0 +1,48 @@
+use crate::{
+    sched::{TaskId, TaskRequest},
+    word::Word,
+    Error, Forth,
+};
+
+impl<T: 'static> Forth<T> {
+    /// `pause ( -- )`
+    ///
+    /// Does nothing if the VM isn't a task.
+    pub fn pause(&mut self) -> Result<(), Error> {
+        if self.task.id != 0 {
+            self.task.request = Some(TaskRequest::Pause);
+        }
+        Ok(())
+    }
+
+    /// `task-id ( -- id )`
+    pub fn task_id(&mut self) -> Result<(), Error> {
+        self.data_stack.push(Word::data(i32::from(self.task.id)))?;
+        Ok(())
+    }
+
+    /// `spawn ( xt -- id )`
+    pub fn spawn(&mut self) -> Result<(), Error> {
+        let xt = self.data_stack.try_pop()?;
+        self.check_xt(xt)?;
+        self.request_task(TaskRequest::Spawn(xt))
+    }
+
+    /// `kill ( id -- )`
+    pub fn kill(&mut self) -> Result<(), Error> {
+        let id = unsafe { self.data_stack.try_pop()?.data };
+        // No task has id 0, so killing it does nothing.
+        let id = TaskId::try_from(id).unwrap_or(0);
+        self.request_task(TaskRequest::Kill(id))
+    }
+
+    /// Asks the scheduler running this VM to do something, once the current
+    /// word is done.
+    fn request_task(&mut self, request: TaskRequest) -> Result<(), Error> {
+        if self.task.id == 0 {
+            return Err(Error::NoScheduler);
+        }
+        self.task.request = Some(request);
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+//! Wordlists and the search order.
+//!
+//! Every dictionary entry is in a wordlist, identified by a number (its
+//! "wid"). Words are looked up in the wordlists of the search order, first
+//! to last, and new words are added to the "current" wordlist, which is
+//! chosen with `definitions` or `set-current`. Builtins aren't in any
+//! wordlist, and are found whatever the search order is.
+
+use crate::{
+    dictionary::{DictionaryEntry, EntryType, FORTH_WORDLIST},
+    fastr::TmpFaStr,
+    vm::sandbox::Access,
+    word::Word,
+    Error, Forth,
+};
+
+/// The most wordlists that can be in the search order.
+pub(crate) const MAX_ORDER: usize = 8;
+
+/// The wordlists searched by a VM, and the wordlists it has created.
+#[derive(Clone, Debug)]
+pub(crate) struct SearchOrder {
+    /// The wordlists to search, in the order they're searched.
+    order: [u16; MAX_ORDER],
+    len: usize,
+    /// The wid of the next wordlist to be created.
+    next: u16,
+}
+
+impl SearchOrder {
+    /// Returns where `wid` is in the search order, if it's there at all.
+    pub(crate) fn rank(&self, wid: u16) -> Option<usize> {
+        self.order[..self.len].iter().position(|&w| w == wid)
+    }
+
+    /// Makes sure that wordlists created from now on don't reuse `wid`, e.g.
+    /// after loading an image with entries in it.
+    pub(crate) fn reserve(&mut self, wid: u16) {
+        self.next = self.next.max(wid.saturating_add(1));
+    }
+
+    /// Returns the wordlists to search, in the order they're searched.
+    #[cfg(feature = "use-std")]
+    pub(crate) fn wids(&self) -> &[u16] {
+        &self.order[..self.len]
+    }
+
+    /// Replaces the search order with `wids`, which must be no more than
+    /// [`MAX_ORDER`] wordlists, making sure that they're not reused.
+    #[cfg(feature = "use-std")]
+    pub(crate) fn set_wids(&mut self, wids: &[u16]) {
+        self.order[..wids.len()].copy_from_slice(wids);
+        self.len = wids.len();
+        for &wid in wids {
+            self.reserve(wid);
+        }
+    }
+
+    fn only(&mut self) {
+        self.order[0] = FORTH_WORDLIST;
+        self.len = 1;
+    }
+
+    fn first_mut(&mut self) -> Result<&mut u16, Error> {
+        self.order[..self.len].first_mut().ok_or(Error::SearchOrderUnderflow)
+    }
+}
+
+impl Default for SearchOrder {
+    fn default() -> Self {
+        Self {
+            order: [FORTH_WORDLIST; MAX_ORDER],
+            len: 1,
+            next: FORTH_WORDLIST + 1,
+        }
+    }
+}
+
+impl<T: 'static> Forth<T> {
+    /// `forth-wordlist ( -- wid )`
+    pub fn forth_wordlist(&mut self) -> Result<(), Error> {
+        self.data_stack.push(Word::data(FORTH_WORDLIST.into()))?;
+        Ok(())
+    }
+
+    /// `wordlist ( -- wid )`
+    pub fn wordlist(&mut self) -> Result<(), Error> {
+        let wid = self.new_wordlist()?;
+        self.data_stack.push(Word::data(wid.into()))?;
+        Ok(())
+    }
+
+    /// `get-order ( -- widn ... wid1 n )`
+    pub fn get_order(&mut self) -> Result<(), Error> {
+        let order = self.search_order.clone();
+        for &wid in order.order[..order.len].iter().rev() {
+            self.data_stack.push(Word::data(wid.into()))?;
+        }
+        self.data_stack.push(Word::try_from(order.len)?)?;
+        Ok(())
+    }
+
+    /// `set-order ( widn ... wid1 n -- )`
+    ///
+    /// If `n` is -1, this is the same as `only`.
+    pub fn set_order(&mut self) -> Result<(), Error> {
+        let n = unsafe { self.data_stack.try_pop()?.data };
+        if n == -1 {
+            self.search_order.only();
+            return Ok(());
+        }
+        let len = usize::try_from(n).map_err(|_| Error::WordToUsizeInvalid(n))?;
+        if len > MAX_ORDER {
+            return Err(Error::SearchOrderOverflow);
+        }
+        let mut order = [FORTH_WORDLIST; MAX_ORDER];
+        for wid in &mut order[..len] {
+            *wid = self.pop_wid()?;
+        }
+        self.search_order.order = order;
+        self.search_order.len = len;
+        Ok(())
+    }
+
+    /// `get-current ( -- wid )`
+    pub fn get_current(&mut self) -> Result<(), Error> {
+        self.data_stack.push(Word::data(self.dict.current.into()))?;
+        Ok(())
+    }
+
+    /// `set-current ( wid -- )`
+    pub fn set_current(&mut self) -> Result<(), Error> {
+        self.dict.current = self.pop_wid()?;
+        Ok(())
+    }
+
+    /// `definitions ( -- )`
+    ///
+    /// Adds new words to the first wordlist in the search order.
+    pub fn definitions(&mut self) -> Result<(), Error> {
+        self.dict.current = *self.search_order.first_mut()?;
+        Ok(())
+    }
+
+    /// `search-wordlist ( addr len wid -- 0 | xt -1 )`
+    ///
+    /// Searching `forth-wordlist` finds builtins, too. There are no
+    /// immediate words, so a word that's found is always followed by -1.
+    pub fn search_wordlist(&mut self) -> Result<(), Error> {
+        let wid = self.pop_wid()?;
+        let len: usize = self.data_stack.try_pop()?.try_into()?;
+        let addr = unsafe { self.data_stack.try_pop()?.ptr.cast::<u8>() };
+        self.check_access(addr, len, Access::Read)?;
+        let bytes = unsafe { core::slice::from_raw_parts(addr, len) };
+        let name = core::str::from_utf8(bytes).map_err(|_| Error::BadStrLiteral)?;
+
+        let fastr = TmpFaStr::new_from(name);
+        let found = self
+            .dict
+            .entries()
+            .map(|loc| loc.entry())
+            .find(|&de| {
+                unsafe { de.as_ref() }.wordlist == wid
+                    && unsafe { DictionaryEntry::name(de) } == *fastr
+            })
+            .map(|de| Word::ptr(de.as_ptr()))
+            .or_else(|| {
+                let bi = (wid == FORTH_WORDLIST).then(|| self.find_in_bis(&fastr))??;
+                Some(Word::ptr(bi.as_ptr()))
+            });
+        match found {
+            Some(xt) => {
+                self.data_stack.push(xt)?;
+                self.data_stack.push(Word::data(-1))?;
+            }
+            None => self.data_stack.push(Word::data(0))?,
+        }
+        Ok(())
+    }
+
+    /// `only ( -- )`
+    ///
+    /// Sets the search order to just `forth-wordlist`.
+    pub fn only(&mut self) -> Result<(), Error> {
+        self.search_order.only();
+        Ok(())
+    }
+
+    /// `also ( -- )`
+    ///
+    /// Duplicates the first wordlist in the search order, usually so that a
+    /// vocabulary can replace it.
+    pub fn also(&mut self) -> Result<(), Error> {
+        let order = &mut self.search_order;
+        let first = *order.first_mut()?;
+        if order.len == MAX_ORDER {
+            return Err(Error::SearchOrderOverflow);
+        }
+        order.order.copy_within(..order.len, 1);
+        order.order[0] = first;
+        order.len += 1;
+        Ok(())
+    }
+
+    /// `previous ( -- )`
+    ///
+    /// Removes the first wordlist from the search order, unless it's the
+    /// only one left.
+    pub fn previous(&mut self) -> Result<(), Error> {
+        let order = &mut self.search_order;
+        if order.len <= 1 {
+            return Err(Error::SearchOrderUnderflow);
+        }
+        order.order.copy_within(1..order.len, 0);
+        order.len -= 1;
+        Ok(())
+    }
+
+    /// `forth ( -- )`
+    ///
+    /// Replaces the first wordlist in the search order with
+    /// `forth-wordlist`.
+    pub fn forth(&mut self) -> Result<(), Error> {
+        *self.search_order.first_mut()? = FORTH_WORDLIST;
+        Ok(())
+    }
+
+    /// `vocabulary NAME`
+    ///
+    /// Creates a wordlist, and a word which replaces the first wordlist in
+    /// the search order with it.
+    pub fn create_vocabulary(&mut self) -> Result<(), Error> {
+        let name = self.munch_name()?;
+        let wid = self.new_wordlist()?;
+        self.dict
+            .build_entry()?
+            .write_word(Word::data(wid.into()))?
+            .finish(name, EntryType::Vocabulary, Self::vocabulary);
+        Ok(())
+    }
+
+    /// The runtime of a word created by `vocabulary`.
+    pub fn vocabulary(&mut self) -> Result<(), Error> {
+        let me = self.call_stack.try_peek()?;
+        let de = me.eh.cast::<DictionaryEntry<T>>();
+        let wid = unsafe { DictionaryEntry::<T>::pfa(de).as_ptr().read().data };
+        *self.search_order.first_mut()? = u16::try_from(wid).map_err(|_| Error::BadWordlist(wid))?;
+        Ok(())
+    }
+
+    fn new_wordlist(&mut self) -> Result<u16, Error> {
+        let wid = self.search_order.next;
+        self.search_order.next = wid.checked_add(1).ok_or(Error::TooManyWordlists)?;
+        Ok(wid)
+    }
+
+    /// Pops the wid of a wordlist that this VM has created.
+    fn pop_wid(&mut self) -> Result<u16, Error> {
+        let wid = unsafe { self.data_stack.try_pop()?.data };
+        match u16::try_from(wid) {
+            Ok(w) if w < self.search_order.next => Ok(w),
+            _ => Err(Error::BadWordlist(wid)),
+        }
+    }
+}
@@ -0,0 +1,487 @@
+//! Saving and loading compiled dictionaries as images.
+//!
+//! An image holds the entries of a VM's current (mutable) dictionary, oldest
+//! first, in a position-independent format, so that source compiled once can
+//! be loaded into another VM (or after a reboot) without recompiling it.
+//!
+//! All integers are little-endian. An image starts with a header:
+//!
+//! | bytes | contents                                  |
+//! |-------|-------------------------------------------|
+//! | 4     | the magic number `F3IM`                   |
+//! | 1     | the format version                        |
+//! | 1     | `size_of::<Word>()` of the saving VM      |
+//! | 4     | the number of entries                     |
+//!
+//! followed by each entry:
+//!
+//! - its name, as a length byte followed by the name's bytes
+//! - its [`EntryKind`], as a byte
+//! - its [`EntryType`], as a byte, which decides the function it's run by.
+//!   A runtime builtin is looked up by its own name when the image is loaded.
+//! - the wordlist it's in, as a `u16`
+//! - the number of cells in its parameter field, as a `u16`, then each cell.
+//!
+//! A cell is a tag byte followed by its value. Words in the parameter field
+//! that point to another entry are stored as references to an earlier entry
+//! in the image, or by name for builtins and entries in parent dictionaries,
+//! and are looked up again when the image is loaded. Anything else is stored
+//! as a raw data word. A string compiled by `."` is stored as a single cell.
+//!
+//! Jump offsets count parameter field words, which depend on the size of a
+//! [`Word`], so an image can only be loaded by a VM with the same word size.
+
+use core::{marker::PhantomData, mem::size_of, ptr::NonNull};
+
+use crate::{
+    dictionary::{BuiltinEntry, DictLocation, DictionaryEntry, EntryHeader, EntryKind, EntryType},
+    fastr::TmpFaStr,
+    word::Word,
+    Error, Forth, WordFunc,
+};
+
+#[cfg(feature = "async")]
+use crate::dictionary::AsyncBuiltinEntry;
+
+const MAGIC: &[u8; 4] = b"F3IM";
+const VERSION: u8 = 3;
+
+/// A raw data word, stored as an `i64`.
+pub(crate) const CELL_DATA: u8 = 0;
+/// A reference to an earlier entry in the image, by index.
+pub(crate) const CELL_ENTRY: u8 = 1;
+/// A reference to a builtin, by name.
+pub(crate) const CELL_BUILTIN: u8 = 2;
+/// A reference to an async builtin, by name.
+#[cfg(feature = "async")]
+const CELL_ASYNC_BUILTIN: u8 = 3;
+/// A reference to an entry in a parent dictionary, by name.
+pub(crate) const CELL_PARENT: u8 = 4;
+/// The length and bytes of a string compiled by `."`.
+pub(crate) const CELL_STR: u8 = 5;
+
+#[derive(Debug, PartialEq)]
+pub enum ImageError {
+    /// The buffer to save the image to is too small.
+    BufferTooSmall,
+    /// The image ended early.
+    Truncated,
+    /// The data isn't an image (or is from an unsupported version).
+    BadMagic,
+    /// The image was saved by a VM with a different word size.
+    WordSizeMismatch,
+    /// The image is corrupt.
+    Malformed,
+    /// A builtin or parent dictionary entry named in the image doesn't exist
+    /// in the loading VM.
+    MissingWord,
+    /// A word in a definition points somewhere that can't be saved.
+    UnknownPointer,
+}
+
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<T: 'static> Forth<T> {
+    /// Saves the entries of this VM's current dictionary into `buf` as an
+    /// image, returning the number of bytes written.
+    ///
+    /// Entries in parent dictionaries aren't saved, but may be referred to by
+    /// name. The contents of variables are saved as they are, so any pointers
+    /// stored in them will dangle when the image is loaded.
+    pub fn save_image(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut w = Writer { buf, pos: 0 };
+        let count = self.current_entries().count();
+        w.bytes(MAGIC)?;
+        w.u8(VERSION)?;
+        w.u8(size_of::<Word>() as u8)?;
+        w.u32(count as u32)?;
+
+        for idx in 0..count {
+            let entry = self.current_entry(count, idx)?;
+            let de = unsafe { entry.as_ref() };
+            w.name(unsafe { DictionaryEntry::name(entry) }.as_bytes())?;
+            w.u8(de.hdr.kind as u16 as u8)?;
+            w.u8(de.ty as u8)?;
+            w.u16(de.wordlist)?;
+
+            let pfa = unsafe { DictionaryEntry::pfa(entry).as_ptr() };
+            let words = unsafe { core::slice::from_raw_parts(pfa, usize::from(de.hdr.len)) };
+            if de.ty == EntryType::Colon {
+                self.save_code(&mut w, count, words)?;
+            } else {
+                w.u16(de.hdr.len)?;
+                for word in words {
+                    w.data(*word)?;
+                }
+            }
+        }
+        Ok(w.pos)
+    }
+
+    /// Writes the cells of a `:` definition's parameter field.
+    fn save_code(&self, w: &mut Writer<'_>, count: usize, words: &[Word]) -> Result<(), Error> {
+        // The number of cells isn't known up front, as strings take more than
+        // one word, so come back and fill it in.
+        let cells_pos = w.pos;
+        w.u16(0)?;
+        let mut cells = 0u16;
+        let mut idx = 0;
+        while idx < words.len() {
+            let ptr = self.dict.resolve_word(words[idx]);
+            idx += 1;
+            cells += 1;
+            if let Some(bi) = self.builtin_at(ptr) {
+                w.u8(CELL_BUILTIN)?;
+                w.name(bi.hdr.name.as_bytes())?;
+                match bi.hdr.name.as_str() {
+                    "(literal)" | "(jump-zero)" | "(jmp)" | "(jmp-doloop)" => {
+                        let word = words.get(idx).ok_or(ImageError::Malformed)?;
+                        w.data(*word)?;
+                        idx += 1;
+                        cells += 1;
+                    }
+                    "(write-str)" => {
+                        let len = words.get(idx).ok_or(ImageError::Malformed)?;
+                        let len = usize::try_from(unsafe { len.data })
+                            .map_err(|_| ImageError::Malformed)?;
+                        let len_words = len.div_ceil(size_of::<Word>());
+                        let str_words =
+                            words.get(idx + 1..idx + 1 + len_words).ok_or(ImageError::Malformed)?;
+                        let bytes = unsafe {
+                            core::slice::from_raw_parts(str_words.as_ptr().cast::<u8>(), len)
+                        };
+                        w.u8(CELL_STR)?;
+                        w.u16(len as u16)?;
+                        w.bytes(bytes)?;
+                        idx += 1 + len_words;
+                        cells += 1;
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            #[cfg(feature = "async")]
+            if let Some(bi) = self.async_builtin_at(ptr) {
+                w.u8(CELL_ASYNC_BUILTIN)?;
+                w.name(bi.hdr.name.as_bytes())?;
+                continue;
+            }
+
+            let target = self
+                .dict
+                .entries()
+                .enumerate()
+                .find(|(_, loc)| loc.entry().as_ptr().cast() == ptr)
+                .ok_or(ImageError::UnknownPointer)?;
+            match target {
+                (pos, DictLocation::Current(_)) => {
+                    w.u8(CELL_ENTRY)?;
+                    w.u32((count - 1 - pos) as u32)?;
+                }
+                (_, DictLocation::Parent(de)) => {
+                    w.u8(CELL_PARENT)?;
+                    w.name(unsafe { DictionaryEntry::name(de) }.as_bytes())?;
+                }
+            }
+        }
+        w.buf[cells_pos..cells_pos + 2].copy_from_slice(&cells.to_le_bytes());
+        Ok(())
+    }
+
+    /// Loads the entries saved in `image` by [`Forth::save_image`] into this
+    /// VM's current dictionary, as if their source had been compiled again.
+    ///
+    /// Builtins are looked up by name in this VM's builtins, and so are
+    /// entries that were in a parent dictionary when the image was saved. If
+    /// loading fails, the dictionary is left as it was.
+    pub fn load_image(&mut self, image: &[u8]) -> Result<(), Error> {
+        let tail = self.dict.tail;
+        let cur = self.dict.alloc.cur;
+        let search_order = self.search_order.clone();
+        let res = self.load_entries(image);
+        if res.is_err() {
+            self.dict.tail = tail;
+            self.dict.alloc.cur = cur;
+            self.search_order = search_order;
+        }
+        res
+    }
+
+    fn load_entries(&mut self, image: &[u8]) -> Result<(), Error> {
+        let mut r = Reader::new(image)?;
+        let count = r.u32()? as usize;
+
+        for loaded in 0..count {
+            let name = self.dict.alloc.bump_str(r.name_str()?)?;
+            let kind = match r.u8()? {
+                1 => EntryKind::RuntimeBuiltin,
+                2 => EntryKind::Dictionary,
+                #[cfg(feature = "async")]
+                4 => EntryKind::RuntimeAsyncBuiltin,
+                _ => return Err(ImageError::Malformed.into()),
+            };
+            let ty = EntryType::from_u8(r.u8()?).ok_or(ImageError::Malformed)?;
+            let wordlist = r.u16()?;
+            self.search_order.reserve(wordlist);
+            let func = match Self::entry_type_func(ty) {
+                Some(func) => func,
+                #[cfg(feature = "async")]
+                None if matches!(kind, EntryKind::RuntimeAsyncBuiltin) => {
+                    Self::runtime_async_builtin
+                }
+                // Runtime builtins aren't in the builtins slice, but a builtin
+                // with the same name may be.
+                None => {
+                    let bi = self.find_builtin(name.as_str())?;
+                    unsafe { bi.as_ref() }.func
+                }
+            };
+
+            let base = self.dict.alloc.bump::<DictionaryEntry<T>>()?;
+            let mut len = 0u16;
+            for _ in 0..r.u16()? {
+                len = len
+                    .checked_add(self.load_cell(&mut r, loaded)?)
+                    .ok_or(ImageError::Malformed)?;
+            }
+            unsafe {
+                base.as_ptr().write(DictionaryEntry {
+                    hdr: EntryHeader {
+                        name: self.dict.entry_name(name, base),
+                        kind,
+                        len,
+                        _pd: PhantomData,
+                    },
+                    func,
+                    link: self.dict.link_to(self.dict.tail),
+                    wordlist,
+                    ty,
+                    parameter_field: [],
+                });
+            }
+            self.dict.tail = Some(base);
+        }
+        if !r.is_done() {
+            return Err(ImageError::Malformed.into());
+        }
+        Ok(())
+    }
+
+    /// Reads a cell and writes it to the dictionary, returning the number of
+    /// words written. `loaded` is how many of the image's entries have been
+    /// loaded so far.
+    fn load_cell(&mut self, r: &mut Reader<'_>, loaded: usize) -> Result<u16, Error> {
+        let word = match r.u8()? {
+            CELL_DATA => {
+                let val = isize::try_from(r.i64()?).map_err(|_| ImageError::Malformed)?;
+                Word::ptr_data(val)
+            }
+            CELL_ENTRY => {
+                let idx = r.u32()? as usize;
+                if idx >= loaded {
+                    return Err(ImageError::Malformed.into());
+                }
+                let entry = self
+                    .dict
+                    .entries()
+                    .nth(loaded - 1 - idx)
+                    .ok_or(ImageError::Malformed)?;
+                self.dict.ref_word(entry.entry())
+            }
+            CELL_BUILTIN => Word::ptr(self.find_builtin(r.name_str()?)?.as_ptr()),
+            #[cfg(feature = "async")]
+            CELL_ASYNC_BUILTIN => {
+                let name = TmpFaStr::new_from(r.name_str()?);
+                let bi = self.find_in_async_bis(&name).ok_or(ImageError::MissingWord)?;
+                Word::ptr(bi.as_ptr())
+            }
+            CELL_PARENT => Word::ptr(self.find_parent(r.name_str()?)?.as_ptr()),
+            CELL_STR => {
+                let len = r.u16()?;
+                let bytes = r.bytes(usize::from(len))?;
+                self.dict.alloc.bump_write(Word::data(i32::from(len)))?;
+                if len != 0 {
+                    let dest = self.dict.alloc.bump_u8s(bytes.len()).ok_or(Error::Bump(
+                        crate::dictionary::BumpError::OutOfMemory,
+                    ))?;
+                    unsafe {
+                        dest.as_ptr().copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+                    }
+                }
+                let len_words = usize::from(len).div_ceil(size_of::<Word>());
+                return Ok(1 + len_words as u16);
+            }
+            _ => return Err(ImageError::Malformed.into()),
+        };
+        self.dict.alloc.bump_write(word)?;
+        Ok(1)
+    }
+
+    /// The function that runs entries of type `ty`, or `None` for runtime
+    /// builtins, which each have their own.
+    pub(crate) fn entry_type_func(ty: EntryType) -> Option<WordFunc<T>> {
+        match ty {
+            EntryType::Builtin => None,
+            EntryType::Colon => Some(Self::interpret),
+            EntryType::Constant => Some(Self::constant),
+            EntryType::Variable | EntryType::Array => Some(Self::variable),
+            EntryType::Channel => Some(Self::channel),
+            EntryType::Vocabulary => Some(Self::vocabulary),
+        }
+    }
+
+    /// Iterates over the entries of the current dictionary, newest first.
+    pub(super) fn current_entries(&self) -> impl Iterator<Item = NonNull<DictionaryEntry<T>>> + '_ {
+        self.dict.entries().map_while(|loc| match loc {
+            DictLocation::Current(de) => Some(de),
+            DictLocation::Parent(_) => None,
+        })
+    }
+
+    /// Returns the `idx`th oldest of the `count` entries in the current
+    /// dictionary.
+    pub(super) fn current_entry(&self, count: usize, idx: usize) -> Result<NonNull<DictionaryEntry<T>>, Error> {
+        self.current_entries()
+            .nth(count - 1 - idx)
+            .ok_or(Error::InternalError)
+    }
+
+    pub(super) fn builtin_at(&self, ptr: *mut ()) -> Option<&BuiltinEntry<T>> {
+        self.builtins
+            .iter()
+            .find(|bi| core::ptr::eq(*bi as *const BuiltinEntry<T>, ptr.cast()))
+    }
+
+    #[cfg(feature = "async")]
+    pub(super) fn async_builtin_at(&self, ptr: *mut ()) -> Option<&AsyncBuiltinEntry<T>> {
+        self.async_builtins
+            .iter()
+            .find(|bi| core::ptr::eq(*bi as *const AsyncBuiltinEntry<T>, ptr.cast()))
+    }
+
+    pub(super) fn find_builtin(&self, name: &str) -> Result<NonNull<BuiltinEntry<T>>, Error> {
+        self.find_in_bis(&TmpFaStr::new_from(name))
+            .ok_or_else(|| ImageError::MissingWord.into())
+    }
+
+    /// Finds the entry named `name` in one of the parent dictionaries.
+    pub(super) fn find_parent(&self, name: &str) -> Result<NonNull<DictionaryEntry<T>>, Error> {
+        let name = TmpFaStr::new_from(name);
+        self.dict
+            .entries()
+            .find_map(|loc| match loc {
+                DictLocation::Parent(de) if unsafe { DictionaryEntry::name(de) } == *name => {
+                    Some(de)
+                }
+                _ => None,
+            })
+            .ok_or_else(|| ImageError::MissingWord.into())
+    }
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), ImageError> {
+        let end = self.pos + bytes.len();
+        self.buf
+            .get_mut(self.pos..end)
+            .ok_or(ImageError::BufferTooSmall)?
+            .copy_from_slice(bytes);
+        self.pos = end;
+        Ok(())
+    }
+
+    fn u8(&mut self, val: u8) -> Result<(), ImageError> {
+        self.bytes(&[val])
+    }
+
+    fn u16(&mut self, val: u16) -> Result<(), ImageError> {
+        self.bytes(&val.to_le_bytes())
+    }
+
+    fn u32(&mut self, val: u32) -> Result<(), ImageError> {
+        self.bytes(&val.to_le_bytes())
+    }
+
+    fn name(&mut self, name: &[u8]) -> Result<(), ImageError> {
+        // Names are at most 31 bytes long.
+        self.u8(name.len() as u8)?;
+        self.bytes(name)
+    }
+
+    fn data(&mut self, word: Word) -> Result<(), ImageError> {
+        self.u8(CELL_DATA)?;
+        self.bytes(&(unsafe { word.ptr_data } as i64).to_le_bytes())
+    }
+}
+
+impl<'a> Reader<'a> {
+    /// Checks the header of `image`, returning a reader positioned at the
+    /// number of entries.
+    pub(crate) fn new(image: &'a [u8]) -> Result<Self, ImageError> {
+        let mut r = Self::raw(image);
+        if r.bytes(MAGIC.len())? != MAGIC || r.u8()? != VERSION {
+            return Err(ImageError::BadMagic);
+        }
+        if usize::from(r.u8()?) != size_of::<Word>() {
+            return Err(ImageError::WordSizeMismatch);
+        }
+        Ok(r)
+    }
+
+    /// A reader for data in the same encoding as images, without the header.
+    pub(crate) fn raw(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// Returns `true` once everything has been read.
+    pub(crate) fn is_done(&self) -> bool {
+        self.pos == self.buf.len()
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], ImageError> {
+        let end = self.pos + len;
+        let bytes = self.buf.get(self.pos..end).ok_or(ImageError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, ImageError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, ImageError> {
+        let mut le = [0; 2];
+        le.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(le))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, ImageError> {
+        let mut le = [0; 4];
+        le.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(le))
+    }
+
+    pub(crate) fn i64(&mut self) -> Result<i64, ImageError> {
+        let mut le = [0; 8];
+        le.copy_from_slice(self.bytes(8)?);
+        Ok(i64::from_le_bytes(le))
+    }
+
+    fn name(&mut self) -> Result<&'a [u8], ImageError> {
+        let len = self.u8()?;
+        self.bytes(usize::from(len))
+    }
+
+    pub(crate) fn name_str(&mut self) -> Result<&'a str, ImageError> {
+        core::str::from_utf8(self.name()?).map_err(|_| ImageError::Malformed)
+    }
+}
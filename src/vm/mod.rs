@@ -7,22 +7,36 @@ use core::{
 };
 
 use crate::{
+    block::Blocks,
     dictionary::{
         DictLocation, BuiltinEntry, BumpError, DictionaryEntry, EntryHeader,
-        EntryKind, OwnedDict,
+        EntryKind, EntryType, OwnedDict,
     },
     fastr::{FaStr, TmpFaStr},
-    input::WordStrBuf,
+    input::{InputError, InputRequest, InputSource, WordStrBuf},
     output::OutputBuf,
+    sched::TaskState,
     stack::{Stack, StackError},
-    word::Word,
-    CallContext, Error, Lookup, Mode, ReplaceErr, WordFunc,
+    word::{FromWords, IntoWords, Word},
+    Builtin, CallContext, Error, Lookup, Mode, ReplaceErr,
 };
 
+use self::{builtins::wordlist::SearchOrder, sandbox::Sandbox};
+
+#[cfg(feature = "async")]
+use crate::{
+    dictionary::{AsyncBuiltinEntry, AsyncBuiltins},
+    input::AsyncInputSource,
+};
 #[cfg(feature = "async")]
-use crate::dictionary::{AsyncBuiltinEntry, AsyncBuiltins};
+use core::task::{ready, Context, Poll};
 
 pub mod builtins;
+pub(crate) mod image;
+pub(crate) mod sandbox;
+pub(crate) mod stats;
+#[cfg(feature = "use-std")]
+pub(crate) mod snapshot;
 
 #[cfg(feature = "async")]
 mod async_vm;
@@ -43,6 +57,36 @@ pub struct Forth<T: 'static> {
     builtins: &'static [BuiltinEntry<T>],
     #[cfg(feature = "async")]
     async_builtins: &'static [AsyncBuiltinEntry<T>],
+    input_source: Option<NonNull<dyn InputSource>>,
+    /// Where further lines of nested input come from (e.g. the file being
+    /// `include`d), and the nesting depth of the input it belongs to.
+    nested_source: Option<(LineSource, u16)>,
+    /// The block device used by the block words, and its buffers.
+    blocks: Option<Blocks>,
+    /// How many more words may be executed before [`Error::OutOfFuel`], or
+    /// `None` for no limit.
+    fuel: Option<usize>,
+    /// This VM's state as a task, if it's being run by a `Scheduler`.
+    pub(crate) task: TaskState,
+    /// The wordlists that words are looked up in.
+    pub(crate) search_order: SearchOrder,
+    /// What Forth code may access, if the VM is sandboxed.
+    sandbox: Option<Sandbox>,
+    /// Every file that has been `include`d, for `require`.
+    #[cfg(feature = "use-std")]
+    included_files: std::vec::Vec<std::path::PathBuf>,
+    /// Files opened by Forth code, or `None` if file access is disabled.
+    #[cfg(feature = "use-std")]
+    files: Option<builtins::file::FileTable>,
+    #[cfg(feature = "async")]
+    async_input_source: Option<NonNull<dyn AsyncInputSource>>,
+    /// A read that is waiting on the `async_input_source`.
+    #[cfg(feature = "async")]
+    input_request: Option<InputRequest>,
+    /// The result of the last `input_request`, waiting to be picked up by the
+    /// word that made the request.
+    #[cfg(feature = "async")]
+    input_response: Option<Result<Option<usize>, InputError>>,
 }
 
 enum ProcessAction {
@@ -51,13 +95,24 @@ enum ProcessAction {
     Done,
 }
 
-#[derive(Copy, Clone, Eq, PartialEq)]
-enum Step {
+/// Where lines of input are read from.
+#[derive(Clone, Copy)]
+pub(crate) enum LineSource {
+    /// A synchronous [`InputSource`].
+    Input(NonNull<dyn InputSource>),
+    /// The block being `load`ed, and which of its lines to read next.
+    Block { block: u32, line: usize },
+}
+
+/// Whether the line being run has finished, as returned by [`Forth::step`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Step {
     Done,
     NotDone,
 }
 
 impl<T> Forth<T> {
+    #[allow(clippy::too_many_arguments)]
     pub unsafe fn new(
         dstack_buf: (*mut Word, usize),
         rstack_buf: (*mut Word, usize),
@@ -82,9 +137,26 @@ impl<T> Forth<T> {
             output,
             host_ctxt,
             builtins,
+            input_source: None,
+            nested_source: None,
+            blocks: None,
+            fuel: None,
+            task: TaskState::default(),
+            search_order: SearchOrder::default(),
+            sandbox: None,
+            #[cfg(feature = "use-std")]
+            included_files: std::vec::Vec::new(),
+            #[cfg(feature = "use-std")]
+            files: None,
 
             #[cfg(feature = "async")]
             async_builtins: &[],
+            #[cfg(feature = "async")]
+            async_input_source: None,
+            #[cfg(feature = "async")]
+            input_request: None,
+            #[cfg(feature = "async")]
+            input_response: None,
         })
     }
 
@@ -92,6 +164,7 @@ impl<T> Forth<T> {
     /// slot, and overflowing onto the injection queue if the local
     /// queue is full.
     #[cfg(feature = "async")]
+    #[allow(clippy::too_many_arguments)]
      unsafe fn new_async(
         dstack_buf: (*mut Word, usize),
         rstack_buf: (*mut Word, usize),
@@ -118,6 +191,20 @@ impl<T> Forth<T> {
             host_ctxt,
             builtins,
             async_builtins,
+            input_source: None,
+            nested_source: None,
+            blocks: None,
+            fuel: None,
+            task: TaskState::default(),
+            search_order: SearchOrder::default(),
+            sandbox: None,
+            #[cfg(feature = "use-std")]
+            included_files: std::vec::Vec::new(),
+            #[cfg(feature = "use-std")]
+            files: None,
+            async_input_source: None,
+            input_request: None,
+            input_response: None,
         })
     }
 
@@ -138,6 +225,7 @@ impl<T> Forth<T> {
     /// # Safety
     ///
     /// This method requires the same invariants be upheld as [`Forth::new`].
+    #[allow(clippy::too_many_arguments)]
     pub unsafe fn fork(
         &mut self,
         mut new_dict: OwnedDict<T>,
@@ -151,7 +239,7 @@ impl<T> Forth<T> {
     ) -> Result<Self, Error> {
         let shared_dict = self.dict.fork_onto(my_dict);
         new_dict.set_parent(shared_dict);
-        Self::new(
+        let mut child = Self::new(
             dstack_buf,
             rstack_buf,
             cstack_buf,
@@ -160,25 +248,97 @@ impl<T> Forth<T> {
             output,
             host_ctxt,
             self.builtins,
-        )
+        )?;
+        child.dict.current = self.dict.current;
+        child.search_order = self.search_order.clone();
+        child.sandbox = self.sandbox;
+        child.dict.alloc.set_quota(self.dict.alloc.quota());
+        #[cfg(feature = "async")]
+        {
+            child.async_builtins = self.async_builtins;
+        }
+        Ok(child)
+    }
+
+    /// Attaches an [`InputSource`] that the VM can pull more input from, or
+    /// detaches the current one if `source` is `None`.
+    ///
+    /// See the [`InputSource`] documentation for details.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `source` lives at least as long as it is
+    /// attached to the VM, and that it is not accessed other than through the
+    /// VM while attached.
+    pub unsafe fn set_input_source(&mut self, source: Option<NonNull<dyn InputSource>>) {
+        self.input_source = source;
     }
 
     pub fn add_builtin_static_name(
         &mut self,
         name: &'static str,
-        bi: WordFunc<T>,
+        bi: impl Builtin<T> + 'static,
     ) -> Result<(), Error> {
         let name = unsafe { FaStr::new(name.as_ptr(), name.len()) };
-        self.dict.add_bi_fastr(name, bi)?;
-        Ok(())
+        self.add_builtin_fastr(name, bi)
     }
 
-    pub fn add_builtin(&mut self, name: &str, bi: WordFunc<T>) -> Result<(), Error> {
+    /// Adds a builtin word named `name` to the dictionary.
+    ///
+    /// `bi` is moved into the dictionary, so a closure's captured state
+    /// lives there too. It's never dropped, even if the word is forgotten.
+    pub fn add_builtin(
+        &mut self,
+        name: &str,
+        bi: impl Builtin<T> + 'static,
+    ) -> Result<(), Error> {
         let name = self.dict.alloc.bump_str(name)?;
-        self.dict.add_bi_fastr(name, bi)?;
+        self.add_builtin_fastr(name, bi)
+    }
+
+    fn add_builtin_fastr<B>(&mut self, name: FaStr, bi: B) -> Result<(), Error>
+    where
+        B: Builtin<T> + 'static,
+    {
+        // The parameter field is only aligned for words.
+        const {
+            assert!(
+                align_of::<B>() <= align_of::<Word>(),
+                "builtins can't be more aligned than a `Word`",
+            )
+        };
+        let mut entry = self.dict.build_entry()?.kind(EntryKind::RuntimeBuiltin);
+        for _ in 0..size_of::<B>().div_ceil(size_of::<Word>()) {
+            entry = entry.write_word(Word::data(0))?;
+        }
+        let entry = entry.finish(name, EntryType::Builtin, Self::call_builtin::<B>);
+        unsafe { DictionaryEntry::pfa(entry).as_ptr().cast::<B>().write(bi) };
+        Ok(())
+    }
+
+    /// Runs a builtin added with [`Forth::add_builtin`], which is kept in
+    /// the parameter field of its entry.
+    fn call_builtin<B: Builtin<T>>(&mut self) -> Result<(), Error> {
+        let top = self.call_stack.try_peek()?;
+        let bi = unsafe { &*DictionaryEntry::<T>::pfa(top.eh.cast()).as_ptr().cast::<B>() };
+        bi.call(self)
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn add_async_builtin_fastr(&mut self, name: FaStr) -> Result<(), Error> {
+        self.dict
+            .add_bi_kind(name, Self::runtime_async_builtin, EntryKind::RuntimeAsyncBuiltin)?;
         Ok(())
     }
 
+    /// The function of an async builtin added at runtime, which is only
+    /// called if a sync VM runs it; an [`AsyncForth`] dispatches it to its
+    /// async builtins instead.
+    #[cfg(feature = "async")]
+    pub(crate) fn runtime_async_builtin(&mut self) -> Result<(), Error> {
+        Err(Error::AsyncBuiltinInSyncContext)
+    }
+
     fn parse_num(word: &str) -> Option<i32> {
         i32::from_str(word).ok()
     }
@@ -208,9 +368,24 @@ impl<T> Forth<T> {
             .map(NonNull::from)
     }
 
+    /// Finds the newest entry named `fastr` in the first wordlist of the
+    /// search order that has one.
     fn find_in_dict(&self, fastr: &TmpFaStr<'_>) -> Option<DictLocation<T>> {
-        self.dict.entries()
-            .find(|de| &unsafe { de.entry().as_ref() }.hdr.name == fastr.deref())
+        let mut found: Option<(usize, DictLocation<T>)> = None;
+        for loc in self.dict.entries() {
+            if unsafe { DictionaryEntry::name(loc.entry()) } != *fastr.deref() {
+                continue;
+            }
+            let wordlist = unsafe { loc.entry().as_ref() }.wordlist;
+            match self.search_order.rank(wordlist) {
+                Some(0) => return Some(loc),
+                Some(rank) if found.as_ref().is_none_or(|(best, _)| rank < *best) => {
+                    found = Some((rank, loc));
+                }
+                _ => {}
+            }
+        }
+        found.map(|(_, loc)| loc)
     }
 
     pub fn lookup(&self, word: &str) -> Result<Lookup<T>, Error> {
@@ -225,6 +400,7 @@ impl<T> Forth<T> {
             "constant" => Ok(Lookup::Constant),
             "variable" => Ok(Lookup::Variable),
             "array" => Ok(Lookup::Array),
+            "channel" => Ok(Lookup::Channel),
             r#".""# => Ok(Lookup::LQuote),
             _ => {
                 let fastr = TmpFaStr::new_from(word);
@@ -254,23 +430,96 @@ impl<T> Forth<T> {
         }
     }
 
+    /// Limits how many words may be executed before [`Forth::process_line`]
+    /// gives up with [`Error::OutOfFuel`], or removes the limit if `fuel` is
+    /// `None`.
+    ///
+    /// Every word executed uses one unit of fuel, including each word of a
+    /// `:` definition, and the fuel isn't refilled by `process_line`.
+    pub fn set_fuel(&mut self, fuel: Option<usize>) {
+        self.fuel = fuel;
+    }
+
+    /// How much fuel is left, or `None` if there is no limit.
+    pub fn fuel(&self) -> Option<usize> {
+        self.fuel
+    }
+
     pub fn process_line(&mut self) -> Result<(), Error> {
+        // A new line abandons whatever ran out of fuel on the last one.
+        if !self.call_stack.is_empty() {
+            self.return_stack.clear();
+            self.call_stack.clear();
+        }
+        self.run_line()
+    }
+
+    /// Carries on with the line that [`Forth::process_line`] was running when
+    /// it returned [`Error::OutOfFuel`], usually after adding more fuel with
+    /// [`Forth::set_fuel`].
+    ///
+    /// This may be called as many times as needed, and behaves just like
+    /// `process_line` once the line is finished.
+    pub fn resume(&mut self) -> Result<(), Error> {
+        self.run_line()
+    }
+
+    /// Runs a little more of the current line: either one word, or parsing
+    /// the next word of the line. Returns [`Step::Done`] (having written "ok."
+    /// to the output) once the line is finished.
+    ///
+    /// This lets a host interleave running a line with its own work, instead
+    /// of calling [`Forth::process_line`], which runs the line to completion.
+    /// Fill [`Forth::input`] with the line, then call `step` until it is done.
+    /// Errors clear the stacks, just like `process_line`.
+    pub fn step(&mut self) -> Result<Step, Error> {
+        let res = self.step_line();
+        self.finish_line(res)
+    }
+
+    /// Like [`Forth::step`], but takes up to `steps` steps, stopping early if
+    /// the line is finished.
+    pub fn run_for(&mut self, steps: usize) -> Result<Step, Error> {
         let res = (|| {
-            loop {
-                match self.start_processing_line()? {
-                    ProcessAction::Done => {
-                        self.output.push_str("ok.\n")?;
-                        break Ok(());
-                    },
-                    ProcessAction::Continue => {},
-                    ProcessAction::Execute =>
-                        // Loop until execution completes.
-                        while self.steppa_pig()? != Step::Done {},
+            for _ in 0..steps {
+                if self.step_line()? == Step::Done {
+                    return Ok(Step::Done);
                 }
             }
+            Ok(Step::NotDone)
+        })();
+        self.finish_line(res)
+    }
+
+    fn run_line(&mut self) -> Result<(), Error> {
+        let res = (|| {
+            while self.step_line()? != Step::Done {}
+            Ok(())
         })();
+        self.finish_line(res)
+    }
+
+    fn step_line(&mut self) -> Result<Step, Error> {
+        // Finish whatever is on the call stack before moving on through the
+        // line, which may be where we left off last time.
+        if !self.call_stack.is_empty() {
+            self.steppa_pig()?;
+            return Ok(Step::NotDone);
+        }
+        match self.start_processing_line()? {
+            ProcessAction::Done => {
+                self.output.push_str("ok.\n")?;
+                Ok(Step::Done)
+            }
+            ProcessAction::Continue | ProcessAction::Execute => Ok(Step::NotDone),
+        }
+    }
+
+    fn finish_line<R>(&mut self, res: Result<R, Error>) -> Result<R, Error> {
         match res {
-            Ok(_) => Ok(()),
+            Ok(r) => Ok(r),
+            // Leave everything as it is, to be resumed.
+            Err(Error::OutOfFuel) => Err(Error::OutOfFuel),
             Err(e) => {
                 self.data_stack.clear();
                 self.return_stack.clear();
@@ -280,6 +529,249 @@ impl<T> Forth<T> {
         }
     }
 
+    /// Interprets `src` as if it had been typed in place of the rest of the
+    /// current line, then goes back to the current line.
+    ///
+    /// This may be called by builtins (it's how the `evaluate` word works), in
+    /// which case any words called by `src` are run to completion on top of
+    /// the caller's call stack. `src` is copied into the unused space after
+    /// the current line in [`Forth::input`], so it must fit there.
+    ///
+    /// Unlike [`Forth::process_line`], no "ok." is written to the output. If
+    /// the VM wasn't already running a word, any error clears the stacks just
+    /// like `process_line` does.
+    pub fn evaluate(&mut self, src: &str) -> Result<(), Error> {
+        unsafe { self.evaluate_raw(src.as_ptr(), src.len()) }
+    }
+
+    /// Calls the word `name` with `args` pushed onto the data stack, then
+    /// pops its results.
+    ///
+    #[cfg_attr(feature = "use-std", doc = "```rust")]
+    #[cfg_attr(not(feature = "use-std"), doc = "```rust,ignore")]
+    /// # use forth3::{leakbox::{LBForth, LBForthParams}, Forth};
+    /// # let mut lbforth = LBForth::from_params(LBForthParams::default(), (), Forth::FULL_BUILTINS);
+    /// # let forth = &mut lbforth.forth;
+    /// forth.evaluate(": divmod 2dup / rot rot mod ;").unwrap();
+    /// let (quot, rem): (i32, i32) = forth.call("divmod", (7, 2)).unwrap();
+    /// assert_eq!((quot, rem), (3, 1));
+    /// ```
+    ///
+    /// Like [`Forth::evaluate`], this may be called by builtins, and the word
+    /// is run to completion.
+    pub fn call<R: FromWords>(&mut self, name: &str, args: impl IntoWords) -> Result<R, Error> {
+        let depth = self.call_stack.depth();
+        let res = (|| {
+            let (eh, len) = match self.lookup(name)? {
+                Lookup::Dict(DictLocation::Current(de) | DictLocation::Parent(de)) => {
+                    (de.cast(), unsafe { de.as_ref() }.hdr.len)
+                }
+                Lookup::Builtin { bi } => (bi.cast(), 0),
+                #[cfg(feature = "async")]
+                Lookup::Async { bi } => (bi.cast(), 0),
+                _ => return Err(Error::LookupFailed),
+            };
+            args.push_words(&mut self.data_stack)?;
+            self.call_stack.push(CallContext { eh, idx: 0, len })?;
+            while self.call_stack.depth() > depth {
+                self.steppa_pig()?;
+            }
+            R::pop_words(&mut self.data_stack)
+        })();
+        self.finish_nested(depth, res)
+    }
+
+    /// # Safety
+    ///
+    /// `src` must be valid for reads of `len` bytes.
+    pub(crate) unsafe fn evaluate_raw(&mut self, src: *const u8, len: usize) -> Result<(), Error> {
+        let depth = self.call_stack.depth();
+        let saved = self.input.push_nested(src, len)?;
+        let res = self.interpret_nested(depth);
+        self.input.pop_nested(saved);
+        self.finish_nested(depth, res)
+    }
+
+    /// Interprets each line read from `source` in turn, as nested input, then
+    /// goes back to the current line.
+    ///
+    /// Like [`Forth::evaluate`], any words called are run to completion, and
+    /// errors clear the stacks if the VM wasn't already running a word.
+    #[cfg(feature = "use-std")]
+    pub(crate) fn interpret_lines(
+        &mut self,
+        source: &mut (dyn InputSource + 'static),
+    ) -> Result<(), Error> {
+        self.interpret_line_source(LineSource::Input(NonNull::from(source)))
+    }
+
+    /// Like [`Forth::interpret_lines`], for any [`LineSource`].
+    pub(crate) fn interpret_line_source(&mut self, source: LineSource) -> Result<(), Error> {
+        let depth = self.call_stack.depth();
+        // Start with an empty nested line, which the source's lines replace.
+        let saved_input = unsafe { self.input.push_nested(NonNull::dangling().as_ptr(), 0)? };
+        let saved_source = self
+            .nested_source
+            .replace((source, self.input.nesting()));
+        let res = (|| {
+            while self.pull_input(InputRequest::Line)?.is_some() {
+                self.interpret_nested(depth)?;
+            }
+            Ok(())
+        })();
+        self.nested_source = saved_source;
+        self.input.pop_nested(saved_input);
+        self.finish_nested(depth, res)
+    }
+
+    /// Interprets the rest of the current (nested) input, running any words
+    /// it calls until the call stack is back down to `depth`.
+    fn interpret_nested(&mut self, depth: usize) -> Result<(), Error> {
+        loop {
+            match self.start_processing_line()? {
+                ProcessAction::Done => break Ok(()),
+                ProcessAction::Continue => {}
+                ProcessAction::Execute => {
+                    // Only run the words we pushed, not our caller.
+                    while self.call_stack.depth() > depth {
+                        self.steppa_pig()?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Clears the stacks if nested interpretation failed while the VM wasn't
+    /// already running a word, just like `process_line` would.
+    fn finish_nested<R>(&mut self, depth: usize, res: Result<R, Error>) -> Result<R, Error> {
+        // The rest of the nested input is gone, so there's nothing to resume.
+        let res = res.map_err(|e| match e {
+            Error::OutOfFuel => Error::OutOfFuelWhileNested,
+            e => e,
+        });
+        if res.is_err() && depth == 0 {
+            self.data_stack.clear();
+            self.return_stack.clear();
+            self.call_stack.clear();
+        }
+        res
+    }
+
+    /// Reads more input for `req` from the attached input source.
+    ///
+    /// Returns the number of bytes read (or the character, for
+    /// [`InputRequest::Key`]), or `None` if the source is exhausted.
+    ///
+    /// If only an [`AsyncInputSource`] is attached, the request is stashed and
+    /// [`Error::PendingInput`] is returned. The async VM will then perform the
+    /// read, and call the word again, at which point the result is returned.
+    pub(crate) fn pull_input(&mut self, req: InputRequest) -> Result<Option<usize>, Error> {
+        // There's no "next line" of a string being `evaluate`d, only of a
+        // file being `include`d.
+        if let (InputRequest::Line, true) = (req, self.input.is_nested()) {
+            let res = match self.line_source() {
+                Some(LineSource::Input(mut src)) => {
+                    unsafe { src.as_mut() }.read_line(self.input.raw_buf_mut())
+                }
+                Some(LineSource::Block { block, line }) => Ok(self.read_block_line(block, line)?),
+                None => return Ok(None),
+            };
+            return Ok(self.finish_input(req, res)?);
+        }
+
+        #[cfg(feature = "async")]
+        if let Some(res) = self.input_response.take() {
+            return Ok(res?);
+        }
+
+        if let Some(mut src) = self.input_source {
+            let src = unsafe { src.as_mut() };
+            let res = match req {
+                InputRequest::Line => src.read_line(self.input.raw_buf_mut()),
+                InputRequest::Key => src.read_key().map(|k| k.map(usize::from)),
+                InputRequest::Accept { addr, len } => {
+                    src.read_line(unsafe { core::slice::from_raw_parts_mut(addr, len) })
+                }
+            };
+            return Ok(self.finish_input(req, res)?);
+        }
+
+        #[cfg(feature = "async")]
+        if self.async_input_source.is_some() {
+            self.input_request = Some(req);
+            return Err(Error::PendingInput);
+        }
+
+        Err(Error::NoInputSource)
+    }
+
+    #[cfg(feature = "async")]
+    fn poll_input(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<usize>, InputError>> {
+        let (Some(mut src), Some(req)) = (self.async_input_source, self.input_request) else {
+            return Poll::Ready(Err(InputError::ReadFailed));
+        };
+        let src = unsafe { src.as_mut() };
+        let res = ready!(match req {
+            InputRequest::Line => src.poll_read_line(cx, self.input.raw_buf_mut()),
+            InputRequest::Key => src.poll_read_key(cx).map_ok(|k| k.map(usize::from)),
+            InputRequest::Accept { addr, len } => {
+                src.poll_read_line(cx, unsafe { core::slice::from_raw_parts_mut(addr, len) })
+            }
+        });
+        self.input_request = None;
+        Poll::Ready(self.finish_input(req, res))
+    }
+
+    fn finish_input(
+        &mut self,
+        req: InputRequest,
+        res: Result<Option<usize>, InputError>,
+    ) -> Result<Option<usize>, InputError> {
+        let read = res?;
+        if let (InputRequest::Line, Some(len)) = (req, read) {
+            self.input.commit(len)?;
+        }
+        Ok(read)
+    }
+
+    /// Returns the synchronous source that the next line of the current input
+    /// would be read from, if there is one.
+    fn line_source(&self) -> Option<LineSource> {
+        if !self.input.is_nested() {
+            return self.input_source.map(LineSource::Input);
+        }
+        match self.nested_source {
+            Some((src, depth)) if depth == self.input.nesting() => Some(src),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the attached input source has a character ready.
+    pub(crate) fn input_key_ready(&mut self) -> bool {
+        if let Some(mut src) = self.input_source {
+            return unsafe { src.as_mut() }.key_ready();
+        }
+
+        #[cfg(feature = "async")]
+        if let Some(mut src) = self.async_input_source {
+            return unsafe { src.as_mut() }.key_ready();
+        }
+
+        false
+    }
+
+    /// Refills the input buffer while compiling, so that definitions can span
+    /// multiple lines.
+    ///
+    /// Compilation can't be suspended, so this only uses a synchronous
+    /// [`InputSource`]. Returns `false` if no more input is available.
+    fn refill_for_compile(&mut self) -> Result<bool, Error> {
+        if self.line_source().is_none() {
+            return Ok(false);
+        }
+        Ok(self.pull_input(InputRequest::Line)?.is_some())
+    }
+
     /// Returns `true` if we must call `steppa_pig` until it returns `Ready`,
     /// false if not.
     fn start_processing_line(&mut self) -> Result<ProcessAction, Error> {
@@ -303,10 +795,18 @@ impl<T> Forth<T> {
             }
             // Found in a parent (frozen) dictionary. If this is a variable, we
             // may mutate it, so it must be copied into our dictionary.
-            // TODO(eliza): we probably only need to do this when it's a
-            // variable lookup?
+            // Anything else can be run where it is, which matters for static
+            // parent dictionaries, whose words shouldn't take up any RAM.
             Lookup::Dict(DictLocation::Parent(de)) => {
                 let dref = unsafe { de.as_ref() };
+                if !matches!(dref.ty, EntryType::Variable | EntryType::Array) {
+                    self.call_stack.push(CallContext {
+                        eh: de.cast(),
+                        idx: 0,
+                        len: dref.hdr.len,
+                    })?;
+                    return Ok(ProcessAction::Execute);
+                }
                 let mut builder = self.dict.build_entry()?;
                 unsafe {
                     let mut p = DictionaryEntry::pfa(de).as_ptr();
@@ -315,22 +815,20 @@ impl<T> Forth<T> {
                         p = p.offset(1);
                     }
                 }
-                let name = unsafe {
-                    // safety: a `FaStr` points to a string region stored in a
-                    // dictionary. we can alias the name because our dictionary
-                    // holds a reference to the parent dictionary, keeping it
-                    // alive as long as our dictionary exists, and the new
-                    // pointer will be in a value in our dictionary.
-                    //
-                    // IF IT WAS POSSIBLE FOR PARENTS TO BE DROPPED WHILE THEIR
-                    // FORKS EXIST, THIS WOULD BE A DANGLING POINTER. IF YOU
-                    // EVER CHANGE THE PARENT REFERENCE COUNTING RULES TO ALLOW
-                    // PARENTS TO BE DEALLOCATED WHILE A CHILD EXISTS, YOU MUST
-                    // CHANGE THIS TO DEEP COPY THE `FaStr` INTO THE CHILD
-                    // DICT'S ARENA.
-                    dref.hdr.name.copy_in_child()
-                };
-                let entry = builder.kind(dref.hdr.kind).finish(name, dref.func);
+                // a `FaStr` points to a string region stored in a
+                // dictionary. we can alias the name because our dictionary
+                // holds a reference to the parent dictionary, keeping it
+                // alive as long as our dictionary exists, and the new
+                // pointer will be in a value in our dictionary.
+                //
+                // IF IT WAS POSSIBLE FOR PARENTS TO BE DROPPED WHILE THEIR
+                // FORKS EXIST, THIS WOULD BE A DANGLING POINTER. IF YOU
+                // EVER CHANGE THE PARENT REFERENCE COUNTING RULES TO ALLOW
+                // PARENTS TO BE DEALLOCATED WHILE A CHILD EXISTS, YOU MUST
+                // CHANGE THIS TO DEEP COPY THE `FaStr` INTO THE CHILD
+                // DICT'S ARENA.
+                let name = unsafe { DictionaryEntry::name(de) };
+                let entry = builder.kind(dref.hdr.kind).finish(name, dref.ty, dref.func);
                 self.call_stack.push(CallContext {
                     eh: entry.cast(),
                     idx: 0,
@@ -388,6 +886,9 @@ impl<T> Forth<T> {
             Lookup::Array => {
                 self.munch_array(&mut 0)?;
             }
+            Lookup::Channel => {
+                self.munch_channel(&mut 0)?;
+            }
         }
 
         Ok(ProcessAction::Continue)
@@ -401,20 +902,25 @@ impl<T> Forth<T> {
             Err(e) => return Err(Error::Stack(e)),
         };
 
+        match self.fuel {
+            Some(0) => return Err(Error::OutOfFuel),
+            Some(ref mut fuel) => *fuel -= 1,
+            None => {}
+        }
+
         let kind = unsafe { top.eh.as_ref().kind };
         let res = unsafe { match kind {
             EntryKind::StaticBuiltin => (top.eh.cast::<BuiltinEntry<T>>().as_ref().func)(self),
             EntryKind::RuntimeBuiltin => (top.eh.cast::<BuiltinEntry<T>>().as_ref().func)(self),
             EntryKind::Dictionary => (top.eh.cast::<DictionaryEntry<T>>().as_ref().func)(self),
 
+            // An `AsyncForth` VM only performs non-async execution steps when
+            // a builtin `evaluate`s a string, which can't wait on an async
+            // builtin.
             #[cfg(feature = "async")]
-            EntryKind::AsyncBuiltin => {
-                unreachable!(
-                    "only an AsyncForth VM should have async builtins, and an \
-                    AsyncForth VM should never perform a non-async execution \
-                    step! this is a bug."
-                )
-            },
+            EntryKind::AsyncBuiltin | EntryKind::RuntimeAsyncBuiltin => {
+                Err(Error::AsyncBuiltinInSyncContext)
+            }
         }};
 
         match res {
@@ -436,7 +942,7 @@ impl<T> Forth<T> {
 
         if let Some(word) = top.get_word_at_cur_idx() {
             // Push the item in the list to the top of stack, will be executed on next step
-            let ptr = unsafe { word.ptr.cast::<EntryHeader<T>>() };
+            let ptr = self.dict.resolve_in(top.eh, *word).cast::<EntryHeader<T>>();
             let nn = NonNull::new(ptr).ok_or(Error::NullPointerInCFA)?;
             let ehref = unsafe { nn.as_ref() };
             let callee = CallContext {
@@ -463,7 +969,7 @@ impl<T> Forth<T> {
 
         // Write a conditional jump, followed by space for a literal
         let literal_cj = self.find_word("2d>2r").ok_or(Error::WordNotInDict)?;
-        self.dict.alloc.bump_write(Word::ptr(literal_cj.as_ptr()))?;
+        self.dict.bump_ref(literal_cj)?;
         *len += 1;
 
         let do_start = *len;
@@ -484,8 +990,7 @@ impl<T> Forth<T> {
         let delta = *len - do_start;
         let offset = i32::from(delta + 1).neg();
         let literal_dojmp = self.find_word("(jmp-doloop)").ok_or(Error::WordNotInDict)?;
-        self.dict.alloc
-            .bump_write(Word::ptr(literal_dojmp.as_ptr()))?;
+        self.dict.bump_ref(literal_dojmp)?;
         self.dict.alloc.bump_write(Word::data(offset))?;
         *len += 2;
 
@@ -497,7 +1002,7 @@ impl<T> Forth<T> {
 
         // Write a conditional jump, followed by space for a literal
         let literal_cj = self.find_word("(jump-zero)").ok_or(Error::WordNotInDict)?;
-        self.dict.alloc.bump_write(Word::ptr(literal_cj.as_ptr()))?;
+        self.dict.bump_ref(literal_cj)?;
         let cj_offset: &mut i32 = {
             let cj_offset_word = self.dict.alloc.bump::<Word>()?;
             unsafe {
@@ -542,8 +1047,7 @@ impl<T> Forth<T> {
 
         // Write a conditional jump, followed by space for a literal
         let literal_jmp = self.find_word("(jmp)").ok_or(Error::WordNotInDict)?;
-        self.dict.alloc
-            .bump_write(Word::ptr(literal_jmp.as_ptr()))?;
+        self.dict.bump_ref(literal_jmp)?;
         let jmp_offset: &mut i32 = {
             let jmp_offset_word = self.dict.alloc.bump::<Word>()?;
             unsafe {
@@ -577,10 +1081,14 @@ impl<T> Forth<T> {
     fn munch_one(&mut self, len: &mut u16) -> Result<u16, Error> {
         let start = *len;
         self.input.advance();
-        let word = match self.input.cur_word() {
-            Some(w) => w,
-            None => return Ok(0),
-        };
+        while self.input.cur_word().is_none() {
+            // We hit the end of the line, see if there's more to compile.
+            if !self.refill_for_compile()? {
+                return Ok(0);
+            }
+            self.input.advance();
+        }
+        let word = self.input.cur_word().ok_or(Error::InternalError)?;
 
         match self.lookup(word)? {
             Lookup::If => return self.munch_if(len),
@@ -590,16 +1098,16 @@ impl<T> Forth<T> {
             Lookup::Dict(DictLocation::Current(de)) | Lookup::Dict(DictLocation::Parent(de)) => {
                 // Dictionary items are put into the CFA array directly as
                 // a pointer to the dictionary entry
-                self.dict.alloc.bump_write(Word::ptr(de.as_ptr()))?;
+                self.dict.bump_ref(de)?;
                 *len += 1;
             }
             Lookup::Builtin { bi } => {
-                self.dict.alloc.bump_write(Word::ptr(bi.as_ptr()))?;
+                self.dict.bump_ref(bi)?;
                 *len += 1;
             }
             #[cfg(feature = "async")]
             Lookup::Async { bi } => {
-                self.dict.alloc.bump_write(Word::ptr(bi.as_ptr()))?;
+                self.dict.bump_ref(bi)?;
                 *len += 1;
             }
             #[cfg(feature = "floats")]
@@ -609,8 +1117,7 @@ impl<T> Forth<T> {
                 // 1. The address of the `literal()` dictionary item
                 // 2. The value of the literal, as a data word
                 let literal_dict = self.find_word("(literal)").ok_or(Error::WordNotInDict)?;
-                self.dict.alloc
-                    .bump_write(Word::ptr(literal_dict.as_ptr()))?;
+                self.dict.bump_ref(literal_dict)?;
                 self.dict.alloc.bump_write(Word::float(val))?;
                 *len += 2;
             }
@@ -620,8 +1127,7 @@ impl<T> Forth<T> {
                 // 1. The address of the `literal()` dictionary item
                 // 2. The value of the literal, as a data word
                 let literal_dict = self.find_word("(literal)").ok_or(Error::WordNotInDict)?;
-                self.dict.alloc
-                    .bump_write(Word::ptr(literal_dict.as_ptr()))?;
+                self.dict.bump_ref(literal_dict)?;
                 self.dict.alloc.bump_write(Word::data(val))?;
                 *len += 2;
             }
@@ -632,6 +1138,7 @@ impl<T> Forth<T> {
             Lookup::Constant => return self.munch_constant(len),
             Lookup::Variable => return self.munch_variable(len),
             Lookup::Array => return self.munch_array(len),
+            Lookup::Channel => return self.munch_channel(len),
         }
         Ok(*len - start)
     }
@@ -664,27 +1171,26 @@ impl<T> Forth<T> {
             .cur_str_literal()
             .ok_or(Error::LQuoteMissingRQuote)?;
         let str_len =
-            u16::try_from(lit_str.as_bytes().len()).replace_err(Error::LiteralStringTooLong)?;
+            u16::try_from(lit_str.len()).replace_err(Error::LiteralStringTooLong)?;
 
         let literal_writestr = self.find_word("(write-str)").ok_or(Error::WordNotInDict)?;
-        self.dict.alloc
-            .bump_write::<Word>(Word::ptr(literal_writestr.as_ptr()))?;
+        self.dict.bump_ref(literal_writestr)?;
         self.dict.alloc
             .bump_write::<Word>(Word::data(str_len.into()))?;
         *len += 2;
 
         let start_ptr = self
             .dict.alloc
-            .bump_u8s(lit_str.as_bytes().len())
+            .bump_u8s(lit_str.len())
             .ok_or(Error::Bump(BumpError::OutOfMemory))?;
 
         unsafe {
             start_ptr
                 .as_ptr()
-                .copy_from_nonoverlapping(lit_str.as_bytes().as_ptr(), lit_str.as_bytes().len());
+                .copy_from_nonoverlapping(lit_str.as_bytes().as_ptr(), lit_str.len());
         }
         let word_size = size_of::<Word>();
-        let words_written = (str_len as usize + (word_size - 1)) / word_size;
+        let words_written = (str_len as usize).div_ceil(word_size);
         *len += words_written as u16;
 
         Ok(*len - start)
@@ -715,7 +1221,7 @@ impl<T> Forth<T> {
         self.dict.build_entry()?.write_word(Word::data(value_i32))?
             // TODO: Should we look up `(constant)` for consistency?
             // Use `find_word`?
-            .finish(name, Self::constant);
+            .finish(name, EntryType::Constant, Self::constant);
         Ok(0)
     }
 
@@ -725,7 +1231,7 @@ impl<T> Forth<T> {
         self.dict.build_entry()?.write_word(Word::data(0))?
             // TODO: Should we look up `(variable)` for consistency?
             // Use `find_word`?
-            .finish(name, Self::variable);
+            .finish(name, EntryType::Variable, Self::variable);
         Ok(0)
     }
 
@@ -750,7 +1256,7 @@ impl<T> Forth<T> {
         //
         // TODO: Should we look up `(variable)` for consistency?
         // Use `find_word`?
-        entry.finish(name, Self::variable);
+        entry.finish(name, EntryType::Array, Self::variable);
         Ok(0)
     }
 }
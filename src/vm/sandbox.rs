@@ -0,0 +1,207 @@
+//! Checking the memory that Forth code uses.
+//!
+//! Words like `@` and `!` use whatever address is on the stack, so Forth
+//! code can read or write any memory at all. That's fine for trusted code,
+//! but a VM running untrusted code should be [sandboxed], so that these words
+//! fail with [`Error::AccessViolation`] instead.
+//!
+//! [sandboxed]: Forth::set_sandboxed
+
+use core::{mem::size_of, ptr::NonNull};
+
+use crate::{
+    dictionary::{DictLocation, DictionaryEntry, EntryHeader, EntryType},
+    word::Word,
+    Error, Forth,
+};
+
+/// The most regions that can be granted to a sandboxed VM.
+const MAX_REGIONS: usize = 4;
+
+/// What Forth code in a sandboxed VM may access, other than its own
+/// dictionary.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Sandbox {
+    regions: [Option<NonNull<[u8]>>; MAX_REGIONS],
+}
+
+/// How memory is being accessed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Access {
+    Read,
+    Write,
+}
+
+impl<T: 'static> Forth<T> {
+    /// Turns sandboxing on or off.
+    ///
+    /// In a sandboxed VM, words that use memory at an address on the stack
+    /// (such as `@`, `!`, `b@`, `b!`, `evaluate` and `read-file`) fail with
+    /// [`Error::AccessViolation`] unless the memory is:
+    ///
+    /// - to read it, in the VM's dictionary or a parent dictionary that it
+    ///   was forked from,
+    /// - to write it, in a `variable` or `array` in the VM's dictionary,
+    /// - in its input buffer, to read it,
+    /// - in one of its block buffers, or
+    /// - in a region granted with [`Forth::grant_region`].
+    ///
+    /// Words that take an execution token (`execute` and `spawn`) check that
+    /// it's a word in the dictionary or a builtin, and channel words check
+    /// that they were given a channel.
+    ///
+    /// VMs forked from a sandboxed VM are sandboxed too, with the same
+    /// granted regions.
+    ///
+    /// This doesn't stop Forth code from using words such as `open-file` or
+    /// `include`. To take those away, leave them out of the VM's builtins
+    /// with a [`BuiltinSet`](crate::builtin_set::BuiltinSet).
+    pub fn set_sandboxed(&mut self, sandboxed: bool) {
+        self.sandbox = match (sandboxed, self.sandbox) {
+            (true, Some(sandbox)) => Some(sandbox),
+            (true, None) => Some(Sandbox::default()),
+            (false, _) => None,
+        };
+    }
+
+    pub fn is_sandboxed(&self) -> bool {
+        self.sandbox.is_some()
+    }
+
+    /// Lets Forth code in a sandboxed VM read and write `region`, e.g. for a
+    /// buffer shared with the host. This turns sandboxing on, if it wasn't
+    /// already.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `region` is valid for reads and writes for
+    /// as long as it's granted, and that it isn't accessed other than through
+    /// the VM during that time.
+    pub unsafe fn grant_region(&mut self, region: NonNull<[u8]>) -> Result<(), Error> {
+        let sandbox = self.sandbox.get_or_insert_with(Sandbox::default);
+        let slot = sandbox
+            .regions
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(Error::TooManyRegions)?;
+        *slot = Some(region);
+        Ok(())
+    }
+
+    /// Takes back all of the regions granted with [`Forth::grant_region`].
+    pub fn revoke_regions(&mut self) {
+        if let Some(sandbox) = self.sandbox.as_mut() {
+            sandbox.regions = [None; MAX_REGIONS];
+        }
+    }
+
+    /// Checks that Forth code may access the `len` bytes at `addr`.
+    pub(crate) fn check_access(
+        &self,
+        addr: *const u8,
+        len: usize,
+        access: Access,
+    ) -> Result<(), Error> {
+        let Some(sandbox) = self.sandbox.as_ref() else {
+            return Ok(());
+        };
+        let in_region = |start: *const u8, region_len: usize| {
+            let (start, addr) = (start as usize, addr as usize);
+            addr >= start
+                && addr
+                    .checked_add(len)
+                    .is_some_and(|end| end <= start + region_len)
+        };
+        let (input, input_len) = self.input.source();
+        let in_dict = match access {
+            Access::Read => self.dict.alloc.contains_range(addr, len),
+            // Only the values of variables and arrays can be written, rather
+            // than the headers and code around them. Entries are allocated in
+            // order and listed newest first, so the only entry that can hold
+            // `addr` is the first one that starts before it.
+            Access::Write => {
+                self.dict.alloc.contains_range(addr, len)
+                    && self
+                        .dict
+                        .entries()
+                        .map_while(|loc| match loc {
+                            DictLocation::Current(de) => Some(de),
+                            DictLocation::Parent(_) => None,
+                        })
+                        .find(|de| de.as_ptr() as usize <= addr as usize)
+                        .is_some_and(|de| {
+                            let entry = unsafe { de.as_ref() };
+                            let pfa = unsafe { DictionaryEntry::pfa(de) };
+                            matches!(entry.ty, EntryType::Variable | EntryType::Array)
+                                && in_region(
+                                    pfa.as_ptr().cast(),
+                                    usize::from(entry.hdr.len) * size_of::<Word>(),
+                                )
+                        })
+            }
+        };
+        let allowed = in_dict
+            || self.blocks.as_ref().is_some_and(|blocks| blocks.data_contains(addr, len))
+            || sandbox
+                .regions
+                .iter()
+                .flatten()
+                .any(|region| in_region(region.as_ptr().cast(), region.len()))
+            || (access == Access::Read
+                && (self.dict.parents_contain(addr, len) || in_region(input, input_len)));
+        if allowed {
+            Ok(())
+        } else {
+            Err(Error::AccessViolation)
+        }
+    }
+
+    /// Checks that Forth code may access the word at `addr`, which must also
+    /// be aligned.
+    pub(crate) fn check_word_access(
+        &self,
+        addr: *const Word,
+        access: Access,
+    ) -> Result<(), Error> {
+        if self.sandbox.is_some() && !addr.is_aligned() {
+            return Err(Error::AccessViolation);
+        }
+        self.check_access(addr.cast(), size_of::<Word>(), access)
+    }
+
+    /// Checks that `xt` is a word that can be executed, returning its header.
+    pub(crate) fn check_xt(&self, xt: Word) -> Result<NonNull<EntryHeader<T>>, Error> {
+        let eh = NonNull::new(unsafe { xt.ptr.cast::<EntryHeader<T>>() })
+            .ok_or(Error::NullPointerInCFA)?;
+        if self.sandbox.is_none() {
+            return Ok(eh);
+        }
+        let ptr = eh.as_ptr().cast::<()>();
+        let is_entry = self.dict.entries().any(|loc| loc.entry().as_ptr().cast() == ptr)
+            || self.builtin_at(ptr).is_some();
+        #[cfg(feature = "async")]
+        let is_entry = is_entry || self.async_builtin_at(ptr).is_some();
+        if is_entry {
+            Ok(eh)
+        } else {
+            Err(Error::AccessViolation)
+        }
+    }
+
+    /// Checks that `chan` is the address of a channel.
+    pub(crate) fn check_channel(&self, chan: *mut ()) -> Result<(), Error> {
+        if self.sandbox.is_none() {
+            return Ok(());
+        }
+        let is_channel = self.dict.entries().any(|loc| {
+            let de = loc.entry();
+            let pfa = unsafe { DictionaryEntry::pfa(de) };
+            pfa.as_ptr().cast() == chan && unsafe { de.as_ref() }.ty == EntryType::Channel
+        });
+        if is_channel {
+            Ok(())
+        } else {
+            Err(Error::AccessViolation)
+        }
+    }
+}
@@ -0,0 +1,435 @@
+//! Checkpointing a whole VM, so that it can be restored later (or on another
+//! VM).
+//!
+//! A [`Snapshot`] holds everything needed to carry on from where the VM was:
+//! its current dictionary (as an image, see [`Forth::save_image`]), its data,
+//! return and call stacks, its [`Mode`], the line of input it's parsing, and
+//! its search order and current wordlist.
+//! Frames of the call stack are stored as references to entries in the
+//! image, or by name for builtins and entries in parent dictionaries, just
+//! like the cells of an image.
+//!
+//! [`Snapshot::to_bytes`] encodes a snapshot in the same little-endian style
+//! as an image:
+//!
+//! | bytes | contents                                             |
+//! |-------|------------------------------------------------------|
+//! | 4     | the magic number `F3SN`                              |
+//! | 1     | the format version                                   |
+//! | 1     | `size_of::<Word>()` of the saving VM                 |
+//! | 1     | the mode: 0 for [`Mode::Run`], 1 for [`Mode::Compile`] |
+//! | 4 + n | the dictionary image, as its length and bytes        |
+//! | 4 + n | the data stack, as its depth and `i64`s, bottom first |
+//! | 4 + n | the return stack, likewise                           |
+//! | 4 + n | the call stack, as its depth and frames, bottom first |
+//! | 4 + n | the current line of input, as its length and bytes   |
+//! | 4     | how far into the line parsing has got                |
+//! | 1 + n | the search order, as its length and `u16` wids, first first |
+//! | 2     | the current wordlist                                 |
+//!
+//! A frame is a tag byte followed by its target (an entry index as a `u32`,
+//! or a name as a length byte and its bytes), then its position and length
+//! as `u16`s.
+
+use core::{mem::size_of, ptr::NonNull};
+use std::{string::String, vec, vec::Vec};
+
+use crate::{
+    dictionary::{DictLocation, DictionaryEntry, EntryHeader},
+    stack::StackError,
+    vm::{
+        builtins::wordlist::MAX_ORDER,
+        image::{ImageError, Reader},
+    },
+    word::Word,
+    CallContext, Error, Forth, Mode,
+};
+
+const MAGIC: &[u8; 4] = b"F3SN";
+const VERSION: u8 = 2;
+
+const FRAME_ENTRY: u8 = 0;
+const FRAME_BUILTIN: u8 = 1;
+const FRAME_ASYNC_BUILTIN: u8 = 2;
+const FRAME_PARENT: u8 = 3;
+
+/// The state of a VM, as saved by [`Forth::snapshot`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// The current dictionary, as an image.
+    pub dictionary: Vec<u8>,
+    /// The data stack, bottom first.
+    pub data_stack: Vec<i64>,
+    /// The return stack, bottom first.
+    pub return_stack: Vec<i64>,
+    /// The call stack, bottom first.
+    pub call_stack: Vec<Frame>,
+    pub mode: Mode,
+    /// The line of input being parsed.
+    pub input: Vec<u8>,
+    /// How many bytes of `input` have been parsed.
+    pub input_cursor: usize,
+    /// The wordlists to search, in the order they're searched.
+    pub search_order: Vec<u16>,
+    /// The wordlist that new words are added to.
+    pub current: u16,
+}
+
+/// A frame of the call stack in a [`Snapshot`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub target: FrameTarget,
+    /// The position in the target's parameter field.
+    pub idx: u16,
+    /// The length of the target's parameter field.
+    pub len: u16,
+}
+
+/// What a [`Frame`] is running.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameTarget {
+    /// An entry in the snapshot's dictionary, by its index in the image
+    /// (oldest first).
+    Entry(u32),
+    Builtin(String),
+    AsyncBuiltin(String),
+    /// An entry in a parent dictionary, by name.
+    Parent(String),
+}
+
+impl<T: 'static> Forth<T> {
+    /// Saves the state of the VM, to be restored with [`Forth::restore`].
+    ///
+    /// This may be called by builtins, so that a word can checkpoint the VM
+    /// part way through running. It can't be called while a string is being
+    /// `evaluate`d or a file `include`d, as the state of those is held by the
+    /// host's stack.
+    ///
+    /// As with [`Forth::save_image`], words on the stacks and in variables
+    /// are saved as they are, so any pointers in them are only meaningful to
+    /// a VM whose dictionary is laid out the same way.
+    pub fn snapshot(&self) -> Result<Snapshot, Error> {
+        if self.input.is_nested() {
+            return Err(Error::SnapshotWhileNested);
+        }
+
+        let mut dictionary = vec![0; 256];
+        let len = loop {
+            match self.save_image(&mut dictionary) {
+                Err(Error::Image(ImageError::BufferTooSmall)) => {
+                    dictionary.resize(dictionary.len() * 2, 0);
+                }
+                res => break res?,
+            }
+        };
+        dictionary.truncate(len);
+
+        let count = self.current_entries().count();
+        let call_stack = (0..self.call_stack.depth())
+            .rev()
+            .map(|n| {
+                let ctx = self.call_stack.peek_back_n(n).ok_or(Error::InternalError)?;
+                Ok(Frame {
+                    target: self.frame_target(count, ctx.eh)?,
+                    idx: ctx.idx,
+                    len: ctx.len,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        let (line, input_cursor) = self.input.line_and_cursor();
+        Ok(Snapshot {
+            dictionary,
+            data_stack: stack_words(self.data_stack.depth(), |n| self.data_stack.peek_back_n(n)),
+            return_stack: stack_words(self.return_stack.depth(), |n| {
+                self.return_stack.peek_back_n(n)
+            }),
+            call_stack,
+            mode: self.mode,
+            input: line.to_vec(),
+            input_cursor,
+            search_order: self.search_order.wids().to_vec(),
+            current: self.dict.current,
+        })
+    }
+
+    /// Puts the VM back into the state saved in `snapshot`, by this VM or
+    /// another with the same word size, builtins and parent dictionaries.
+    ///
+    /// The current dictionary is replaced by the snapshot's, so anything
+    /// allocated from it must not be used afterwards. If the block device's
+    /// buffers were allocated from it, the device is saved and detached.
+    ///
+    /// If restoring fails, the VM is left as it was (other than the block
+    /// device being detached, if it had to be).
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), Error> {
+        if self.input.is_nested() {
+            return Err(Error::SnapshotWhileNested);
+        }
+        if snapshot.input_cursor > snapshot.input.len() {
+            return Err(ImageError::Malformed.into());
+        }
+        self.input.check_line(&snapshot.input)?;
+        if snapshot.search_order.len() > MAX_ORDER {
+            return Err(ImageError::Malformed.into());
+        }
+        let data_stack = words_from(&snapshot.data_stack)?;
+        let return_stack = words_from(&snapshot.return_stack)?;
+        if data_stack.len() > self.data_stack.capacity()
+            || return_stack.len() > self.return_stack.capacity()
+            || snapshot.call_stack.len() > self.call_stack.capacity()
+        {
+            return Err(StackError::StackFull.into());
+        }
+
+        // Loading the snapshot's dictionary overwrites this one, so keep a
+        // copy of it to put back if that fails.
+        self.release_block_buffers(self.dict.alloc.start)?;
+        let (start, used) = (self.dict.alloc.start, self.dict.alloc.used());
+        let saved = unsafe { core::slice::from_raw_parts(start, used) }.to_vec();
+        let tail = self.dict.tail;
+        let search_order = self.search_order.clone();
+        self.dict.tail = None;
+        self.dict.alloc.cur = start;
+        let frames = self.load_image(&snapshot.dictionary).and_then(|()| {
+            let count = self.current_entries().count();
+            snapshot
+                .call_stack
+                .iter()
+                .map(|frame| {
+                    Ok(CallContext {
+                        eh: self.frame_entry(count, &frame.target)?,
+                        idx: frame.idx,
+                        len: frame.len,
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()
+        });
+        let frames = match frames {
+            Ok(frames) => frames,
+            Err(e) => {
+                unsafe { core::ptr::copy_nonoverlapping(saved.as_ptr(), start, used) };
+                self.dict.tail = tail;
+                self.dict.alloc.cur = start.wrapping_add(used);
+                self.search_order = search_order;
+                return Err(e);
+            }
+        };
+
+        // Nothing below can fail, as it was all checked above.
+        self.data_stack.clear();
+        for word in data_stack {
+            self.data_stack.push(word)?;
+        }
+        self.return_stack.clear();
+        for word in return_stack {
+            self.return_stack.push(word)?;
+        }
+        self.call_stack.clear();
+        for frame in frames {
+            self.call_stack.push(frame)?;
+        }
+        self.mode = snapshot.mode;
+        self.input.restore_line(&snapshot.input, snapshot.input_cursor)?;
+        self.search_order.set_wids(&snapshot.search_order);
+        self.search_order.reserve(snapshot.current);
+        self.dict.current = snapshot.current;
+        Ok(())
+    }
+
+    fn frame_target(
+        &self,
+        count: usize,
+        eh: NonNull<EntryHeader<T>>,
+    ) -> Result<FrameTarget, Error> {
+        let ptr = eh.as_ptr().cast::<()>();
+        if let Some(bi) = self.builtin_at(ptr) {
+            return Ok(FrameTarget::Builtin(bi.hdr.name.as_str().into()));
+        }
+        #[cfg(feature = "async")]
+        if let Some(bi) = self.async_builtin_at(ptr) {
+            return Ok(FrameTarget::AsyncBuiltin(bi.hdr.name.as_str().into()));
+        }
+        let target = self
+            .dict
+            .entries()
+            .enumerate()
+            .find(|(_, loc)| loc.entry().as_ptr().cast() == ptr)
+            .ok_or(ImageError::UnknownPointer)?;
+        Ok(match target {
+            (pos, DictLocation::Current(_)) => FrameTarget::Entry((count - 1 - pos) as u32),
+            (_, DictLocation::Parent(de)) => {
+                FrameTarget::Parent(unsafe { DictionaryEntry::name(de) }.as_str().into())
+            }
+        })
+    }
+
+    fn frame_entry(
+        &self,
+        count: usize,
+        target: &FrameTarget,
+    ) -> Result<NonNull<EntryHeader<T>>, Error> {
+        Ok(match target {
+            FrameTarget::Entry(idx) => {
+                let idx = *idx as usize;
+                if idx >= count {
+                    return Err(ImageError::Malformed.into());
+                }
+                self.current_entry(count, idx)?.cast()
+            }
+            FrameTarget::Builtin(name) => self.find_builtin(name)?.cast(),
+            #[cfg(feature = "async")]
+            FrameTarget::AsyncBuiltin(name) => {
+                let name = crate::fastr::TmpFaStr::new_from(name);
+                self.find_in_async_bis(&name)
+                    .ok_or(ImageError::MissingWord)?
+                    .cast()
+            }
+            #[cfg(not(feature = "async"))]
+            FrameTarget::AsyncBuiltin(_) => return Err(ImageError::MissingWord.into()),
+            FrameTarget::Parent(name) => self.find_parent(name)?.cast(),
+        })
+    }
+}
+
+fn stack_words(depth: usize, peek_back_n: impl Fn(usize) -> Option<Word>) -> Vec<i64> {
+    (0..depth)
+        .rev()
+        .filter_map(peek_back_n)
+        .map(|word| unsafe { word.ptr_data } as i64)
+        .collect()
+}
+
+fn words_from(vals: &[i64]) -> Result<Vec<Word>, Error> {
+    vals.iter()
+        .map(|&val| {
+            let val = isize::try_from(val).map_err(|_| ImageError::Malformed)?;
+            Ok(Word::ptr_data(val))
+        })
+        .collect()
+}
+
+// === impl Snapshot ===
+
+impl Snapshot {
+    /// Encodes the snapshot as bytes, to be decoded by
+    /// [`Snapshot::from_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(MAGIC);
+        buf.push(VERSION);
+        buf.push(size_of::<Word>() as u8);
+        buf.push(match self.mode {
+            Mode::Run => 0,
+            Mode::Compile => 1,
+        });
+        put_bytes(&mut buf, &self.dictionary);
+        for stack in [&self.data_stack, &self.return_stack] {
+            put_u32(&mut buf, stack.len());
+            for word in stack {
+                buf.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        put_u32(&mut buf, self.call_stack.len());
+        for frame in &self.call_stack {
+            match &frame.target {
+                FrameTarget::Entry(idx) => {
+                    buf.push(FRAME_ENTRY);
+                    buf.extend_from_slice(&idx.to_le_bytes());
+                }
+                FrameTarget::Builtin(name) => put_name(&mut buf, FRAME_BUILTIN, name),
+                FrameTarget::AsyncBuiltin(name) => put_name(&mut buf, FRAME_ASYNC_BUILTIN, name),
+                FrameTarget::Parent(name) => put_name(&mut buf, FRAME_PARENT, name),
+            }
+            buf.extend_from_slice(&frame.idx.to_le_bytes());
+            buf.extend_from_slice(&frame.len.to_le_bytes());
+        }
+        put_bytes(&mut buf, &self.input);
+        put_u32(&mut buf, self.input_cursor);
+        // The search order holds at most `MAX_ORDER` wordlists.
+        buf.push(self.search_order.len() as u8);
+        for wid in &self.search_order {
+            buf.extend_from_slice(&wid.to_le_bytes());
+        }
+        buf.extend_from_slice(&self.current.to_le_bytes());
+        buf
+    }
+
+    /// Decodes a snapshot encoded by [`Snapshot::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ImageError> {
+        let mut r = Reader::raw(bytes);
+        if r.bytes(MAGIC.len())? != MAGIC || r.u8()? != VERSION {
+            return Err(ImageError::BadMagic);
+        }
+        if usize::from(r.u8()?) != size_of::<Word>() {
+            return Err(ImageError::WordSizeMismatch);
+        }
+        let mode = match r.u8()? {
+            0 => Mode::Run,
+            1 => Mode::Compile,
+            _ => return Err(ImageError::Malformed),
+        };
+        let dictionary = get_bytes(&mut r)?.to_vec();
+        let data_stack = get_words(&mut r)?;
+        let return_stack = get_words(&mut r)?;
+        let call_stack = (0..r.u32()?)
+            .map(|_| {
+                let target = match r.u8()? {
+                    FRAME_ENTRY => FrameTarget::Entry(r.u32()?),
+                    FRAME_BUILTIN => FrameTarget::Builtin(r.name_str()?.into()),
+                    FRAME_ASYNC_BUILTIN => FrameTarget::AsyncBuiltin(r.name_str()?.into()),
+                    FRAME_PARENT => FrameTarget::Parent(r.name_str()?.into()),
+                    _ => return Err(ImageError::Malformed),
+                };
+                Ok(Frame {
+                    target,
+                    idx: r.u16()?,
+                    len: r.u16()?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let input = get_bytes(&mut r)?.to_vec();
+        let input_cursor = r.u32()? as usize;
+        let search_order = (0..r.u8()?).map(|_| r.u16()).collect::<Result<_, _>>()?;
+        let current = r.u16()?;
+        if !r.is_done() {
+            return Err(ImageError::Malformed);
+        }
+        Ok(Self {
+            dictionary,
+            data_stack,
+            return_stack,
+            call_stack,
+            mode,
+            input,
+            input_cursor,
+            search_order,
+            current,
+        })
+    }
+}
+
+fn put_u32(buf: &mut Vec<u8>, val: usize) {
+    buf.extend_from_slice(&(val as u32).to_le_bytes());
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    put_u32(buf, bytes.len());
+    buf.extend_from_slice(bytes);
+}
+
+fn put_name(buf: &mut Vec<u8>, tag: u8, name: &str) {
+    // Names are at most 31 bytes long.
+    buf.push(tag);
+    buf.push(name.len() as u8);
+    buf.extend_from_slice(name.as_bytes());
+}
+
+fn get_bytes<'a>(r: &mut Reader<'a>) -> Result<&'a [u8], ImageError> {
+    let len = r.u32()? as usize;
+    r.bytes(len)
+}
+
+fn get_words(r: &mut Reader<'_>) -> Result<Vec<i64>, ImageError> {
+    (0..r.u32()?).map(|_| r.i64()).collect()
+}
@@ -0,0 +1,125 @@
+//! Reporting how much memory a VM is using, and limiting how much of its
+//! dictionary it may use.
+
+use core::fmt::Write;
+
+use crate::{dictionary::Dictionary, stack::Stack, Error, Forth};
+
+/// How much of a VM's memory is in use, as returned by
+/// [`Forth::memory_stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// The VM's own dictionary. See [`Forth::dict_stats`] for the
+    /// dictionaries it was forked from.
+    pub dictionary: DictStats,
+    pub data_stack: StackStats,
+    pub return_stack: StackStats,
+    pub call_stack: StackStats,
+}
+
+/// How much of a dictionary is in use, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DictStats {
+    pub used: usize,
+    pub capacity: usize,
+    /// The most that may be used, if it's less than the capacity. See
+    /// [`Forth::set_dict_quota`].
+    pub quota: Option<usize>,
+}
+
+/// How much of a stack is in use, in items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackStats {
+    pub depth: usize,
+    /// The most items that have been on the stack at once.
+    pub high_water: usize,
+    pub capacity: usize,
+}
+
+impl DictStats {
+    fn new<T: 'static>(dict: &Dictionary<T>) -> Self {
+        Self {
+            used: dict.alloc.used(),
+            capacity: dict.alloc.capacity(),
+            quota: dict.alloc.quota(),
+        }
+    }
+}
+
+impl StackStats {
+    fn new<U: Copy>(stack: &Stack<U>) -> Self {
+        Self {
+            depth: stack.depth(),
+            high_water: stack.high_water(),
+            capacity: stack.capacity(),
+        }
+    }
+}
+
+impl<T: 'static> Forth<T> {
+    /// Returns how much of the VM's dictionary and stacks is in use.
+    pub fn memory_stats(&self) -> MemoryStats {
+        MemoryStats {
+            dictionary: DictStats::new(&self.dict),
+            data_stack: StackStats::new(&self.data_stack),
+            return_stack: StackStats::new(&self.return_stack),
+            call_stack: StackStats::new(&self.call_stack),
+        }
+    }
+
+    /// Starts measuring the stacks' [`StackStats::high_water`] marks again
+    /// from their current depths, e.g. before running a script whose stack
+    /// usage is being measured.
+    pub fn reset_stack_stats(&mut self) {
+        self.data_stack.reset_high_water();
+        self.return_stack.reset_high_water();
+        self.call_stack.reset_high_water();
+    }
+
+    /// `.stats ( -- )`
+    ///
+    /// Prints the depth, capacity and high-water mark of each stack.
+    pub fn dot_stats(&mut self) -> Result<(), Error> {
+        let stats = self.memory_stats();
+        let stacks = [
+            ("data", stats.data_stack),
+            ("return", stats.return_stack),
+            ("call", stats.call_stack),
+        ];
+        for (name, stack) in stacks {
+            writeln!(
+                &mut self.output,
+                "{name} stack: {}/{} (max {})",
+                stack.depth, stack.capacity, stack.high_water,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Returns how much of the VM's own dictionary is in use, followed by
+    /// each of the frozen dictionaries it was forked from, nearest first.
+    ///
+    /// A [`StaticDict`](crate::rom::StaticDict) parent isn't included, as it
+    /// doesn't use any of the VM's memory.
+    pub fn dict_stats(&self) -> impl Iterator<Item = DictStats> + '_ {
+        self.dict.chain().map(DictStats::new)
+    }
+
+    /// Limits the VM's dictionary to `quota` bytes, or lets it use all of its
+    /// capacity if `quota` is `None`.
+    ///
+    /// Once the quota is used up, anything that allocates in the dictionary
+    /// fails with [`BumpError::OutOfMemory`](crate::dictionary::BumpError),
+    /// just as if the dictionary were full. A quota smaller than what's
+    /// already used doesn't free anything, but stops any more allocations.
+    ///
+    /// The quota is kept when the VM is forked, and VMs forked from it get
+    /// the same quota for their own dictionaries.
+    pub fn set_dict_quota(&mut self, quota: Option<usize>) {
+        self.dict.alloc.set_quota(quota);
+    }
+
+    pub fn dict_quota(&self) -> Option<usize> {
+        self.dict.alloc.quota()
+    }
+}
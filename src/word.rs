@@ -1,6 +1,6 @@
 use core::{fmt::Debug, mem::MaybeUninit, ptr::addr_of_mut};
 
-use crate::ReplaceErr;
+use crate::{stack::Stack, Error, ReplaceErr};
 
 // Use a union so that things work on both 32- and 64-bit systems,
 // so the *data* is always 32 bits, but the pointer is whatever the
@@ -47,7 +47,7 @@ impl TryInto<usize> for Word {
 
 impl Word {
     #[inline]
-    pub fn data(data: i32) -> Self {
+    pub const fn data(data: i32) -> Self {
         let mut mu_word: MaybeUninit<Word> = MaybeUninit::zeroed();
         unsafe {
             addr_of_mut!((*mu_word.as_mut_ptr()).data).write(data);
@@ -57,7 +57,7 @@ impl Word {
 
     #[cfg(feature = "floats")]
     #[inline]
-    pub fn float(f: f32) -> Self {
+    pub const fn float(f: f32) -> Self {
         let mut mu_word: MaybeUninit<Word> = MaybeUninit::zeroed();
         unsafe {
             addr_of_mut!((*mu_word.as_mut_ptr()).float).write(f);
@@ -66,7 +66,7 @@ impl Word {
     }
 
     #[inline]
-    pub fn ptr<T>(ptr: *mut T) -> Self {
+    pub const fn ptr<T>(ptr: *mut T) -> Self {
         let mut mu_word: MaybeUninit<Word> = MaybeUninit::zeroed();
         unsafe {
             addr_of_mut!((*mu_word.as_mut_ptr()).ptr).write(ptr.cast());
@@ -75,7 +75,7 @@ impl Word {
     }
 
     #[inline]
-    pub fn ptr_data(ptr_data: isize) -> Self {
+    pub const fn ptr_data(ptr_data: isize) -> Self {
         let mut mu_word: MaybeUninit<Word> = MaybeUninit::zeroed();
         unsafe {
             addr_of_mut!((*mu_word.as_mut_ptr()).ptr_data).write(ptr_data);
@@ -83,3 +83,119 @@ impl Word {
         }
     }
 }
+
+/// Values that can be passed to a word with [`Forth::call`](crate::Forth::call),
+/// by pushing them onto the data stack.
+///
+/// Tuples are pushed in order, so the last element ends up on top.
+pub trait IntoWords {
+    fn push_words(self, stack: &mut Stack<Word>) -> Result<(), Error>;
+}
+
+/// Values that can be returned by a word called with
+/// [`Forth::call`](crate::Forth::call), by popping them off the data stack.
+///
+/// Tuples are popped in reverse, so the last element comes from the top.
+pub trait FromWords: Sized {
+    fn pop_words(stack: &mut Stack<Word>) -> Result<Self, Error>;
+}
+
+impl IntoWords for Word {
+    fn push_words(self, stack: &mut Stack<Word>) -> Result<(), Error> {
+        stack.push(self)?;
+        Ok(())
+    }
+}
+
+impl FromWords for Word {
+    fn pop_words(stack: &mut Stack<Word>) -> Result<Self, Error> {
+        Ok(stack.try_pop()?)
+    }
+}
+
+impl IntoWords for i32 {
+    fn push_words(self, stack: &mut Stack<Word>) -> Result<(), Error> {
+        Word::data(self).push_words(stack)
+    }
+}
+
+impl FromWords for i32 {
+    fn pop_words(stack: &mut Stack<Word>) -> Result<Self, Error> {
+        Ok(unsafe { Word::pop_words(stack)?.data })
+    }
+}
+
+impl IntoWords for u32 {
+    fn push_words(self, stack: &mut Stack<Word>) -> Result<(), Error> {
+        (self as i32).push_words(stack)
+    }
+}
+
+impl FromWords for u32 {
+    fn pop_words(stack: &mut Stack<Word>) -> Result<Self, Error> {
+        Ok(i32::pop_words(stack)? as u32)
+    }
+}
+
+/// Forth's true is -1 (all bits set), and anything other than 0 is taken as
+/// true.
+impl IntoWords for bool {
+    fn push_words(self, stack: &mut Stack<Word>) -> Result<(), Error> {
+        i32::from(self).wrapping_neg().push_words(stack)
+    }
+}
+
+impl FromWords for bool {
+    fn pop_words(stack: &mut Stack<Word>) -> Result<Self, Error> {
+        Ok(i32::pop_words(stack)? != 0)
+    }
+}
+
+#[cfg(feature = "floats")]
+impl IntoWords for f32 {
+    fn push_words(self, stack: &mut Stack<Word>) -> Result<(), Error> {
+        Word::float(self).push_words(stack)
+    }
+}
+
+#[cfg(feature = "floats")]
+impl FromWords for f32 {
+    fn pop_words(stack: &mut Stack<Word>) -> Result<Self, Error> {
+        Ok(unsafe { Word::pop_words(stack)?.float })
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident)*) => {
+        impl<$($name: IntoWords),*> IntoWords for ($($name,)*) {
+            #[allow(non_snake_case, unused_variables)]
+            fn push_words(self, stack: &mut Stack<Word>) -> Result<(), Error> {
+                let ($($name,)*) = self;
+                $($name.push_words(stack)?;)*
+                Ok(())
+            }
+        }
+
+        impl<$($name: FromWords),*> FromWords for ($($name,)*) {
+            #[allow(non_snake_case, unused_variables)]
+            fn pop_words(stack: &mut Stack<Word>) -> Result<Self, Error> {
+                impl_tuple!(@pop stack; $($name)*);
+                Ok(($($name,)*))
+            }
+        }
+    };
+    // Pop the last element first, as it's on top of the stack.
+    (@pop $stack:ident;) => {};
+    (@pop $stack:ident; $first:ident $($rest:ident)*) => {
+        impl_tuple!(@pop $stack; $($rest)*);
+        let $first = $first::pop_words($stack)?;
+    };
+}
+
+impl_tuple!();
+impl_tuple!(A);
+impl_tuple!(A B);
+impl_tuple!(A B C);
+impl_tuple!(A B C D);
+impl_tuple!(A B C D E);
+impl_tuple!(A B C D E F);